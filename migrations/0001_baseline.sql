@@ -1,3 +1,5 @@
+-- Schema the worker was first deployed with, a no-op on databases created from it
+
 CREATE TABLE IF NOT EXISTS crowd_levels (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     percentage TEXT NOT NULL,
@@ -22,4 +24,4 @@ CREATE TABLE IF NOT EXISTS time_averages (
 
 -- Create indexes for the time_averages table
 CREATE INDEX IF NOT EXISTS idx_time_averages_website_url ON time_averages(website_url);
-CREATE INDEX IF NOT EXISTS idx_time_averages_day_hour ON time_averages(day_of_week, hour); 
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_time_averages_day_hour ON time_averages(day_of_week, hour);
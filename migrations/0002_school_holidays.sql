@@ -0,0 +1,9 @@
+-- School holiday periods uploaded by an admin, dates are inclusive local dates (YYYY-MM-DD)
+-- Holidays are excluded from the weekday averages and get their own profile (day_of_week = 7)
+CREATE TABLE school_holidays (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    start_date TEXT NOT NULL,
+    end_date TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
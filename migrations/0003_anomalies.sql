@@ -0,0 +1,18 @@
+-- Standard deviation used for anomaly detection, filled in by the next midnight job
+ALTER TABLE time_averages ADD COLUMN stddev_percentage REAL NOT NULL DEFAULT 0;
+
+-- Readings that deviated strongly from the stored time average for their slot
+CREATE TABLE anomalies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    crowd_level_id INTEGER NOT NULL,
+    website_url TEXT NOT NULL,
+    website_name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    percentage REAL NOT NULL,
+    expected_percentage REAL NOT NULL,
+    stddev_percentage REAL NOT NULL,
+    z_score REAL NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_anomalies_website_url_created_at ON anomalies(website_url, created_at DESC);
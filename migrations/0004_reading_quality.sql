@@ -0,0 +1,2 @@
+-- Quality flag of each reading: ok, stuck (flat-lined) or closed
+ALTER TABLE crowd_levels ADD COLUMN quality TEXT NOT NULL DEFAULT 'ok';
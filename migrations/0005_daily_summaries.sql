@@ -0,0 +1,21 @@
+-- Per-gym summary of each local day, written by the midnight job
+-- Times are local "HH:MM", quiet windows are stretches below 30% lasting at least 30 minutes
+CREATE TABLE daily_summaries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    website_url TEXT NOT NULL,
+    website_name TEXT NOT NULL,
+    summary_date TEXT NOT NULL,
+    mean_percentage REAL,
+    max_percentage REAL,
+    peak_time TEXT,
+    first_quiet_start TEXT,
+    first_quiet_end TEXT,
+    last_quiet_start TEXT,
+    last_quiet_end TEXT,
+    sample_count INTEGER NOT NULL,
+    coverage_percentage REAL,
+    last_updated TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(website_url, summary_date)
+);
+
+CREATE INDEX idx_daily_summaries_summary_date ON daily_summaries(summary_date DESC);
@@ -0,0 +1,2 @@
+-- Descriptions are derived from the gym's crowd level bands at read time
+ALTER TABLE crowd_levels DROP COLUMN description;
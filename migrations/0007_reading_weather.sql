@@ -0,0 +1,3 @@
+-- Weather recorded alongside each reading, NULL without weather data
+ALTER TABLE crowd_levels ADD COLUMN temperature REAL; -- °C at the gym when the reading was taken
+ALTER TABLE crowd_levels ADD COLUMN precipitation REAL; -- mm in the preceding hour
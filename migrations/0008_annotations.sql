@@ -0,0 +1,15 @@
+-- Events marked on the timeline (route resets, competitions, maintenance)
+-- Times are UTC like created_at, end_at is NULL for point annotations and website_url NULL for all gyms
+-- Readings inside an abnormal annotation are left out of the time averages
+CREATE TABLE annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    website_url TEXT,
+    kind TEXT NOT NULL,
+    title TEXT NOT NULL,
+    start_at TEXT NOT NULL,
+    end_at TEXT,
+    abnormal INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_annotations_start_at ON annotations(start_at);
@@ -0,0 +1,17 @@
+-- Threshold alerts delivered to a webhook
+-- windows is a JSON list of {"days": [1, 2, 3, 4, 5], "start": "17:00", "end": "22:00"} in local time, empty = always
+-- matching and last_notified_at keep a subscriber from being notified on every scrape
+CREATE TABLE subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    website_url TEXT NOT NULL,
+    threshold REAL NOT NULL,
+    direction TEXT NOT NULL, -- below or above
+    windows TEXT NOT NULL DEFAULT '[]',
+    webhook_url TEXT NOT NULL,
+    cooldown_minutes INTEGER NOT NULL DEFAULT 60,
+    matching INTEGER NOT NULL DEFAULT 0,
+    last_notified_at TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_subscriptions_website_url ON subscriptions(website_url);
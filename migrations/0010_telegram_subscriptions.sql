@@ -0,0 +1,24 @@
+-- Telegram delivery, exactly one of webhook_url and telegram_chat_id is set
+-- webhook_url becomes nullable, which SQLite can only do by recreating the table
+CREATE TABLE subscriptions_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    website_url TEXT NOT NULL,
+    threshold REAL NOT NULL,
+    direction TEXT NOT NULL, -- below or above
+    windows TEXT NOT NULL DEFAULT '[]',
+    webhook_url TEXT,
+    telegram_chat_id INTEGER,
+    cooldown_minutes INTEGER NOT NULL DEFAULT 60,
+    language TEXT NOT NULL DEFAULT 'en', -- language of chat messages
+    matching INTEGER NOT NULL DEFAULT 0,
+    last_notified_at TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO subscriptions_new (id, website_url, threshold, direction, windows, webhook_url, cooldown_minutes, matching, last_notified_at, created_at)
+    SELECT id, website_url, threshold, direction, windows, webhook_url, cooldown_minutes, matching, last_notified_at, created_at FROM subscriptions;
+
+DROP TABLE subscriptions;
+ALTER TABLE subscriptions_new RENAME TO subscriptions;
+
+CREATE INDEX idx_subscriptions_website_url ON subscriptions(website_url);
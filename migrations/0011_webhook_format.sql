@@ -0,0 +1,2 @@
+-- Webhook payload format: json, discord or slack
+ALTER TABLE subscriptions ADD COLUMN format TEXT NOT NULL DEFAULT 'json';
@@ -0,0 +1,6 @@
+-- Web Push delivery, the browser's push subscription with its p256dh and auth keys
+ALTER TABLE subscriptions ADD COLUMN push_endpoint TEXT;
+ALTER TABLE subscriptions ADD COLUMN push_p256dh TEXT;
+ALTER TABLE subscriptions ADD COLUMN push_auth TEXT;
+
+CREATE INDEX idx_subscriptions_push_endpoint ON subscriptions(push_endpoint);
@@ -0,0 +1,196 @@
+-- Configured gyms, ids and slugs never change, url and name follow the configuration
+-- The scheduled job upserts every gym from `scraper::get_configured_websites`
+CREATE TABLE gyms (
+    id INTEGER PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    url TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL
+);
+
+INSERT INTO gyms (id, slug, url, name) VALUES
+    (1, 'muc-ost', 'https://www.boulderwelt-muenchen-ost.de/', 'Boulderwelt München Ost'),
+    (2, 'muc-west', 'https://www.boulderwelt-muenchen-west.de/', 'Boulderwelt München West'),
+    (3, 'muc-sued', 'https://www.boulderwelt-muenchen-sued.de/', 'Boulderwelt München Süd');
+
+-- Readings reference their gym by id instead of its URL and name, the table is recreated so gym_id
+-- can be NOT NULL, readings of gyms that are no longer configured are dropped
+CREATE TABLE crowd_levels_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    percentage TEXT NOT NULL,
+    gym_id INTEGER NOT NULL REFERENCES gyms(id),
+    quality TEXT NOT NULL DEFAULT 'ok', -- ok, stuck (flat-lined) or closed
+    temperature REAL, -- °C at the gym when the reading was taken, NULL without weather data
+    precipitation REAL, -- mm in the preceding hour
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO crowd_levels_new (id, percentage, gym_id, quality, temperature, precipitation, created_at)
+    SELECT crowd_levels.id, crowd_levels.percentage, gyms.id, crowd_levels.quality,
+           crowd_levels.temperature, crowd_levels.precipitation, crowd_levels.created_at
+    FROM crowd_levels JOIN gyms ON gyms.url = crowd_levels.website_url;
+
+-- Dropping the table also drops its URL indexes, including the ones of the early README setup
+DROP TABLE crowd_levels;
+ALTER TABLE crowd_levels_new RENAME TO crowd_levels;
+
+CREATE INDEX idx_crowd_levels_gym_id_created_at ON crowd_levels(gym_id, created_at DESC);
+
+-- Averages move to the gym id the same way, averages of gyms that are no longer configured are dropped
+CREATE TABLE time_averages_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    gym_id INTEGER NOT NULL REFERENCES gyms(id),
+    day_of_week INTEGER NOT NULL,
+    hour INTEGER NOT NULL,
+    average_percentage REAL NOT NULL,
+    stddev_percentage REAL NOT NULL DEFAULT 0,
+    sample_count INTEGER NOT NULL,
+    last_updated TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(gym_id, day_of_week, hour)
+);
+
+INSERT INTO time_averages_new (id, gym_id, day_of_week, hour, average_percentage, stddev_percentage, sample_count, last_updated)
+    SELECT time_averages.id, gyms.id, time_averages.day_of_week, time_averages.hour, time_averages.average_percentage,
+           time_averages.stddev_percentage, time_averages.sample_count, time_averages.last_updated
+    FROM time_averages JOIN gyms ON gyms.url = time_averages.website_url;
+
+DROP TABLE time_averages;
+ALTER TABLE time_averages_new RENAME TO time_averages;
+
+CREATE INDEX idx_time_averages_day_hour ON time_averages(day_of_week, hour);
+
+-- Anomalies, daily summaries and subscriptions follow the same pattern
+CREATE TABLE anomalies_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    crowd_level_id INTEGER NOT NULL,
+    gym_id INTEGER NOT NULL REFERENCES gyms(id),
+    kind TEXT NOT NULL,
+    percentage REAL NOT NULL,
+    expected_percentage REAL NOT NULL,
+    stddev_percentage REAL NOT NULL,
+    z_score REAL NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO anomalies_new (id, crowd_level_id, gym_id, kind, percentage, expected_percentage, stddev_percentage, z_score, created_at)
+    SELECT anomalies.id, anomalies.crowd_level_id, gyms.id, anomalies.kind, anomalies.percentage,
+           anomalies.expected_percentage, anomalies.stddev_percentage, anomalies.z_score, anomalies.created_at
+    FROM anomalies JOIN gyms ON gyms.url = anomalies.website_url;
+
+DROP TABLE anomalies;
+ALTER TABLE anomalies_new RENAME TO anomalies;
+
+CREATE INDEX idx_anomalies_gym_id_created_at ON anomalies(gym_id, created_at DESC);
+
+CREATE TABLE daily_summaries_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    gym_id INTEGER NOT NULL REFERENCES gyms(id),
+    summary_date TEXT NOT NULL,
+    mean_percentage REAL,
+    max_percentage REAL,
+    peak_time TEXT,
+    first_quiet_start TEXT,
+    first_quiet_end TEXT,
+    last_quiet_start TEXT,
+    last_quiet_end TEXT,
+    sample_count INTEGER NOT NULL,
+    coverage_percentage REAL,
+    last_updated TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(gym_id, summary_date)
+);
+
+INSERT INTO daily_summaries_new (id, gym_id, summary_date, mean_percentage, max_percentage, peak_time,
+                                 first_quiet_start, first_quiet_end, last_quiet_start, last_quiet_end,
+                                 sample_count, coverage_percentage, last_updated)
+    SELECT daily_summaries.id, gyms.id, daily_summaries.summary_date, daily_summaries.mean_percentage,
+           daily_summaries.max_percentage, daily_summaries.peak_time, daily_summaries.first_quiet_start,
+           daily_summaries.first_quiet_end, daily_summaries.last_quiet_start, daily_summaries.last_quiet_end,
+           daily_summaries.sample_count, daily_summaries.coverage_percentage, daily_summaries.last_updated
+    FROM daily_summaries JOIN gyms ON gyms.url = daily_summaries.website_url;
+
+DROP TABLE daily_summaries;
+ALTER TABLE daily_summaries_new RENAME TO daily_summaries;
+
+CREATE INDEX idx_daily_summaries_summary_date ON daily_summaries(summary_date DESC);
+
+-- gym_id stays NULL for annotations of all gyms, annotations of gyms that are no longer configured are dropped
+CREATE TABLE annotations_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    gym_id INTEGER REFERENCES gyms(id),
+    kind TEXT NOT NULL,
+    title TEXT NOT NULL,
+    start_at TEXT NOT NULL,
+    end_at TEXT,
+    abnormal INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO annotations_new (id, gym_id, kind, title, start_at, end_at, abnormal, created_at)
+    SELECT annotations.id, gyms.id, annotations.kind, annotations.title, annotations.start_at,
+           annotations.end_at, annotations.abnormal, annotations.created_at
+    FROM annotations LEFT JOIN gyms ON gyms.url = annotations.website_url
+    WHERE annotations.website_url IS NULL OR gyms.id IS NOT NULL;
+
+DROP TABLE annotations;
+ALTER TABLE annotations_new RENAME TO annotations;
+
+CREATE INDEX idx_annotations_start_at ON annotations(start_at);
+
+CREATE TABLE subscriptions_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    gym_id INTEGER NOT NULL REFERENCES gyms(id),
+    threshold REAL NOT NULL,
+    direction TEXT NOT NULL, -- below or above
+    windows TEXT NOT NULL DEFAULT '[]',
+    webhook_url TEXT,
+    telegram_chat_id INTEGER,
+    push_endpoint TEXT,
+    push_p256dh TEXT,
+    push_auth TEXT,
+    format TEXT NOT NULL DEFAULT 'json',
+    cooldown_minutes INTEGER NOT NULL DEFAULT 60,
+    language TEXT NOT NULL DEFAULT 'en',
+    matching INTEGER NOT NULL DEFAULT 0,
+    last_notified_at TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO subscriptions_new (id, gym_id, threshold, direction, windows, webhook_url, telegram_chat_id,
+                               push_endpoint, push_p256dh, push_auth, format, cooldown_minutes, language,
+                               matching, last_notified_at, created_at)
+    SELECT subscriptions.id, gyms.id, subscriptions.threshold, subscriptions.direction, subscriptions.windows,
+           subscriptions.webhook_url, subscriptions.telegram_chat_id, subscriptions.push_endpoint,
+           subscriptions.push_p256dh, subscriptions.push_auth, subscriptions.format, subscriptions.cooldown_minutes,
+           subscriptions.language, subscriptions.matching, subscriptions.last_notified_at, subscriptions.created_at
+    FROM subscriptions JOIN gyms ON gyms.url = subscriptions.website_url;
+
+DROP TABLE subscriptions;
+ALTER TABLE subscriptions_new RENAME TO subscriptions;
+
+CREATE INDEX idx_subscriptions_gym_id ON subscriptions(gym_id);
+CREATE INDEX idx_subscriptions_push_endpoint ON subscriptions(push_endpoint);
+
+-- Every table together with its gym's slug, URL and name, all reads go through these views
+CREATE VIEW gym_crowd_levels AS
+    SELECT crowd_levels.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM crowd_levels JOIN gyms ON gyms.id = crowd_levels.gym_id;
+
+CREATE VIEW gym_time_averages AS
+    SELECT time_averages.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM time_averages JOIN gyms ON gyms.id = time_averages.gym_id;
+
+CREATE VIEW gym_anomalies AS
+    SELECT anomalies.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM anomalies JOIN gyms ON gyms.id = anomalies.gym_id;
+
+CREATE VIEW gym_daily_summaries AS
+    SELECT daily_summaries.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM daily_summaries JOIN gyms ON gyms.id = daily_summaries.gym_id;
+
+-- Annotations of all gyms have NULL slug, URL and name
+CREATE VIEW gym_annotations AS
+    SELECT annotations.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM annotations LEFT JOIN gyms ON gyms.id = annotations.gym_id;
+
+CREATE VIEW gym_subscriptions AS
+    SELECT subscriptions.*, gyms.slug AS gym_slug, gyms.url AS website_url, gyms.name AS website_name
+    FROM subscriptions JOIN gyms ON gyms.id = subscriptions.gym_id;
@@ -0,0 +1,2 @@
+-- Bumped whenever a reading's quality flag changes, so caches can tell a re-flagged reading apart
+ALTER TABLE crowd_levels ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,427 @@
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Absolute z-score from which a reading is considered anomalous
+pub const ANOMALY_Z_THRESHOLD: f64 = 3.0;
+
+/// Minimum number of samples a time average needs before it is trusted
+pub const MIN_PROFILE_SAMPLES: i64 = 6;
+
+/// Lower bound for the standard deviation, so very stable hours don't flag every small wobble
+pub const MIN_STDDEV: f64 = 5.0;
+
+/// Hours after which the current deviation from the profile has mostly faded out of a forecast
+pub const FORECAST_DECAY_HOURS: f64 = 2.0;
+
+/// Level below which a stretch of readings counts as a quiet window in daily summaries
+pub const QUIET_THRESHOLD: f64 = 30.0;
+
+/// Minimum length of a quiet window in minutes
+pub const QUIET_WINDOW_MIN_MINUTES: i64 = 30;
+
+/// Interval of the scheduled scrape in minutes
+pub const SCRAPE_INTERVAL_MINUTES: i64 = 10;
+
+/// Default time without readings during opening hours from which a stretch counts as a gap
+pub const DEFAULT_GAP_THRESHOLD_MINUTES: i64 = 30;
+
+/// Default hourly average from which an hour counts as busy in trend reports
+pub const DEFAULT_BUSY_THRESHOLD: f64 = 70.0;
+
+/// Quality flag for readings that look genuine
+pub const QUALITY_OK: &str = "ok";
+
+/// Quality flag for readings that are part of a flat-lined sequence
+pub const QUALITY_STUCK: &str = "stuck";
+
+/// Quality flag for readings taken while the gym was closed, either outside its configured
+/// opening hours or during a run of zeros that shows it closed unexpectedly
+pub const QUALITY_CLOSED: &str = "closed";
+
+/// Number of identical consecutive readings (including the new one) that count as flat-lined,
+/// 6 readings at a 10 minute interval is one hour without any movement
+pub const STUCK_MIN_READINGS: usize = 6;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Anomaly {
+    pub kind: &'static str,
+    pub percentage: f64,
+    pub expected_percentage: f64,
+    pub stddev_percentage: f64,
+    pub z_score: f64,
+}
+
+/// Compares a reading against the stored profile for its slot and returns an
+/// anomaly if it deviates by more than `ANOMALY_Z_THRESHOLD` standard deviations
+pub fn detect_anomaly(percentage: f64, expected: f64, stddev: f64, sample_count: i64) -> Option<Anomaly> {
+    if sample_count < MIN_PROFILE_SAMPLES {
+        return None;
+    }
+
+    let stddev = stddev.max(MIN_STDDEV);
+    let z_score = (percentage - expected) / stddev;
+
+    if z_score.abs() < ANOMALY_Z_THRESHOLD {
+        return None;
+    }
+
+    Some(Anomaly {
+        kind: if z_score > 0.0 { "unusually_high" } else { "unusually_low" },
+        percentage,
+        expected_percentage: expected,
+        stddev_percentage: stddev,
+        z_score: (z_score * 100.0).round() / 100.0,
+    })
+}
+
+/// Determines the quality flag of a new reading
+///
+/// `previous` holds the percentages of the most recent readings, newest first.
+/// The indicator drops to 0 when the gym shuts down, but an empty gym reads 0 as well,
+/// so during opening hours zeros only count as closed once they flat-line, e.g. on a
+/// closure day that isn't in the configured opening hours. Other flat lines are stuck.
+pub fn classify_reading(percentage: f64, is_open: bool, previous: &[f64]) -> &'static str {
+    if !is_open {
+        return QUALITY_CLOSED;
+    }
+
+    if is_flatlined(percentage, previous) {
+        return if percentage == 0.0 { QUALITY_CLOSED } else { QUALITY_STUCK };
+    }
+
+    QUALITY_OK
+}
+
+/// Checks whether the new reading completes a run of `STUCK_MIN_READINGS` identical values
+pub fn is_flatlined(percentage: f64, previous: &[f64]) -> bool {
+    previous.len() + 1 >= STUCK_MIN_READINGS
+        && previous.iter()
+            .take(STUCK_MIN_READINGS - 1)
+            .all(|value| *value == percentage)
+}
+
+/// Forecasts the crowd level `hours_ahead` hours from now
+///
+/// Starts from the typical level of the target slot and carries over today's
+/// deviation from the profile, which fades out exponentially with the horizon.
+pub fn forecast_level(current: Option<f64>, typical_now: Option<f64>, typical_target: f64, hours_ahead: f64) -> f64 {
+    let deviation = match (current, typical_now) {
+        (Some(current), Some(typical_now)) => current - typical_now,
+        _ => 0.0,
+    };
+
+    let forecast = typical_target + deviation * (-hours_ahead.max(0.0) / FORECAST_DECAY_HOURS).exp();
+    (forecast.clamp(0.0, 100.0) * 10.0).round() / 10.0
+}
+
+/// Granularity of the trend reports
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendPeriod {
+    #[default]
+    Week,
+    Month,
+}
+
+impl TrendPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendPeriod::Week => "week",
+            TrendPeriod::Month => "month",
+        }
+    }
+
+    /// SQLite expression mapping `created_at` to the first day of its period (weeks start on Monday)
+    pub fn sql_period_start(&self) -> &'static str {
+        match self {
+            TrendPeriod::Week => "date(created_at, 'weekday 0', '-6 days')",
+            TrendPeriod::Month => "date(created_at, 'start of month')",
+        }
+    }
+
+    /// Number of periods included in a report
+    pub fn periods_shown(&self) -> u32 {
+        match self {
+            TrendPeriod::Week => 12,
+            TrendPeriod::Month => 12,
+        }
+    }
+
+    /// Returns the start of the period before the one starting at `start`
+    pub fn previous(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TrendPeriod::Week => start - Duration::weeks(1),
+            TrendPeriod::Month => start - Months::new(1),
+        }
+    }
+
+    /// Returns the start of the matching period one year earlier
+    ///
+    /// Weeks go back 52 weeks so that both periods start on a Monday.
+    pub fn year_before(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TrendPeriod::Week => start - Duration::weeks(52),
+            TrendPeriod::Month => start.with_year(start.year() - 1).unwrap_or(start),
+        }
+    }
+
+    /// Returns the start of the oldest period shown in a report ending at `today`
+    pub fn first_shown(&self, today: NaiveDate) -> NaiveDate {
+        let mut start = match self {
+            TrendPeriod::Week => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+            TrendPeriod::Month => today.with_day(1).unwrap_or(today),
+        };
+        for _ in 1..self.periods_shown() {
+            start = self.previous(start);
+        }
+        start
+    }
+}
+
+/// Statistics of a single gym for one local day
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DailySummary {
+    pub mean_percentage: Option<f64>,
+    pub max_percentage: Option<f64>,
+    pub peak_time: Option<String>,
+    pub first_quiet_start: Option<String>,
+    pub first_quiet_end: Option<String>,
+    pub last_quiet_start: Option<String>,
+    pub last_quiet_end: Option<String>,
+    pub sample_count: i64,
+    pub coverage_percentage: Option<f64>,
+}
+
+/// Summarizes the readings of one local day
+///
+/// `readings` are (local time, percentage) pairs in chronological order and
+/// `expected_readings` is how many scrapes the opening hours allow for that day.
+/// A quiet window is a run of readings below `QUIET_THRESHOLD` lasting at least
+/// `QUIET_WINDOW_MIN_MINUTES`; its end is the last reading still below the threshold.
+pub fn summarize_day(readings: &[(NaiveDateTime, f64)], expected_readings: i64) -> DailySummary {
+    let format_time = |time: NaiveDateTime| time.format("%H:%M").to_string();
+
+    let coverage_percentage = coverage_percentage(readings.len() as i64, expected_readings);
+
+    if readings.is_empty() {
+        return DailySummary {
+            coverage_percentage,
+            ..Default::default()
+        };
+    }
+
+    let mean = readings.iter().map(|(_, value)| value).sum::<f64>() / readings.len() as f64;
+    let (peak_time, max) = readings.iter()
+        .fold(readings[0], |peak, reading| if reading.1 > peak.1 { *reading } else { peak });
+
+    // Collect all sufficiently long runs below the quiet threshold
+    let mut windows: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+    let mut run: Option<(NaiveDateTime, NaiveDateTime)> = None;
+    for (time, value) in readings {
+        if *value < QUIET_THRESHOLD {
+            run = Some(match run {
+                Some((start, _)) => (start, *time),
+                None => (*time, *time),
+            });
+        } else if let Some(finished) = run.take() {
+            windows.push(finished);
+        }
+    }
+    windows.extend(run);
+    windows.retain(|(start, end)| (*end - *start).num_minutes() >= QUIET_WINDOW_MIN_MINUTES);
+
+    DailySummary {
+        mean_percentage: Some((mean * 100.0).round() / 100.0),
+        max_percentage: Some(max),
+        peak_time: Some(format_time(peak_time)),
+        first_quiet_start: windows.first().map(|(start, _)| format_time(*start)),
+        first_quiet_end: windows.first().map(|(_, end)| format_time(*end)),
+        last_quiet_start: windows.last().map(|(start, _)| format_time(*start)),
+        last_quiet_end: windows.last().map(|(_, end)| format_time(*end)),
+        sample_count: readings.len() as i64,
+        coverage_percentage,
+    }
+}
+
+/// Pearson correlation coefficient of the given pairs, None if either side has no variance
+pub fn pearson_correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+/// Share of the expected readings that were actually recorded, capped at 100%
+pub fn coverage_percentage(readings: i64, expected_readings: i64) -> Option<f64> {
+    (expected_readings > 0).then(|| {
+        let coverage = readings as f64 / expected_readings as f64 * 100.0;
+        (coverage.min(100.0) * 10.0).round() / 10.0
+    })
+}
+
+/// Finds stretches within `[window_start, window_end)` without readings that last
+/// longer than `threshold_minutes`
+///
+/// `readings` must be sorted. The window edges count as boundaries, so a late
+/// first reading or a missing tail is reported as a gap too.
+pub fn find_gaps(
+    readings: &[NaiveDateTime],
+    window_start: NaiveDateTime,
+    window_end: NaiveDateTime,
+    threshold_minutes: i64,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut gaps = Vec::new();
+    let mut previous = window_start;
+
+    let inside = readings.iter().filter(|time| **time >= window_start && **time < window_end);
+    for time in inside.copied().chain(std::iter::once(window_end)) {
+        if (time - previous).num_minutes() > threshold_minutes {
+            gaps.push((previous, time));
+        }
+        previous = time;
+    }
+
+    gaps
+}
+
+/// A predicted stretch of opening hours in which the typical crowd level stays low
+#[derive(Debug, Clone)]
+pub struct QuietWindow {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// Typical level over the window, weighted by the length of each slot
+    pub mean_percentage: f64,
+    pub max_percentage: f64,
+}
+
+/// Merges consecutive (start, end, typical level) slots at or below `max_level` into
+/// windows and keeps those lasting at least `min_minutes`
+///
+/// `slots` must be sorted. A slot that doesn't start where the previous one ended,
+/// e.g. the next day, always starts a new window.
+pub fn predicted_quiet_windows(
+    slots: &[(NaiveDateTime, NaiveDateTime, f64)],
+    max_level: f64,
+    min_minutes: i64,
+) -> Vec<QuietWindow> {
+    let mut windows = Vec::new();
+    let mut current: Vec<(NaiveDateTime, NaiveDateTime, f64)> = Vec::new();
+
+    let finish = |run: &mut Vec<(NaiveDateTime, NaiveDateTime, f64)>, windows: &mut Vec<QuietWindow>| {
+        if let (Some(first), Some(last)) = (run.first(), run.last()) {
+            let minutes = (last.1 - first.0).num_minutes();
+            if minutes >= min_minutes {
+                let weighted = run.iter()
+                    .map(|(start, end, level)| level * (*end - *start).num_minutes() as f64)
+                    .sum::<f64>();
+                windows.push(QuietWindow {
+                    start: first.0,
+                    end: last.1,
+                    mean_percentage: ((weighted / minutes as f64) * 10.0).round() / 10.0,
+                    max_percentage: run.iter().map(|(_, _, level)| *level).fold(0.0, f64::max),
+                });
+            }
+        }
+        run.clear();
+    };
+
+    for slot in slots {
+        let continues = current.last().is_some_and(|last| last.1 == slot.0);
+        if !continues || slot.2 > max_level {
+            finish(&mut current, &mut windows);
+        }
+        if slot.2 <= max_level {
+            current.push(*slot);
+        }
+    }
+    finish(&mut current, &mut windows);
+
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 3, 28).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn merges_consecutive_quiet_slots() {
+        let slots = [
+            (at(7, 30), at(8, 0), 10.0),
+            (at(8, 0), at(9, 0), 20.0),
+            (at(9, 0), at(10, 0), 60.0),
+            (at(10, 0), at(11, 0), 25.0),
+        ];
+
+        let windows = predicted_quiet_windows(&slots, 30.0, 60);
+        assert_eq!(windows.len(), 2);
+        assert_eq!((windows[0].start, windows[0].end), (at(7, 30), at(9, 0)));
+        // Weighted by duration: (10 * 30 + 20 * 60) / 90
+        assert_eq!(windows[0].mean_percentage, 16.7);
+        assert_eq!(windows[0].max_percentage, 20.0);
+        assert_eq!((windows[1].start, windows[1].end), (at(10, 0), at(11, 0)));
+    }
+
+    #[test]
+    fn drops_short_windows_and_splits_at_gaps() {
+        let next_day = |hour| at(hour, 0) + Duration::days(1);
+        let slots = [
+            (at(21, 0), at(22, 0), 10.0),
+            (at(22, 0), at(22, 30), 10.0),
+            (next_day(7), next_day(8), 10.0),
+            (next_day(12), next_day(13), 40.0),
+        ];
+
+        let windows = predicted_quiet_windows(&slots, 30.0, 90);
+        assert_eq!(windows.len(), 1);
+        assert_eq!((windows[0].start, windows[0].end), (at(21, 0), at(22, 30)));
+        assert!(predicted_quiet_windows(&slots, 5.0, 30).is_empty());
+    }
+
+    #[test]
+    fn readings_outside_opening_hours_are_closed() {
+        assert_eq!(classify_reading(0.0, false, &[]), QUALITY_CLOSED);
+        assert_eq!(classify_reading(42.0, false, &[40.0, 38.0]), QUALITY_CLOSED);
+    }
+
+    #[test]
+    fn single_zero_while_open_is_ok() {
+        assert_eq!(classify_reading(0.0, true, &[]), QUALITY_OK);
+        assert_eq!(classify_reading(0.0, true, &[0.0, 0.0, 5.0, 8.0, 10.0]), QUALITY_OK);
+    }
+
+    #[test]
+    fn run_of_zeros_while_open_is_closed() {
+        assert_eq!(classify_reading(0.0, true, &[0.0; STUCK_MIN_READINGS - 1]), QUALITY_CLOSED);
+        assert_eq!(classify_reading(0.0, true, &[0.0; STUCK_MIN_READINGS + 3]), QUALITY_CLOSED);
+        assert_eq!(classify_reading(0.0, true, &[0.0; STUCK_MIN_READINGS - 2]), QUALITY_OK);
+    }
+
+    #[test]
+    fn run_of_other_values_while_open_is_stuck() {
+        assert_eq!(classify_reading(37.0, true, &[37.0; STUCK_MIN_READINGS - 1]), QUALITY_STUCK);
+        assert_eq!(classify_reading(37.0, true, &[37.0; STUCK_MIN_READINGS - 2]), QUALITY_OK);
+        assert_eq!(classify_reading(37.0, true, &[37.0, 37.0, 37.0, 37.0, 36.0]), QUALITY_OK);
+    }
+}
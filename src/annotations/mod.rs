@@ -0,0 +1,62 @@
+use chrono::DateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Kinds of events that can be marked on the timeline
+pub const ANNOTATION_KINDS: [&str; 4] = ["route_reset", "competition", "maintenance", "other"];
+
+/// An annotation as sent to the admin API
+///
+/// `start` and `end` are Unix timestamps. Without an `end` the annotation marks a
+/// single point in time, otherwise a range. Readings inside the range of an
+/// `abnormal` annotation are left out of the time averages.
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct Annotation {
+    /// Gym the annotation applies to, by slug or website URL, None for all gyms
+    pub website_url: Option<String>,
+    pub kind: String,
+    pub title: String,
+    pub start: i64,
+    pub end: Option<i64>,
+    #[serde(default)]
+    pub abnormal: bool,
+}
+
+impl Annotation {
+    /// Checks the annotation and returns a message describing the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if !ANNOTATION_KINDS.contains(&self.kind.as_str()) {
+            return Err(format!("Invalid kind '{}', expected one of {}", self.kind, ANNOTATION_KINDS.join(", ")));
+        }
+
+        if self.title.trim().is_empty() {
+            return Err("Title must not be empty".to_string());
+        }
+
+        if self.start_at().is_none() || self.end.is_some() && self.end_at().is_none() {
+            return Err("Invalid start or end timestamp".to_string());
+        }
+
+        if self.end.is_some_and(|end| end < self.start) {
+            return Err("End must not be before start".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Start as stored in D1 (UTC, same format as `created_at`)
+    pub fn start_at(&self) -> Option<String> {
+        to_db_timestamp(self.start)
+    }
+
+    /// End as stored in D1, None for point annotations
+    pub fn end_at(&self) -> Option<String> {
+        self.end.and_then(to_db_timestamp)
+    }
+}
+
+fn to_db_timestamp(timestamp: i64) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|dt| utils::format_db_timestamp(dt.naive_utc()))
+}
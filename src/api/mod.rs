@@ -0,0 +1,167 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::Serialize;
+use worker::*;
+
+use crate::db::{CrowdLevelRow, TimeAverageRow};
+use crate::i18n::{self, Language};
+use crate::scraper::WebsiteConfig;
+use crate::utils;
+
+/// Path prefix of the versioned API
+pub const PREFIX: &str = "/api/v1";
+
+/// Page size used when a request doesn't ask for one
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Largest page a client can request
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// A configured gym
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct Gym {
+    pub id: i64,
+    /// Short identifier, accepted wherever a gym is expected
+    pub slug: String,
+    pub url: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Upper bounds of the Very low / Low / Moderate / High description bands
+    pub crowd_level_bands: [f64; 4],
+    /// Whether the gym is open right now
+    pub open: bool,
+}
+
+impl Gym {
+    pub fn from_config(website: &WebsiteConfig, local_now: NaiveDateTime) -> Self {
+        Gym {
+            id: website.id,
+            slug: website.slug.clone(),
+            url: website.url.clone(),
+            name: website.name.clone(),
+            latitude: website.latitude,
+            longitude: website.longitude,
+            crowd_level_bands: website.crowd_level_bands,
+            open: website.opening_hours.is_open_at(local_now),
+        }
+    }
+}
+
+/// A stored crowd level reading
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct Reading {
+    pub id: i64,
+    pub gym_slug: String,
+    pub gym_url: String,
+    pub gym_name: String,
+    pub percentage: f64,
+    /// Localised description of the level, derived from the gym's bands
+    pub description: String,
+    /// ok, stuck (flat-lined) or closed (outside opening hours or a long run of zeros)
+    pub quality: String,
+    /// °C at the gym, null without weather data
+    pub temperature: Option<f64>,
+    /// mm in the preceding hour
+    pub precipitation: Option<f64>,
+    /// ISO 8601, UTC
+    pub recorded_at: String,
+}
+
+impl Reading {
+    pub fn from_row(row: &CrowdLevelRow, websites: &[WebsiteConfig], language: Language) -> Self {
+        let percentage = row.percentage();
+        let bands = websites.iter()
+            .find(|site| site.url == row.website_url)
+            .map(|site| site.crowd_level_bands)
+            .unwrap_or(i18n::DEFAULT_CROWD_LEVEL_BANDS);
+
+        Reading {
+            id: row.id,
+            gym_slug: row.gym_slug.clone(),
+            gym_url: row.website_url.clone(),
+            gym_name: row.website_name.clone(),
+            percentage,
+            description: i18n::describe_level(percentage, &bands, language).to_string(),
+            quality: row.quality.clone(),
+            temperature: row.temperature,
+            precipitation: row.precipitation,
+            recorded_at: iso_timestamp(&row.created_at),
+        }
+    }
+}
+
+/// The typical level of a gym in one hour of the week
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct TimeAverage {
+    pub gym_slug: String,
+    pub gym_url: String,
+    pub gym_name: String,
+    /// Lowercase English weekday in UTC, or "holiday" for the school holiday profile (local holiday dates)
+    pub day: String,
+    /// UTC hour, the profile is bucketed by UTC weekday and hour (see `holidays::profile_slot`)
+    pub hour: i64,
+    pub average_percentage: f64,
+    pub stddev_percentage: f64,
+    pub sample_count: i64,
+    /// ISO 8601, UTC
+    pub updated_at: Option<String>,
+}
+
+impl TimeAverage {
+    /// Returns None for rows with an unknown day
+    pub fn from_row(row: &TimeAverageRow) -> Option<Self> {
+        let weekdays = ["sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "holiday"];
+        let day = weekdays.get(row.day_of_week as usize)?;
+
+        Some(TimeAverage {
+            gym_slug: row.gym_slug.clone(),
+            gym_url: row.website_url.clone(),
+            gym_name: row.website_name.clone(),
+            day: day.to_string(),
+            hour: row.hour,
+            average_percentage: row.average_percentage,
+            stddev_percentage: row.stddev_percentage,
+            sample_count: row.sample_count,
+            updated_at: row.last_updated.as_deref().map(iso_timestamp),
+        })
+    }
+}
+
+/// A list response, `next_cursor` is passed as `cursor` to fetch the following page
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+#[schemars(rename = "{T}Page")]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// A list that is always returned in full
+    pub fn complete(data: Vec<T>) -> Self {
+        Page { data, next_cursor: None }
+    }
+}
+
+/// Converts a timestamp as stored in D1 to ISO 8601, values in another format are passed through
+pub fn iso_timestamp(value: &str) -> String {
+    match utils::parse_db_timestamp(value) {
+        Some(time) => time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Parses a `since`/`until` value, either a unix timestamp or an ISO 8601 date-time, into a unix timestamp
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Some(timestamp);
+    }
+
+    chrono::DateTime::parse_from_rfc3339(value).ok()
+        .map(|time| time.timestamp())
+}
+
+/// Points clients of an unversioned route to its `/api/v1` successor
+pub fn link_successor(response: &mut Response, path: &str) -> Result<()> {
+    response.headers_mut().set("Link", &format!("<{}{}>; rel=\"successor-version\"", PREFIX, path))
+}
@@ -0,0 +1,146 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use chrono::{NaiveDateTime, Timelike};
+use sha2::{Digest, Sha256};
+use worker::*;
+
+use crate::analysis;
+use crate::i18n::Language;
+use crate::scraper::WebsiteConfig;
+use crate::utils;
+
+/// Time a scheduled run needs to store its results after the cron fired
+const RUN_GRACE_SECONDS: i64 = 60;
+
+/// Seconds until the readings of the next scrape are expected, the scrape cron runs on every tenth minute (UTC)
+fn seconds_until_next_scrape(now: NaiveDateTime) -> i64 {
+    let interval = analysis::SCRAPE_INTERVAL_MINUTES * 60;
+    let elapsed = now.num_seconds_from_midnight() as i64 % interval;
+
+    // Right after the cron fired the new readings may still be on their way
+    if elapsed < RUN_GRACE_SECONDS {
+        RUN_GRACE_SECONDS - elapsed
+    } else {
+        interval - elapsed + RUN_GRACE_SECONDS
+    }
+}
+
+/// Seconds until new readings of any of the gyms are expected
+///
+/// The scheduled job skips closed gyms, so a closed gym gets its next reading from the
+/// first scrape after it opens again.
+pub fn seconds_until_new_readings(now: NaiveDateTime, websites: &[WebsiteConfig]) -> i64 {
+    let local_now = utils::utc_to_local(now);
+
+    websites.iter()
+        .map(|website| {
+            if website.opening_hours.is_open_at(local_now) {
+                return seconds_until_next_scrape(now);
+            }
+
+            match website.opening_hours.next_opening(local_now) {
+                Some(opening) => {
+                    let opening = utils::local_to_utc(opening);
+                    (opening - now).num_seconds() + seconds_until_next_scrape(opening)
+                },
+                None => seconds_until_next_scrape(now),
+            }
+        })
+        .min()
+        .unwrap_or_else(|| seconds_until_next_scrape(now))
+}
+
+/// Seconds until the time averages are expected to be recalculated by the midnight job (UTC)
+pub fn seconds_until_next_averages_update(now: NaiveDateTime) -> i64 {
+    let elapsed = now.num_seconds_from_midnight() as i64;
+
+    if elapsed < RUN_GRACE_SECONDS {
+        RUN_GRACE_SECONDS - elapsed
+    } else {
+        24 * 60 * 60 - elapsed + RUN_GRACE_SECONDS
+    }
+}
+
+/// Validator and freshness of a response, derived from the state of the rows behind it
+pub struct Freshness {
+    etag: String,
+    max_age: i64,
+}
+
+impl Freshness {
+    /// `state` identifies the version of the data, e.g. the latest row id, it is combined with the
+    /// requested URL and language so every representation gets its own tag
+    pub fn new(req: &Request, state: &str, max_age: i64) -> Result<Self> {
+        let url = req.url()?;
+        let mut hasher = Sha256::new();
+        hasher.update(url.path().as_bytes());
+        hasher.update(b"?");
+        hasher.update(url.query().unwrap_or_default().as_bytes());
+        hasher.update(b"\n");
+        hasher.update(Language::from_request(req).code().as_bytes());
+        hasher.update(b"\n");
+        hasher.update(state.as_bytes());
+
+        Ok(Freshness {
+            etag: format!("\"{}\"", BASE64_URL.encode(&hasher.finalize()[..18])),
+            max_age,
+        })
+    }
+
+    /// Answers with 304 Not Modified if the client already holds this version
+    pub fn not_modified(&self, req: &Request) -> Result<Option<Response>> {
+        let Some(if_none_match) = req.headers().get("If-None-Match")? else {
+            return Ok(None);
+        };
+
+        // Weak comparison as in RFC 9110, a list of tags or "*" can be sent
+        let matches = if_none_match.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        if !matches {
+            return Ok(None);
+        }
+
+        let mut response = Response::empty()?.with_status(304);
+        self.apply(&mut response)?;
+        Ok(Some(response))
+    }
+
+    /// Sets the `ETag` and `Cache-Control` headers
+    pub fn apply(&self, response: &mut Response) -> Result<()> {
+        response.headers_mut().set("ETag", &self.etag)?;
+        response.headers_mut().set("Cache-Control", &format!("public, max-age={}", self.max_age))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::scraper;
+
+    /// UTC time of a local time in winter (CET)
+    fn winter(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap() - chrono::Duration::hours(1)
+    }
+
+    #[test]
+    fn open_gyms_expire_after_the_next_scrape() {
+        let websites = scraper::get_configured_websites();
+
+        // Tuesday 17:43 local, the next scrape runs at 17:50 and is stored a minute later
+        assert_eq!(seconds_until_new_readings(winter(14, 17, 43), &websites), 8 * 60);
+        assert_eq!(seconds_until_new_readings(winter(14, 17, 40), &websites[..1]), 60);
+    }
+
+    #[test]
+    fn closed_gyms_expire_after_the_first_scrape_once_open() {
+        let websites = scraper::get_configured_websites();
+
+        // Tuesday 23:30 local, the gyms open at 07:00 on Wednesday
+        assert_eq!(seconds_until_new_readings(winter(14, 23, 30), &websites[..1]), 7 * 3600 + 30 * 60 + 60);
+        // Friday 23:30 local, the gyms open at 08:00 on Saturday
+        assert_eq!(seconds_until_new_readings(winter(17, 23, 30), &websites), 8 * 3600 + 30 * 60 + 60);
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::NaiveDateTime;
+
+/// Identifies the feed in the PRODID property
+const PRODUCT_ID: &str = "-//boulderwelt-scraper//Quiet sessions//EN";
+
+/// How often calendar apps should fetch the feed again
+const REFRESH_INTERVAL: &str = "PT6H";
+
+/// Maximum length of a content line in octets, longer lines are folded (RFC 5545 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+
+/// A single event of the feed, times in UTC
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    /// Stable id, so calendar apps update an event instead of adding it again
+    pub uid: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub summary: String,
+    pub description: String,
+    pub location: String,
+    pub url: Option<String>,
+}
+
+/// Renders an RFC 5545 calendar with the given events
+pub fn render(name: &str, events: &[CalendarEvent], now: NaiveDateTime) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+        format!("REFRESH-INTERVAL;VALUE=DURATION:{}", REFRESH_INTERVAL),
+        format!("X-PUBLISHED-TTL:{}", REFRESH_INTERVAL),
+    ];
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", format_utc(now)));
+        lines.push(format!("DTSTART:{}", format_utc(event.start)));
+        lines.push(format!("DTEND:{}", format_utc(event.end)));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        lines.push(format!("LOCATION:{}", escape_text(&event.location)));
+        if let Some(url) = &event.url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter()
+        .map(|line| fold_line(line) + "\r\n")
+        .collect()
+}
+
+fn format_utc(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes a TEXT value (RFC 5545 3.3.11)
+fn escape_text(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits a content line into chunks of at most 75 octets without breaking characters,
+/// continuation lines start with a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut line_octets = 0;
+
+    for c in line.chars() {
+        if line_octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            line_octets = 1;
+        }
+        folded.push(c);
+        line_octets += c.len_utf8();
+    }
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(escape_text("Quiet; bring shoes, chalk\nand tape"), "Quiet\\; bring shoes\\, chalk\\nand tape");
+        assert_eq!(escape_text("Line one\nLine two"), "Line one\\nLine two");
+        assert_eq!(escape_text("a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn keeps_short_lines() {
+        let line = "SUMMARY:".to_string() + &"a".repeat(MAX_LINE_OCTETS - 8);
+        assert_eq!(fold_line(&line), line);
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let line = "DESCRIPTION:".to_string() + &"a".repeat(100);
+        let folded = fold_line(&line);
+
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 75);
+        assert_eq!(parts[1], " ".to_string() + &"a".repeat(112 - 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn folds_without_splitting_multi_byte_characters() {
+        // 73 octets followed by "ü" (2 octets) fits exactly, the next "ü" starts a continuation line
+        let line = "LOCATION:".to_string() + &"a".repeat(64) + "üü" + &"ö".repeat(40);
+        let folded = fold_line(&line);
+
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts[0], "LOCATION:".to_string() + &"a".repeat(64) + "ü");
+        assert_eq!(parts[0].len(), 75);
+        assert!(parts.iter().all(|part| part.len() <= MAX_LINE_OCTETS));
+        assert!(parts[1..].iter().all(|part| part.starts_with(' ')));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
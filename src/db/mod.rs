@@ -1,9 +1,92 @@
 use worker::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use chrono::Duration;
+use crate::analysis::{self, Anomaly, TrendPeriod};
+use crate::annotations::Annotation;
+use crate::feed::{self, EventKind, NotableEvent};
+use crate::holidays::{self, SchoolHoliday};
+use crate::i18n::{self, Language};
 use crate::scraper;
+use crate::subscriptions::{Subscription, SubscriptionRecord};
+use crate::utils;
+use crate::weather::{self, WeatherObservation};
 
-/// Stores a crowd level record in the database
-pub async fn store_crowd_level(env: &Env, percentage: &str, description: &str, website_url: &str, website_name: &str) -> Result<()> {
+/// A row of the `gym_crowd_levels` view, a `crowd_levels` row together with its gym
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CrowdLevelRow {
+    pub id: i64,
+    pub percentage: String,
+    pub gym_id: i64,
+    pub gym_slug: String,
+    pub website_url: String,
+    pub website_name: String,
+    pub quality: String,
+    pub temperature: Option<f64>,
+    pub precipitation: Option<f64>,
+    /// UTC, see `utils::DB_TIMESTAMP_FORMAT`
+    pub created_at: String,
+}
+
+impl CrowdLevelRow {
+    /// The level as a number, the column is text for historical reasons
+    pub fn percentage(&self) -> f64 {
+        self.percentage.parse::<f64>().unwrap_or(0.0)
+    }
+}
+
+/// A row of the `gym_time_averages` view, a `time_averages` row together with its gym
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TimeAverageRow {
+    pub gym_id: i64,
+    pub gym_slug: String,
+    pub website_url: String,
+    pub website_name: String,
+    /// UTC weekday, 0 = Sunday to 6 = Saturday, 7 = holiday profile
+    pub day_of_week: i64,
+    /// UTC hour
+    pub hour: i64,
+    pub average_percentage: f64,
+    pub stddev_percentage: f64,
+    pub sample_count: i64,
+    pub last_updated: Option<String>,
+}
+
+/// Writes the configured gyms to the `gyms` table, which the readings and averages reference
+pub async fn sync_gyms(env: &Env) -> Result<()> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        INSERT INTO gyms (id, slug, url, name) VALUES (?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET slug = excluded.slug, url = excluded.url, name = excluded.name
+    ";
+
+    let mut statements = Vec::new();
+    for website in scraper::get_configured_websites() {
+        statements.push(d1.prepare(stmt).bind(&[
+            (website.id as i32).into(),
+            website.slug.as_str().into(),
+            website.url.as_str().into(),
+            website.name.as_str().into(),
+        ])?);
+    }
+
+    d1.batch(statements).await?;
+
+    Ok(())
+}
+
+/// Stores a crowd level record in the database and returns the id of the new row
+///
+/// The weather at the time of the reading is stored alongside it when available.
+pub async fn store_crowd_level(env: &Env, percentage: &str, gym_id: i64, quality: &str, weather: Option<&WeatherObservation>) -> Result<i64> {
     // Get the D1 database
     let d1 = match env.d1("DB") {
         Ok(db) => db,
@@ -14,21 +97,157 @@ pub async fn store_crowd_level(env: &Env, percentage: &str, description: &str, w
     };
 
     // Insert a new record
-    let stmt = "INSERT INTO crowd_levels (percentage, description, website_url, website_name) VALUES (?, ?, ?, ?)";
+    let stmt = "INSERT INTO crowd_levels (percentage, gym_id, quality, temperature, precipitation) VALUES (?, ?, ?, ?, ?)";
     let prepared_stmt = d1.prepare(stmt);
 
-    let _result = prepared_stmt
-        .bind(&[percentage.into(), description.into(), website_url.into(), website_name.into()])?
+    let (temperature, precipitation) = match weather {
+        Some(observation) => (observation.temperature.into(), observation.precipitation.into()),
+        None => (wasm_bindgen::JsValue::NULL, wasm_bindgen::JsValue::NULL),
+    };
+
+    let result = prepared_stmt
+        .bind(&[percentage.into(), (gym_id as i32).into(), quality.into(), temperature, precipitation])?
         .run()
         .await?;
 
+    let id = result.meta()?
+        .and_then(|meta| meta.last_row_id)
+        .unwrap_or_default();
+
     console_log!("Inserted record successfully");
 
+    Ok(id)
+}
+
+/// Adds the localised crowd level description to a crowd_levels record
+///
+/// Descriptions are not stored, they depend on the gym's configured bands and the reader's language.
+pub fn describe_record(record: &mut serde_json::Value, websites: &[scraper::WebsiteConfig], language: Language) {
+    let percentage = record["percentage"].as_str()
+        .and_then(|percentage| percentage.parse::<f64>().ok())
+        .unwrap_or(0.0);
+
+    let bands = websites.iter()
+        .find(|site| record["website_url"].as_str() == Some(site.url.as_str()))
+        .map(|site| site.crowd_level_bands)
+        .unwrap_or(i18n::DEFAULT_CROWD_LEVEL_BANDS);
+
+    record["description"] = json!(i18n::describe_level(percentage, &bands, language));
+}
+
+/// Retrieves the most recent readings for a website within the given number of minutes, newest first
+pub async fn get_recent_readings(env: &Env, website_url: &str, minutes: i64) -> Result<Vec<serde_json::Value>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        SELECT id, percentage, quality, created_at FROM gym_crowd_levels
+        WHERE website_url = ?
+        AND created_at >= datetime('now', ?)
+        ORDER BY created_at DESC
+    ";
+
+    let result = d1.prepare(stmt)
+        .bind(&[website_url.into(), format!("-{} minutes", minutes).into()])?
+        .all()
+        .await?;
+
+    result.results::<serde_json::Value>()
+}
+
+/// Sets the quality flag of the given crowd level rows and bumps their version
+pub async fn mark_readings_quality(env: &Env, ids: &[i64], quality: &str) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let stmt = format!("UPDATE crowd_levels SET quality = ?, version = version + 1 WHERE id IN ({})", placeholders);
+
+    let mut params = vec![quality.into()];
+    params.extend(ids.iter().map(|id| (*id as i32).into()));
+
+    d1.prepare(&stmt)
+        .bind(&params)?
+        .run()
+        .await?;
+
+    console_log!("Marked {} readings as {}", ids.len(), quality);
+
     Ok(())
 }
 
-/// Retrieves historical crowd level data with pagination
-pub async fn get_crowd_level_history(env: &Env, since_timestamp: Option<i64>, until_timestamp: Option<i64>, website_url: Option<&str>) -> Result<serde_json::Value> {
+/// Retrieves crowd level rows, newest first
+///
+/// Readings flagged as stuck or closed are left out unless `include_flagged` is set.
+/// `before_id` and `limit` page through the result, passing the id of the last row of the previous page.
+pub async fn get_readings(
+    env: &Env,
+    website_url: Option<&str>,
+    since_timestamp: Option<i64>,
+    until_timestamp: Option<i64>,
+    include_flagged: bool,
+    before_id: Option<i64>,
+    limit: Option<usize>,
+) -> Result<Vec<CrowdLevelRow>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let (where_clause, mut params) = reading_conditions(website_url, since_timestamp, until_timestamp, include_flagged, before_id);
+
+    let limit_clause = match limit {
+        Some(limit) => {
+            params.push((limit as i32).into());
+            "LIMIT ?"
+        },
+        None => "",
+    };
+
+    // Ids grow with created_at, so they keep the order stable between pages
+    let stmt = format!("SELECT * FROM gym_crowd_levels {} ORDER BY created_at DESC, id DESC {}", where_clause, limit_clause);
+
+    let result = d1.prepare(&stmt)
+        .bind(&params)?
+        .all()
+        .await?;
+
+    result.results::<CrowdLevelRow>()
+}
+
+/// Identifies the version of the rows `get_readings` returns without reading them
+///
+/// New readings raise the latest id and readings dropping out of the filter lower the count.
+/// Re-flagged readings bump their version, which only ever grows, so the sum of the versions
+/// changes with every flag set later, e.g. a reading moving from stuck to closed.
+pub async fn get_readings_state(
+    env: &Env,
+    website_url: Option<&str>,
+    since_timestamp: Option<i64>,
+    until_timestamp: Option<i64>,
+    include_flagged: bool,
+    before_id: Option<i64>,
+) -> Result<String> {
     // Get the D1 database
     let d1 = match env.d1("DB") {
         Ok(db) => db,
@@ -38,7 +257,29 @@ pub async fn get_crowd_level_history(env: &Env, since_timestamp: Option<i64>, un
         }
     };
 
-    // Build the query based on parameters
+    let (where_clause, params) = reading_conditions(website_url, since_timestamp, until_timestamp, include_flagged, before_id);
+    let stmt = format!("
+        SELECT MAX(id) AS latest_id, COUNT(*) AS count, TOTAL(version) AS versions
+        FROM gym_crowd_levels {}
+    ", where_clause);
+
+    let state = d1.prepare(&stmt)
+        .bind(&params)?
+        .first::<serde_json::Value>(None)
+        .await?
+        .unwrap_or_default();
+
+    Ok(format!("{}-{}-{}", state["latest_id"], state["count"], state["versions"]))
+}
+
+/// Builds the WHERE clause shared by the reading queries
+fn reading_conditions(
+    website_url: Option<&str>,
+    since_timestamp: Option<i64>,
+    until_timestamp: Option<i64>,
+    include_flagged: bool,
+    before_id: Option<i64>,
+) -> (String, Vec<wasm_bindgen::JsValue>) {
     let mut conditions = Vec::new();
     let mut params = Vec::new();
 
@@ -57,30 +298,72 @@ pub async fn get_crowd_level_history(env: &Env, since_timestamp: Option<i64>, un
         params.push(ts.to_string().into());
     }
 
+    if !include_flagged {
+        conditions.push("quality = 'ok'");
+    }
+
+    if let Some(id) = before_id {
+        conditions.push("id < ?");
+        params.push((id as i32).into());
+    }
+
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
         format!("WHERE {}", conditions.join(" AND "))
     };
 
-    let stmt = format!("SELECT * FROM crowd_levels {} ORDER BY created_at DESC", where_clause);
-
-    let prepared_stmt = d1.prepare(&stmt);
+    (where_clause, params)
+}
 
-    let result = prepared_stmt
-        .bind(&params)?
-        .all()
-        .await?;
+/// Retrieves historical crowd level data in the shape of the /history endpoint
+///
+/// Readings flagged as stuck or closed are left out unless `include_flagged` is set.
+pub async fn get_crowd_level_history(env: &Env, since_timestamp: Option<i64>, until_timestamp: Option<i64>, website_url: Option<&str>, include_flagged: bool, language: Language) -> Result<serde_json::Value> {
+    let rows = get_readings(env, website_url, since_timestamp, until_timestamp, include_flagged, None, None).await?;
 
-    let records = result.results::<serde_json::Value>()?;
+    let websites = scraper::get_configured_websites();
+    let mut records = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut record = serde_json::to_value(row)?;
+        describe_record(&mut record, &websites, language);
+        records.push(record);
+    }
 
     Ok(json!({
         "data": records
     }))
 }
 
-/// Retrieves the latest crowd level record from the database for a specific website
-pub async fn get_latest_crowd_level(env: &Env, website_url: Option<&str>) -> Result<serde_json::Value> {
+/// Retrieves the latest unflagged reading for a website taken at most `max_age_minutes` before `at`
+pub async fn get_reading_near(env: &Env, website_url: &str, at: &str, max_age_minutes: i64) -> Result<Option<serde_json::Value>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        SELECT * FROM gym_crowd_levels
+        WHERE website_url = ?
+        AND quality = 'ok'
+        AND created_at <= ?
+        AND created_at >= datetime(?, ?)
+        ORDER BY created_at DESC
+        LIMIT 1
+    ";
+
+    d1.prepare(stmt)
+        .bind(&[website_url.into(), at.into(), at.into(), format!("-{} minutes", max_age_minutes).into()])?
+        .first::<serde_json::Value>(None)
+        .await
+}
+
+/// Retrieves the newest row of every gym, or of a single gym
+pub async fn get_latest_readings(env: &Env, website_url: Option<&str>) -> Result<Vec<CrowdLevelRow>> {
     // Get the D1 database
     let d1 = match env.d1("DB") {
         Ok(db) => db,
@@ -90,15 +373,14 @@ pub async fn get_latest_crowd_level(env: &Env, website_url: Option<&str>) -> Res
         }
     };
 
-    // Query the latest record
     let (stmt, params) = if let Some(url) = website_url {
         (
-            "SELECT * FROM crowd_levels WHERE website_url = ? ORDER BY created_at DESC LIMIT 1",
+            "SELECT * FROM gym_crowd_levels WHERE website_url = ? ORDER BY created_at DESC, id DESC LIMIT 1",
             vec![url.into()]
         )
     } else {
         (
-            "SELECT * FROM crowd_levels ORDER BY created_at DESC LIMIT 1",
+            "SELECT * FROM gym_crowd_levels WHERE id IN (SELECT MAX(id) FROM crowd_levels GROUP BY gym_id) ORDER BY website_url",
             vec![]
         )
     };
@@ -108,34 +390,111 @@ pub async fn get_latest_crowd_level(env: &Env, website_url: Option<&str>) -> Res
         .all()
         .await?;
 
-    let records = result.results::<serde_json::Value>()?;
+    result.results::<CrowdLevelRow>()
+}
 
-    if records.is_empty() {
-        return Ok(json!({
-            "error": "No records found"
-        }));
-    }
+/// Retrieves the latest crowd level record in the shape of the /history/latest endpoint
+///
+/// Without a website the newest record of any gym is returned, None if there is no record yet.
+pub async fn get_latest_crowd_level(env: &Env, website_url: Option<&str>, language: Language) -> Result<Option<serde_json::Value>> {
+    let rows = get_latest_readings(env, website_url).await?;
 
-    let record = &records[0];
+    let Some(row) = rows.into_iter().max_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id))) else {
+        return Ok(None);
+    };
 
-    // Calculate additional fields based on the percentage
-    let percentage = record["percentage"].as_str().unwrap_or("0");
-    let percentage_float = percentage.parse::<f64>().unwrap_or(0.0);
+    let percentage_float = row.percentage();
+    let mut record = serde_json::to_value(&row)?;
+    describe_record(&mut record, &scraper::get_configured_websites(), language);
 
-    Ok(json!({
+    Ok(Some(json!({
         "record": record,
-        "crowd_level_percentage": percentage,
+        "crowd_level_percentage": row.percentage,
         "crowd_level_description": record["description"],
-        "location": record["website_name"],
-        "website_url": record["website_url"],
+        "location": row.website_name,
+        "website_url": row.website_url,
         "details": {
             "raw_percentage": percentage_float,
-            "created_at": record["created_at"]
+            "created_at": row.created_at
         }
-    }))
+    })))
+}
+
+/// Condition on `gym_crowd_levels` leaving out readings inside an abnormal annotation for their gym
+const NOT_IN_ABNORMAL_ANNOTATION: &str = "NOT EXISTS (
+    SELECT 1 FROM annotations
+    WHERE annotations.abnormal = 1
+    AND (annotations.gym_id IS NULL OR annotations.gym_id = gym_crowd_levels.gym_id)
+    AND gym_crowd_levels.created_at >= annotations.start_at
+    AND gym_crowd_levels.created_at < annotations.end_at
+)";
+
+/// Builds an OR-ed list of `created_at` range conditions for the given local date ranges
+fn created_at_range_conditions(ranges: &[(chrono::NaiveDate, chrono::NaiveDate)]) -> (String, Vec<wasm_bindgen::JsValue>) {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    for (start, end) in ranges {
+        conditions.push("(created_at >= ? AND created_at < ?)");
+        params.push(utils::format_db_timestamp(utils::local_day_start_utc(*start)).into());
+        params.push(utils::format_db_timestamp(utils::local_day_start_utc(*end)).into());
+    }
+
+    (conditions.join(" OR "), params)
+}
+
+/// Upserts a single time average row
+async fn upsert_time_average(
+    d1: &D1Database,
+    website: &scraper::WebsiteConfig,
+    avg: &serde_json::Value,
+) -> Result<()> {
+    let day_of_week = avg["day_of_week"].as_i64().unwrap_or(0);
+    let hour = avg["hour"].as_i64().unwrap_or(0);
+    let avg_percentage = avg["avg_percentage"].as_f64().unwrap_or(0.0);
+    let sample_count = avg["sample_count"].as_i64().unwrap_or(0);
+
+    // SQLite has no STDEV, so the query returns the variance and we take the root here
+    let variance = avg["variance"].as_f64().unwrap_or(0.0).max(0.0);
+    let stddev_percentage = (variance.sqrt() * 100.0).round() / 100.0;
+
+    let upsert_stmt = "
+        INSERT INTO time_averages 
+            (gym_id, day_of_week, hour, average_percentage, stddev_percentage, sample_count, last_updated)
+        VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(gym_id, day_of_week, hour)
+        DO UPDATE SET 
+            average_percentage = excluded.average_percentage,
+            stddev_percentage = excluded.stddev_percentage,
+            sample_count = excluded.sample_count,
+            last_updated = CURRENT_TIMESTAMP
+    ";
+
+    d1.prepare(upsert_stmt)
+        .bind(&[
+            (website.id as i32).into(),
+            (day_of_week as i32).into(),
+            (hour as i32).into(),
+            avg_percentage.into(),
+            stddev_percentage.into(),
+            (sample_count as i32).into(),
+        ])?
+        .run()
+        .await?;
+
+    console_log!(
+        "Updated average for {} on day {} at hour {}: {}% (samples: {})",
+        website.name, day_of_week, hour, avg_percentage, sample_count
+    );
+
+    Ok(())
 }
 
 /// Calculates and stores time-based averages for crowd levels
+///
+/// Public and school holidays are left out of the regular weekday averages and
+/// instead feed a separate holiday profile stored under `HOLIDAY_DAY_OF_WEEK`.
+/// Readings inside abnormal annotations (competitions, maintenance) are ignored.
 pub async fn update_time_averages(env: &Env) -> Result<()> {
     // Get the D1 database
     let d1 = match env.d1("DB") {
@@ -146,75 +505,144 @@ pub async fn update_time_averages(env: &Env) -> Result<()> {
         }
     };
 
+    let school_holidays = get_school_holidays(env).await?;
+    let today = utils::utc_to_local(utils::now_utc()).date();
+
+    // Holidays inside the regular 4 week window are excluded from the weekday profile
+    let weekday_ranges = holidays::holiday_ranges(today - Duration::days(28), today, &school_holidays);
+    let (excluded_clause, excluded_params) = created_at_range_conditions(&weekday_ranges);
+
+    // Holidays are rare, so the holiday profile looks back a full year
+    let holiday_ranges = holidays::holiday_ranges(today - Duration::days(365), today, &school_holidays);
+    let (holiday_clause, holiday_params) = created_at_range_conditions(&holiday_ranges);
+
     // Get all unique website URLs
     let websites = scraper::get_configured_websites();
 
     for website in websites {
-        let website_url = website.url;
-        let website_name = website.name;
-
         // Calculate averages for each day and hour combination for the last 4 weeks
-        let avg_stmt = "
+        let avg_stmt = format!("
             SELECT 
                 CAST(strftime('%w', created_at) AS INTEGER) as day_of_week,
                 CAST(strftime('%H', created_at) AS INTEGER) as hour,
                 ROUND(AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)), 2) as avg_percentage,
+                AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT) * CAST(REPLACE(percentage, '%', '') AS FLOAT))
+                    - AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)) * AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)) as variance,
                 COUNT(*) as sample_count
-            FROM crowd_levels 
-            WHERE website_url = ?
+            FROM gym_crowd_levels 
+            WHERE gym_id = ?
+            AND quality = 'ok'
             AND created_at >= datetime('now', '-28 days')
+            AND {}
+            {}
             GROUP BY day_of_week, hour
             ORDER BY day_of_week, hour ASC
-        ";
+        ", NOT_IN_ABNORMAL_ANNOTATION, if excluded_clause.is_empty() { String::new() } else { format!("AND NOT ({})", excluded_clause) });
+
+        let mut params = vec![(website.id as i32).into()];
+        params.extend(excluded_params.iter().cloned());
 
-        let averages = d1.prepare(avg_stmt)
-            .bind(&[website_url.as_str().into()])?
+        let averages = d1.prepare(&avg_stmt)
+            .bind(&params)?
             .all()
             .await?
             .results::<serde_json::Value>()?;
 
         // Update time_averages table for each day/hour combination
         for avg in averages {
-            let day_of_week = avg["day_of_week"].as_i64().unwrap_or(0);
-            let hour = avg["hour"].as_i64().unwrap_or(0);
-            let avg_percentage = avg["avg_percentage"].as_f64().unwrap_or(0.0);
-            let sample_count = avg["sample_count"].as_i64().unwrap_or(0);
-
-            let upsert_stmt = "
-                INSERT INTO time_averages 
-                    (website_url, website_name, day_of_week, hour, average_percentage, sample_count, last_updated)
-                VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
-                ON CONFLICT(website_url, day_of_week, hour)
-                DO UPDATE SET 
-                    average_percentage = excluded.average_percentage,
-                    sample_count = excluded.sample_count,
-                    last_updated = CURRENT_TIMESTAMP
-            ";
-
-            d1.prepare(upsert_stmt)
-                .bind(&[
-                    website_url.as_str().into(),
-                    website_name.as_str().into(),
-                    (day_of_week as i32).into(),
-                    (hour as i32).into(),
-                    avg_percentage.into(),
-                    (sample_count as i32).into(),
-                ])?
-                .run()
-                .await?;
+            upsert_time_average(&d1, &website, &avg).await?;
+        }
 
-            console_log!(
-                "Updated average for {} on day {} at hour {}: {}% (samples: {})",
-                website_name, day_of_week, hour, avg_percentage, sample_count
-            );
+        if holiday_clause.is_empty() {
+            continue;
+        }
+
+        // Calculate the hourly holiday profile
+        let holiday_stmt = format!("
+            SELECT 
+                {} as day_of_week,
+                CAST(strftime('%H', created_at) AS INTEGER) as hour,
+                ROUND(AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)), 2) as avg_percentage,
+                AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT) * CAST(REPLACE(percentage, '%', '') AS FLOAT))
+                    - AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)) * AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)) as variance,
+                COUNT(*) as sample_count
+            FROM gym_crowd_levels 
+            WHERE gym_id = ?
+            AND quality = 'ok'
+            AND {}
+            AND ({})
+            GROUP BY hour
+            ORDER BY hour ASC
+        ", holidays::HOLIDAY_DAY_OF_WEEK, NOT_IN_ABNORMAL_ANNOTATION, holiday_clause);
+
+        let mut params = vec![(website.id as i32).into()];
+        params.extend(holiday_params.iter().cloned());
+
+        let holiday_averages = d1.prepare(&holiday_stmt)
+            .bind(&params)?
+            .all()
+            .await?
+            .results::<serde_json::Value>()?;
+
+        for avg in holiday_averages {
+            upsert_time_average(&d1, &website, &avg).await?;
         }
     }
 
     Ok(())
 }
 
-/// Retrieves the time-based averages for a specific website
-pub async fn get_time_averages(env: &Env, website_url: Option<&str>) -> Result<serde_json::Value> {
+/// Retrieves the admin-maintained list of school holidays
+pub async fn get_school_holidays(env: &Env) -> Result<Vec<SchoolHoliday>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let result = d1.prepare("SELECT name, start_date, end_date FROM school_holidays ORDER BY start_date ASC")
+        .all()
+        .await?;
+
+    result.results::<SchoolHoliday>()
+}
+
+/// Replaces the list of school holidays with the given periods
+pub async fn replace_school_holidays(env: &Env, school_holidays: &[SchoolHoliday]) -> Result<()> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Delete and re-insert in one batch so readers never see a partial list
+    let mut statements = vec![d1.prepare("DELETE FROM school_holidays")];
+    for holiday in school_holidays {
+        statements.push(
+            d1.prepare("INSERT INTO school_holidays (name, start_date, end_date) VALUES (?, ?, ?)")
+                .bind(&[
+                    holiday.name.as_str().into(),
+                    holiday.start_date.as_str().into(),
+                    holiday.end_date.as_str().into(),
+                ])?
+        );
+    }
+
+    d1.batch(statements).await?;
+
+    console_log!("Stored {} school holiday periods", school_holidays.len());
+
+    Ok(())
+}
+
+/// Retrieves the time average rows of a website, or of all websites
+pub async fn get_time_average_rows(env: &Env, website_url: Option<&str>) -> Result<Vec<TimeAverageRow>> {
     // Get the D1 database
     let d1 = match env.d1("DB") {
         Ok(db) => db,
@@ -226,12 +654,12 @@ pub async fn get_time_averages(env: &Env, website_url: Option<&str>) -> Result<s
 
     let (stmt, params) = if let Some(url) = website_url {
         (
-            "SELECT * FROM time_averages WHERE website_url = ? ORDER BY day_of_week, hour ASC",
+            "SELECT * FROM gym_time_averages WHERE website_url = ? ORDER BY day_of_week, hour ASC",
             vec![url.into()]
         )
     } else {
         (
-            "SELECT * FROM time_averages ORDER BY website_url, day_of_week, hour ASC",
+            "SELECT * FROM gym_time_averages ORDER BY website_url, day_of_week, hour ASC",
             vec![]
         )
     };
@@ -241,32 +669,68 @@ pub async fn get_time_averages(env: &Env, website_url: Option<&str>) -> Result<s
         .all()
         .await?;
 
-    let records = result.results::<serde_json::Value>()?;
+    result.results::<TimeAverageRow>()
+}
+
+/// Identifies the version of the rows `get_time_average_rows` returns without reading them
+pub async fn get_time_averages_state(env: &Env, website_url: Option<&str>) -> Result<String> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let (stmt, params) = if let Some(url) = website_url {
+        (
+            "SELECT MAX(last_updated) AS last_updated, COUNT(*) AS count FROM gym_time_averages WHERE website_url = ?",
+            vec![url.into()]
+        )
+    } else {
+        (
+            "SELECT MAX(last_updated) AS last_updated, COUNT(*) AS count FROM gym_time_averages",
+            vec![]
+        )
+    };
+
+    let state = d1.prepare(stmt)
+        .bind(&params)?
+        .first::<serde_json::Value>(None)
+        .await?
+        .unwrap_or_default();
+
+    Ok(format!("{}-{}", state["last_updated"], state["count"]))
+}
+
+/// Retrieves the time-based averages in the shape of the /time-averages endpoint,
+/// keyed by website name, weekday and hour
+pub async fn get_time_averages(env: &Env, website_url: Option<&str>) -> Result<serde_json::Value> {
+    let records = get_time_average_rows(env, website_url).await?;
 
     // Process the data into a more structured format
     let mut processed_data = std::collections::HashMap::new();
-    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Holiday"];
 
     for record in records {
-        let website_name = record["website_name"].as_str().unwrap_or("Unknown");
-        let day_idx = record["day_of_week"].as_i64().unwrap_or(0) as usize;
-        let hour = record["hour"].as_i64().unwrap_or(0);
-        let avg = record["average_percentage"].as_f64().unwrap_or(0.0);
-        let count = record["sample_count"].as_i64().unwrap_or(0);
+        let Some(day_name) = weekdays.get(record.day_of_week as usize) else {
+            continue;
+        };
 
         let website_data = processed_data
-            .entry(website_name.to_string())
-            .or_insert_with(|| std::collections::HashMap::new());
+            .entry(record.website_name)
+            .or_insert_with(std::collections::HashMap::new);
 
         let day_data = website_data
-            .entry(weekdays[day_idx].to_string())
-            .or_insert_with(|| std::collections::HashMap::new());
+            .entry(day_name.to_string())
+            .or_insert_with(std::collections::HashMap::new);
 
         day_data.insert(
-            hour.to_string(),
+            record.hour.to_string(),
             json!({
-                "average": avg,
-                "samples": count
+                "average": record.average_percentage,
+                "samples": record.sample_count
             })
         );
     }
@@ -274,4 +738,1132 @@ pub async fn get_time_averages(env: &Env, website_url: Option<&str>) -> Result<s
     Ok(json!({
         "data": processed_data
     }))
-} 
+}
+
+/// Retrieves the stored time average for a single website/day/hour slot
+pub async fn get_time_average_slot(env: &Env, website_url: &str, day_of_week: i64, hour: i64) -> Result<Option<serde_json::Value>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "SELECT * FROM gym_time_averages WHERE website_url = ? AND day_of_week = ? AND hour = ? LIMIT 1";
+
+    d1.prepare(stmt)
+        .bind(&[website_url.into(), (day_of_week as i32).into(), (hour as i32).into()])?
+        .first::<serde_json::Value>(None)
+        .await
+}
+
+/// Stores an anomaly detected for a freshly stored crowd level reading
+pub async fn store_anomaly(env: &Env, crowd_level_id: i64, website: &scraper::WebsiteConfig, anomaly: &Anomaly) -> Result<()> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        INSERT INTO anomalies
+            (crowd_level_id, gym_id, kind, percentage, expected_percentage, stddev_percentage, z_score)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+    ";
+
+    d1.prepare(stmt)
+        .bind(&[
+            (crowd_level_id as i32).into(),
+            (website.id as i32).into(),
+            anomaly.kind.into(),
+            anomaly.percentage.into(),
+            anomaly.expected_percentage.into(),
+            anomaly.stddev_percentage.into(),
+            anomaly.z_score.into(),
+        ])?
+        .run()
+        .await?;
+
+    console_log!("Stored {} anomaly for {} (z-score: {})", anomaly.kind, website.name, anomaly.z_score);
+
+    Ok(())
+}
+
+/// Retrieves detected anomalies, newest first
+pub async fn get_anomalies(env: &Env, since_timestamp: Option<i64>, website_url: Option<&str>) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(url) = website_url {
+        conditions.push("website_url = ?");
+        params.push(url.into());
+    }
+
+    if let Some(ts) = since_timestamp {
+        conditions.push("created_at > DATETIME(?, 'unixepoch')");
+        params.push(ts.to_string().into());
+    }
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let stmt = format!("SELECT * FROM gym_anomalies {} ORDER BY created_at DESC LIMIT 500", where_clause);
+
+    let result = d1.prepare(&stmt)
+        .bind(&params)?
+        .all()
+        .await?;
+
+    let records = result.results::<serde_json::Value>()?;
+
+    Ok(json!({
+        "data": records
+    }))
+}
+
+/// Calculates per-period trend statistics for a website
+///
+/// Each period reports the mean and peak occupancy and how many hours had an
+/// hourly average at or above `busy_threshold`, together with the change
+/// compared to the previous period and to the same period one year earlier.
+pub async fn get_trends(env: &Env, website_url: &str, period: TrendPeriod, busy_threshold: f64) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Load one extra year so the oldest shown period has a year-over-year comparison
+    let first_shown = period.first_shown(utils::now_utc().date());
+    let report_start = period.year_before(first_shown);
+
+    // Aggregate readings per hour first, so busy hours can be counted per period
+    let stmt = format!("
+        SELECT
+            period_start,
+            ROUND(SUM(hour_sum) / SUM(hour_samples), 2) as mean_percentage,
+            MAX(hour_max) as peak_percentage,
+            SUM(CASE WHEN hour_sum / hour_samples >= ? THEN 1 ELSE 0 END) as busy_hours,
+            SUM(hour_samples) as sample_count
+        FROM (
+            SELECT
+                {} as period_start,
+                SUM(CAST(REPLACE(percentage, '%', '') AS FLOAT)) as hour_sum,
+                MAX(CAST(REPLACE(percentage, '%', '') AS FLOAT)) as hour_max,
+                COUNT(*) as hour_samples
+            FROM gym_crowd_levels
+            WHERE website_url = ?
+            AND quality = 'ok'
+            AND created_at >= ?
+            GROUP BY strftime('%Y-%m-%d %H', created_at)
+        )
+        GROUP BY period_start
+        ORDER BY period_start ASC
+    ", period.sql_period_start());
+
+    let records = d1.prepare(&stmt)
+        .bind(&[
+            busy_threshold.into(),
+            website_url.into(),
+            report_start.format("%Y-%m-%d").to_string().into(),
+        ])?
+        .all()
+        .await?
+        .results::<serde_json::Value>()?;
+
+    let by_start: std::collections::HashMap<String, &serde_json::Value> = records.iter()
+        .filter_map(|record| record["period_start"].as_str().map(|start| (start.to_string(), record)))
+        .collect();
+
+    // Difference of each metric between a period and the one it is compared with
+    let change = |current: &serde_json::Value, other: Option<&&serde_json::Value>| match other {
+        Some(other) => json!({
+            "period_start": other["period_start"],
+            "mean_percentage": round_change(current["mean_percentage"].as_f64(), other["mean_percentage"].as_f64()),
+            "peak_percentage": round_change(current["peak_percentage"].as_f64(), other["peak_percentage"].as_f64()),
+            "busy_hours": round_change(current["busy_hours"].as_f64(), other["busy_hours"].as_f64())
+        }),
+        None => serde_json::Value::Null,
+    };
+
+    let mut periods = Vec::new();
+
+    for record in &records {
+        let Some(start) = record["period_start"].as_str()
+            .and_then(|start| chrono::NaiveDate::parse_from_str(start, "%Y-%m-%d").ok()) else {
+            continue;
+        };
+
+        // Older periods are only loaded to serve as year-over-year comparison
+        if start < first_shown {
+            continue;
+        }
+
+        let previous_key = period.previous(start).format("%Y-%m-%d").to_string();
+        let last_year_key = period.year_before(start).format("%Y-%m-%d").to_string();
+
+        periods.push(json!({
+            "period_start": record["period_start"],
+            "mean_percentage": record["mean_percentage"],
+            "peak_percentage": record["peak_percentage"],
+            "busy_hours": record["busy_hours"],
+            "sample_count": record["sample_count"],
+            "change_vs_previous": change(record, by_start.get(&previous_key)),
+            "change_vs_last_year": change(record, by_start.get(&last_year_key))
+        }));
+    }
+
+    Ok(json!({
+        "website_url": website_url,
+        "period": period.as_str(),
+        "busy_threshold": busy_threshold,
+        "data": periods
+    }))
+}
+
+/// Rounded difference between two optional metrics
+fn round_change(current: Option<f64>, other: Option<f64>) -> Option<f64> {
+    Some(((current? - other?) * 100.0).round() / 100.0)
+}
+
+/// Retrieves the unflagged readings of a website between two UTC timestamps, oldest first
+pub async fn get_readings_between(env: &Env, website_url: &str, start: &str, end: &str) -> Result<Vec<serde_json::Value>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        SELECT id, percentage, created_at FROM gym_crowd_levels
+        WHERE website_url = ?
+        AND quality = 'ok'
+        AND created_at >= ?
+        AND created_at < ?
+        ORDER BY created_at ASC
+    ";
+
+    let result = d1.prepare(stmt)
+        .bind(&[website_url.into(), start.into(), end.into()])?
+        .all()
+        .await?;
+
+    result.results::<serde_json::Value>()
+}
+
+/// Calculates and stores the daily summary of every configured website for a local day
+pub async fn update_daily_summaries(env: &Env, day: chrono::NaiveDate) -> Result<()> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let start = utils::format_db_timestamp(utils::local_day_start_utc(day));
+    let end = utils::format_db_timestamp(utils::local_day_start_utc(day + Duration::days(1)));
+    let summary_date = day.format("%Y-%m-%d").to_string();
+
+    for website in scraper::get_configured_websites() {
+        let readings: Vec<(chrono::NaiveDateTime, f64)> = get_readings_between(env, &website.url, &start, &end).await?
+            .iter()
+            .filter_map(|record| {
+                let created_at = utils::parse_db_timestamp(record["created_at"].as_str()?)?;
+                let percentage = record["percentage"].as_str()?.parse::<f64>().ok()?;
+                Some((utils::utc_to_local(created_at), percentage))
+            })
+            .collect();
+
+        // The scheduled job only scrapes during opening hours, so those define full coverage
+        let expected_readings = website.opening_hours.hours_on(day)
+            .map(|(open, close)| (close - open).num_minutes() / analysis::SCRAPE_INTERVAL_MINUTES)
+            .unwrap_or(0);
+
+        let summary = analysis::summarize_day(&readings, expected_readings);
+
+        let upsert_stmt = "
+            INSERT INTO daily_summaries
+                (gym_id, summary_date, mean_percentage, max_percentage, peak_time,
+                 first_quiet_start, first_quiet_end, last_quiet_start, last_quiet_end,
+                 sample_count, coverage_percentage, last_updated)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(gym_id, summary_date)
+            DO UPDATE SET
+                mean_percentage = excluded.mean_percentage,
+                max_percentage = excluded.max_percentage,
+                peak_time = excluded.peak_time,
+                first_quiet_start = excluded.first_quiet_start,
+                first_quiet_end = excluded.first_quiet_end,
+                last_quiet_start = excluded.last_quiet_start,
+                last_quiet_end = excluded.last_quiet_end,
+                sample_count = excluded.sample_count,
+                coverage_percentage = excluded.coverage_percentage,
+                last_updated = CURRENT_TIMESTAMP
+        ";
+
+        let optional_text = |value: &Option<String>| match value {
+            Some(value) => value.as_str().into(),
+            None => wasm_bindgen::JsValue::NULL,
+        };
+        let optional_number = |value: Option<f64>| match value {
+            Some(value) => value.into(),
+            None => wasm_bindgen::JsValue::NULL,
+        };
+
+        d1.prepare(upsert_stmt)
+            .bind(&[
+                (website.id as i32).into(),
+                summary_date.as_str().into(),
+                optional_number(summary.mean_percentage),
+                optional_number(summary.max_percentage),
+                optional_text(&summary.peak_time),
+                optional_text(&summary.first_quiet_start),
+                optional_text(&summary.first_quiet_end),
+                optional_text(&summary.last_quiet_start),
+                optional_text(&summary.last_quiet_end),
+                (summary.sample_count as i32).into(),
+                optional_number(summary.coverage_percentage),
+            ])?
+            .run()
+            .await?;
+
+        console_log!(
+            "Stored daily summary for {} on {}: mean {:?}%, max {:?}%, coverage {:?}%",
+            website.name, summary_date, summary.mean_percentage, summary.max_percentage, summary.coverage_percentage
+        );
+    }
+
+    Ok(())
+}
+
+/// Retrieves daily summaries, newest first
+pub async fn get_daily_summaries(env: &Env, website_url: Option<&str>, since_date: Option<&str>, until_date: Option<&str>) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(url) = website_url {
+        conditions.push("website_url = ?");
+        params.push(url.into());
+    }
+
+    if let Some(date) = since_date {
+        conditions.push("summary_date >= ?");
+        params.push(date.into());
+    }
+
+    if let Some(date) = until_date {
+        conditions.push("summary_date <= ?");
+        params.push(date.into());
+    }
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let stmt = format!("SELECT * FROM gym_daily_summaries {} ORDER BY summary_date DESC, website_name ASC", where_clause);
+
+    let result = d1.prepare(&stmt)
+        .bind(&params)?
+        .all()
+        .await?;
+
+    let records = result.results::<serde_json::Value>()?;
+
+    Ok(json!({
+        "data": records
+    }))
+}
+
+/// Calculates the hourly time-averages profile split into rainy and dry readings
+///
+/// Only readings with weather data from the last `days` days are used, holidays and
+/// abnormal annotations are left out like in the regular profile. Days and hours are UTC as in `time_averages`.
+pub async fn get_weather_time_averages(env: &Env, website_url: Option<&str>, days: i64) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let school_holidays = get_school_holidays(env).await?;
+    let today = utils::utc_to_local(utils::now_utc()).date();
+    let holiday_ranges = holidays::holiday_ranges(today - Duration::days(days), today, &school_holidays);
+    let (excluded_clause, excluded_params) = created_at_range_conditions(&holiday_ranges);
+
+    let mut conditions = vec![
+        "quality = 'ok'".to_string(),
+        "precipitation IS NOT NULL".to_string(),
+        "created_at >= datetime('now', ?)".to_string(),
+        NOT_IN_ABNORMAL_ANNOTATION.to_string(),
+    ];
+    let mut params = vec![weather::RAIN_THRESHOLD_MM.into(), format!("-{} days", days).into()];
+
+    if let Some(url) = website_url {
+        conditions.push("website_url = ?".to_string());
+        params.push(url.into());
+    }
+
+    if !excluded_clause.is_empty() {
+        conditions.push(format!("NOT ({})", excluded_clause));
+        params.extend(excluded_params);
+    }
+
+    let stmt = format!("
+        SELECT
+            website_name,
+            CASE WHEN precipitation >= ? THEN 'rain' ELSE 'dry' END as weather,
+            CAST(strftime('%w', created_at) AS INTEGER) as day_of_week,
+            CAST(strftime('%H', created_at) AS INTEGER) as hour,
+            ROUND(AVG(CAST(REPLACE(percentage, '%', '') AS FLOAT)), 2) as avg_percentage,
+            COUNT(*) as sample_count
+        FROM gym_crowd_levels
+        WHERE {}
+        GROUP BY website_name, weather, day_of_week, hour
+        ORDER BY website_name, weather, day_of_week, hour ASC
+    ", conditions.join(" AND "));
+
+    let records = d1.prepare(&stmt)
+        .bind(&params)?
+        .all()
+        .await?
+        .results::<serde_json::Value>()?;
+
+    // Same layout as the regular time averages, with the weather condition as an extra level
+    let mut processed_data = std::collections::HashMap::new();
+    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+    for record in records {
+        let website_name = record["website_name"].as_str().unwrap_or("Unknown");
+        let condition = record["weather"].as_str().unwrap_or("dry");
+        let day_idx = record["day_of_week"].as_i64().unwrap_or(0) as usize;
+        let Some(day_name) = weekdays.get(day_idx) else {
+            continue;
+        };
+        let hour = record["hour"].as_i64().unwrap_or(0);
+
+        processed_data
+            .entry(website_name.to_string())
+            .or_insert_with(std::collections::HashMap::new)
+            .entry(condition.to_string())
+            .or_insert_with(std::collections::HashMap::new)
+            .entry(day_name.to_string())
+            .or_insert_with(std::collections::HashMap::new)
+            .insert(
+                hour.to_string(),
+                json!({
+                    "average": record["avg_percentage"],
+                    "samples": record["sample_count"]
+                })
+            );
+    }
+
+    Ok(json!({
+        "rain_threshold_mm": weather::RAIN_THRESHOLD_MM,
+        "days": days,
+        "data": processed_data
+    }))
+}
+
+/// A reading together with the weather it was taken in
+struct WeatherReading {
+    percentage: f64,
+    /// Difference to the time average for the reading's slot, if one exists
+    deviation: Option<f64>,
+    temperature: f64,
+    precipitation: f64,
+}
+
+/// Correlates a website's readings with the weather stored alongside them
+///
+/// Besides the raw crowd level, each reading is compared with the time average
+/// for its slot, so the usual daily rhythm doesn't drown out the weather effect.
+pub async fn get_weather_correlation(env: &Env, website_url: &str, days: i64) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        SELECT c.percentage, c.temperature, c.precipitation, t.average_percentage
+        FROM gym_crowd_levels c
+        LEFT JOIN time_averages t
+            ON t.gym_id = c.gym_id
+            AND t.day_of_week = CAST(strftime('%w', c.created_at) AS INTEGER)
+            AND t.hour = CAST(strftime('%H', c.created_at) AS INTEGER)
+        WHERE c.website_url = ?
+        AND c.quality = 'ok'
+        AND c.temperature IS NOT NULL
+        AND c.precipitation IS NOT NULL
+        AND c.created_at >= datetime('now', ?)
+    ";
+
+    let records = d1.prepare(stmt)
+        .bind(&[website_url.into(), format!("-{} days", days).into()])?
+        .all()
+        .await?
+        .results::<serde_json::Value>()?;
+
+    let readings: Vec<WeatherReading> = records.iter()
+        .filter_map(|record| {
+            let percentage = record["percentage"].as_str()?.replace('%', "").parse::<f64>().ok()?;
+            Some(WeatherReading {
+                percentage,
+                deviation: record["average_percentage"].as_f64().map(|average| percentage - average),
+                temperature: record["temperature"].as_f64()?,
+                precipitation: record["precipitation"].as_f64()?,
+            })
+        })
+        .collect();
+
+    let round = |value: Option<f64>, factor: f64| value.map(|value| (value * factor).round() / factor);
+
+    let correlate = |weather_value: fn(&WeatherReading) -> f64| {
+        let raw: Vec<(f64, f64)> = readings.iter().map(|reading| (weather_value(reading), reading.percentage)).collect();
+        let deviations: Vec<(f64, f64)> = readings.iter()
+            .filter_map(|reading| reading.deviation.map(|deviation| (weather_value(reading), deviation)))
+            .collect();
+
+        json!({
+            "correlation": round(analysis::pearson_correlation(&raw), 1000.0),
+            "deviation_correlation": round(analysis::pearson_correlation(&deviations), 1000.0)
+        })
+    };
+
+    let split = |rainy: bool| {
+        let matching: Vec<&WeatherReading> = readings.iter()
+            .filter(|reading| weather::is_rainy(reading.precipitation) == rainy)
+            .collect();
+        let percentages: Vec<f64> = matching.iter().map(|reading| reading.percentage).collect();
+        let deviations: Vec<f64> = matching.iter().filter_map(|reading| reading.deviation).collect();
+
+        let mean = |values: &[f64]| (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64);
+
+        json!({
+            "samples": matching.len(),
+            "mean_percentage": round(mean(&percentages), 100.0),
+            "mean_deviation": round(mean(&deviations), 100.0)
+        })
+    };
+
+    Ok(json!({
+        "website_url": website_url,
+        "days": days,
+        "samples": readings.len(),
+        "rain_threshold_mm": weather::RAIN_THRESHOLD_MM,
+        "temperature": correlate(|reading| reading.temperature),
+        "precipitation": correlate(|reading| reading.precipitation),
+        "rain": split(true),
+        "dry": split(false)
+    }))
+}
+
+/// Retrieves the annotations overlapping the given time range, oldest first
+///
+/// Annotations without a gym apply to all gyms and are included for every website filter.
+pub async fn get_annotations(env: &Env, since_timestamp: Option<i64>, until_timestamp: Option<i64>, website_url: Option<&str>) -> Result<Vec<serde_json::Value>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(url) = website_url {
+        conditions.push("(website_url IS NULL OR website_url = ?)");
+        params.push(url.into());
+    }
+
+    if let Some(ts) = since_timestamp {
+        conditions.push("COALESCE(end_at, start_at) >= DATETIME(?, 'unixepoch')");
+        params.push(ts.to_string().into());
+    }
+
+    if let Some(ts) = until_timestamp {
+        conditions.push("start_at <= DATETIME(?, 'unixepoch')");
+        params.push(ts.to_string().into());
+    }
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let stmt = format!("
+        SELECT id, website_url, kind, title, start_at, end_at, abnormal = 1 as abnormal, created_at
+        FROM gym_annotations {}
+        ORDER BY start_at ASC
+    ", where_clause);
+
+    let mut records = d1.prepare(&stmt)
+        .bind(&params)?
+        .all()
+        .await?
+        .results::<serde_json::Value>()?;
+
+    // SQLite has no booleans, expose the flag as one
+    for record in records.iter_mut() {
+        record["abnormal"] = json!(record["abnormal"].as_i64() == Some(1));
+    }
+
+    Ok(records)
+}
+
+/// Binds the columns of an annotation in the order used by insert and update
+fn annotation_params(annotation: &Annotation) -> Vec<wasm_bindgen::JsValue> {
+    vec![
+        annotation.website_url.as_deref().into(),
+        annotation.kind.as_str().into(),
+        annotation.title.as_str().into(),
+        annotation.start_at().into(),
+        annotation.end_at().into(),
+        (annotation.abnormal as i32).into(),
+    ]
+}
+
+/// Stores a new annotation and returns its id
+pub async fn store_annotation(env: &Env, annotation: &Annotation) -> Result<i64> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        INSERT INTO annotations (gym_id, kind, title, start_at, end_at, abnormal)
+        VALUES ((SELECT id FROM gyms WHERE url = ?), ?, ?, ?, ?, ?)
+    ";
+
+    let result = d1.prepare(stmt)
+        .bind(&annotation_params(annotation))?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.last_row_id).unwrap_or_default())
+}
+
+/// Replaces an existing annotation, returns false if there is no annotation with that id
+pub async fn update_annotation(env: &Env, id: i64, annotation: &Annotation) -> Result<bool> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        UPDATE annotations
+        SET gym_id = (SELECT id FROM gyms WHERE url = ?), kind = ?, title = ?, start_at = ?, end_at = ?, abnormal = ?
+        WHERE id = ?
+    ";
+
+    let mut params = annotation_params(annotation);
+    params.push((id as i32).into());
+
+    let result = d1.prepare(stmt)
+        .bind(&params)?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or_default() > 0)
+}
+
+/// Deletes an annotation, returns false if there is no annotation with that id
+pub async fn delete_annotation(env: &Env, id: i64) -> Result<bool> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let result = d1.prepare("DELETE FROM annotations WHERE id = ?")
+        .bind(&[(id as i32).into()])?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or_default() > 0)
+}
+
+/// Reports per gym and local day how complete the 10-minute series is
+///
+/// Only opening hours count, as the scheduled job doesn't scrape outside them. Flagged
+/// readings still count as data here, a gap is a stretch without any reading that lasts
+/// longer than `threshold_minutes`. Days run from `since_date` to `until_date` inclusive.
+pub async fn get_coverage(
+    env: &Env,
+    website_url: Option<&str>,
+    since_date: chrono::NaiveDate,
+    until_date: chrono::NaiveDate,
+    threshold_minutes: i64,
+) -> Result<serde_json::Value> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let now = utils::now_utc();
+    let start = utils::format_db_timestamp(utils::local_day_start_utc(since_date));
+    let end = utils::format_db_timestamp(utils::local_day_start_utc(until_date + Duration::days(1)));
+
+    let websites: Vec<scraper::WebsiteConfig> = scraper::get_configured_websites().into_iter()
+        .filter(|website| website_url.is_none_or(|url| website.url == url))
+        .collect();
+
+    let mut data = Vec::new();
+
+    for website in websites {
+        let stmt = "
+            SELECT created_at FROM gym_crowd_levels
+            WHERE website_url = ?
+            AND created_at >= ?
+            AND created_at < ?
+            ORDER BY created_at ASC
+        ";
+
+        let readings: Vec<chrono::NaiveDateTime> = d1.prepare(stmt)
+            .bind(&[website.url.as_str().into(), start.as_str().into(), end.as_str().into()])?
+            .all()
+            .await?
+            .results::<serde_json::Value>()?
+            .iter()
+            .filter_map(|record| utils::parse_db_timestamp(record["created_at"].as_str()?))
+            .collect();
+
+        let mut days = Vec::new();
+        let mut total_expected = 0;
+        let mut total_readings = 0;
+
+        let mut day = since_date;
+        while day <= until_date {
+            // Opening hours of the day in UTC, cut off at the current time for today
+            let window = website.opening_hours.hours_on(day)
+                .map(|(open, close)| (utils::local_to_utc(day.and_time(open)), utils::local_to_utc(day.and_time(close)).min(now)))
+                .filter(|(open, close)| open < close);
+
+            let (expected_readings, reading_count, gaps) = match window {
+                Some((open, close)) => (
+                    (close - open).num_minutes() / analysis::SCRAPE_INTERVAL_MINUTES,
+                    readings.iter().filter(|time| **time >= open && **time < close).count() as i64,
+                    analysis::find_gaps(&readings, open, close, threshold_minutes),
+                ),
+                None => (0, 0, Vec::new()),
+            };
+
+            total_expected += expected_readings;
+            total_readings += reading_count.min(expected_readings);
+
+            days.push(json!({
+                "date": day.format("%Y-%m-%d").to_string(),
+                "expected_readings": expected_readings,
+                "readings": reading_count,
+                "coverage_percentage": analysis::coverage_percentage(reading_count, expected_readings),
+                "gaps": gaps.iter().map(|(gap_start, gap_end)| json!({
+                    "start_at": utils::format_db_timestamp(*gap_start),
+                    "end_at": utils::format_db_timestamp(*gap_end),
+                    "minutes": (*gap_end - *gap_start).num_minutes()
+                })).collect::<Vec<_>>()
+            }));
+
+            day += Duration::days(1);
+        }
+
+        data.push(json!({
+            "website_url": website.url,
+            "website_name": website.name,
+            "coverage_percentage": analysis::coverage_percentage(total_readings, total_expected),
+            "days": days
+        }));
+    }
+
+    Ok(json!({
+        "since": since_date.format("%Y-%m-%d").to_string(),
+        "until": until_date.format("%Y-%m-%d").to_string(),
+        "threshold_minutes": threshold_minutes,
+        "data": data
+    }))
+}
+
+/// Collects the notable events of each gym that lasted until the given UTC time or later, newest first
+///
+/// Events are derived from stored data only: anomaly periods from the anomalies table,
+/// outages from gaps in the readings during opening hours and new all-time peaks from
+/// readings higher than every earlier one. Events are built from whole local days, so an
+/// event that began before `since` keeps its start (and with it its feed id) and peaks are
+/// compared with the complete history before each reading.
+pub async fn get_notable_events(env: &Env, website_url: Option<&str>, since: chrono::NaiveDateTime) -> Result<Vec<NotableEvent>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let now = utils::now_utc();
+    // Readings stop at closing time, so no event spans two local days
+    let lookback = utils::local_day_start_utc(utils::utc_to_local(since).date());
+    let lookback_str = utils::format_db_timestamp(lookback);
+
+    let websites: Vec<scraper::WebsiteConfig> = scraper::get_configured_websites().into_iter()
+        .filter(|website| website_url.is_none_or(|url| website.url == url))
+        .collect();
+
+    let mut events = Vec::new();
+
+    for website in websites {
+        let event = |kind: EventKind, start, end, percentage, expected_percentage| NotableEvent {
+            kind,
+            gym_slug: website.slug.clone(),
+            website_url: website.url.clone(),
+            website_name: website.name.clone(),
+            start,
+            end,
+            percentage,
+            expected_percentage,
+        };
+
+        // Anomalies of the same kind close to each other form one period
+        let anomalies = d1.prepare("
+            SELECT kind, percentage, expected_percentage, created_at FROM gym_anomalies
+            WHERE website_url = ? AND created_at >= ?
+            ORDER BY created_at ASC
+        ")
+            .bind(&[website.url.as_str().into(), lookback_str.as_str().into()])?
+            .all()
+            .await?
+            .results::<serde_json::Value>()?;
+
+        let mut periods: Vec<NotableEvent> = Vec::new();
+        for anomaly in anomalies {
+            let (Some(kind), Some(time), Some(percentage)) = (
+                anomaly["kind"].as_str().and_then(EventKind::from_anomaly),
+                anomaly["created_at"].as_str().and_then(utils::parse_db_timestamp),
+                anomaly["percentage"].as_f64(),
+            ) else {
+                continue;
+            };
+            let expected = anomaly["expected_percentage"].as_f64();
+
+            let open_period = periods.iter_mut().rev()
+                .find(|period| period.kind == kind)
+                .filter(|period| (time - period.end).num_minutes() <= feed::ANOMALY_PERIOD_GAP_MINUTES);
+
+            match open_period {
+                Some(period) => {
+                    period.end = time;
+                    let more_extreme = period.percentage.is_none_or(|current| match kind {
+                        EventKind::UnusuallyLow => percentage < current,
+                        _ => percentage > current,
+                    });
+                    if more_extreme {
+                        period.percentage = Some(percentage);
+                        period.expected_percentage = expected;
+                    }
+                },
+                None => periods.push(event(kind, time, time, Some(percentage), expected)),
+            }
+        }
+        events.extend(periods);
+
+        let readings = d1.prepare("
+            SELECT CAST(percentage AS REAL) AS percentage, quality, created_at FROM gym_crowd_levels
+            WHERE website_url = ? AND created_at >= ?
+            ORDER BY created_at ASC
+        ")
+            .bind(&[website.url.as_str().into(), lookback_str.as_str().into()])?
+            .all()
+            .await?
+            .results::<serde_json::Value>()?;
+
+        let readings: Vec<(chrono::NaiveDateTime, f64, bool)> = readings.iter()
+            .filter_map(|record| Some((
+                utils::parse_db_timestamp(record["created_at"].as_str()?)?,
+                record["percentage"].as_f64()?,
+                record["quality"].as_str() == Some(analysis::QUALITY_OK),
+            )))
+            .collect();
+
+        // Long gaps during the opening hours of each local day
+        let reading_times: Vec<chrono::NaiveDateTime> = readings.iter().map(|(time, _, _)| *time).collect();
+        let mut day = utils::utc_to_local(since).date();
+        while day <= utils::utc_to_local(now).date() {
+            let window = website.opening_hours.hours_on(day)
+                .map(|(open, close)| (utils::local_to_utc(day.and_time(open)), utils::local_to_utc(day.and_time(close)).min(now)))
+                .filter(|(open, close)| open < close);
+
+            if let Some((open, close)) = window {
+                for (gap_start, gap_end) in analysis::find_gaps(&reading_times, open, close, feed::OUTAGE_MIN_MINUTES) {
+                    events.push(event(EventKind::Outage, gap_start, gap_end, None, None));
+                }
+            }
+
+            day += Duration::days(1);
+        }
+
+        // New all-time peaks, only once there is history to compare with
+        let baseline = d1.prepare("
+            SELECT MAX(CAST(percentage AS REAL)) AS peak FROM gym_crowd_levels
+            WHERE website_url = ? AND quality = 'ok' AND created_at < ?
+        ")
+            .bind(&[website.url.as_str().into(), lookback_str.as_str().into()])?
+            .first::<serde_json::Value>(None)
+            .await?
+            .and_then(|record| record["peak"].as_f64());
+
+        if let Some(mut peak) = baseline {
+            let mut peaks: Vec<NotableEvent> = Vec::new();
+            for (time, percentage, ok) in &readings {
+                if !ok || *percentage <= peak {
+                    continue;
+                }
+                peak = *percentage;
+
+                // A rising afternoon beats the record several times, keep one entry per local day
+                match peaks.last_mut() {
+                    Some(last) if utils::utc_to_local(last.start).date() == utils::utc_to_local(*time).date() => {
+                        last.end = *time;
+                        last.percentage = Some(*percentage);
+                    },
+                    _ => peaks.push(event(EventKind::NewPeak, *time, *time, Some(*percentage), None)),
+                }
+            }
+            events.extend(peaks);
+        }
+    }
+
+    events.retain(|event| event.end >= since);
+    events.sort_by_key(|event| std::cmp::Reverse(event.end));
+
+    Ok(events)
+}
+
+/// Stores a new threshold alert subscription and returns its id
+pub async fn store_subscription(env: &Env, subscription: &Subscription) -> Result<i64> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = "
+        INSERT INTO subscriptions (gym_id, threshold, direction, windows, webhook_url, telegram_chat_id, push_endpoint, push_p256dh, push_auth, format, cooldown_minutes, language)
+        VALUES ((SELECT id FROM gyms WHERE url = ?), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ";
+
+    let result = d1.prepare(stmt)
+        .bind(&[
+            subscription.website_url.as_str().into(),
+            subscription.threshold.into(),
+            subscription.direction.as_str().into(),
+            serde_json::to_string(&subscription.windows)?.into(),
+            subscription.webhook_url.as_deref().into(),
+            subscription.telegram_chat_id.map(|chat_id| chat_id as f64).into(),
+            subscription.push.as_ref().map(|push| push.endpoint.as_str()).into(),
+            subscription.push.as_ref().map(|push| push.keys.p256dh.as_str()).into(),
+            subscription.push.as_ref().map(|push| push.keys.auth.as_str()).into(),
+            subscription.format.as_str().into(),
+            (subscription.cooldown_minutes as i32).into(),
+            subscription.language.code().into(),
+        ])?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.last_row_id).unwrap_or_default())
+}
+
+/// Retrieves all subscriptions, optionally only those for one website
+pub async fn get_subscriptions(env: &Env, website_url: Option<&str>) -> Result<Vec<SubscriptionRecord>> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let (stmt, params) = if let Some(url) = website_url {
+        ("SELECT * FROM gym_subscriptions WHERE website_url = ? ORDER BY id ASC", vec![url.into()])
+    } else {
+        ("SELECT * FROM gym_subscriptions ORDER BY id ASC", vec![])
+    };
+
+    d1.prepare(stmt)
+        .bind(&params)?
+        .all()
+        .await?
+        .results::<SubscriptionRecord>()
+}
+
+/// Records whether the latest reading satisfied a subscription and, if notified, when
+pub async fn update_subscription_state(env: &Env, id: i64, matching: bool, notified: bool) -> Result<()> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let stmt = if notified {
+        "UPDATE subscriptions SET matching = ?, last_notified_at = CURRENT_TIMESTAMP WHERE id = ?"
+    } else {
+        "UPDATE subscriptions SET matching = ? WHERE id = ?"
+    };
+
+    d1.prepare(stmt)
+        .bind(&[(matching as i32).into(), (id as i32).into()])?
+        .run()
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes all subscriptions of a Telegram chat and returns how many were removed
+pub async fn delete_telegram_subscriptions(env: &Env, chat_id: i64) -> Result<usize> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Chat ids exceed the i32 range, so they are bound as numbers
+    let result = d1.prepare("DELETE FROM subscriptions WHERE telegram_chat_id = ?")
+        .bind(&[(chat_id as f64).into()])?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or_default())
+}
+
+/// Counts the subscriptions of a browser push endpoint
+pub async fn count_push_subscriptions(env: &Env, endpoint: &str) -> Result<usize> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let count = d1.prepare("SELECT COUNT(*) AS count FROM subscriptions WHERE push_endpoint = ?")
+        .bind(&[endpoint.into()])?
+        .first::<i64>(Some("count"))
+        .await?
+        .unwrap_or_default();
+
+    Ok(count as usize)
+}
+
+/// Deletes the subscriptions of a browser push endpoint, optionally only those for one website
+pub async fn delete_push_subscriptions(env: &Env, endpoint: &str, website_url: Option<&str>) -> Result<usize> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let (stmt, params) = if let Some(url) = website_url {
+        ("DELETE FROM subscriptions WHERE push_endpoint = ? AND gym_id = (SELECT id FROM gyms WHERE url = ?)", vec![endpoint.into(), url.into()])
+    } else {
+        ("DELETE FROM subscriptions WHERE push_endpoint = ?", vec![endpoint.into()])
+    };
+
+    let result = d1.prepare(stmt)
+        .bind(&params)?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or_default())
+}
+
+/// Deletes a subscription, returns false if there is no subscription with that id
+pub async fn delete_subscription(env: &Env, id: i64) -> Result<bool> {
+    // Get the D1 database
+    let d1 = match env.d1("DB") {
+        Ok(db) => db,
+        Err(e) => {
+            console_error!("Error getting D1 database: {}", e);
+            return Err(e);
+        }
+    };
+
+    let result = d1.prepare("DELETE FROM subscriptions WHERE id = ?")
+        .bind(&[(id as i32).into()])?
+        .run()
+        .await?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or_default() > 0)
+}
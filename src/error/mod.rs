@@ -0,0 +1,132 @@
+use std::fmt::Display;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use worker::*;
+
+/// Result of a route handler, errors are rendered as a JSON error envelope
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// Machine readable kind of an error, each maps to one HTTP status code
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A query or path parameter is missing or can't be parsed
+    InvalidParameter,
+    /// The request body isn't valid
+    InvalidBody,
+    /// The gym in a request body isn't in the configured list
+    UnknownGym,
+    Unauthorized,
+    NotFound,
+    /// Only a WebSocket upgrade is accepted
+    UpgradeRequired,
+    /// A feature that isn't configured on this deployment
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::InvalidParameter | ErrorCode::InvalidBody | ErrorCode::UnknownGym => 400,
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::NotFound => 404,
+            ErrorCode::UpgradeRequired => 426,
+            ErrorCode::Internal => 500,
+            ErrorCode::Unavailable => 503,
+        }
+    }
+}
+
+/// An error answered to the client
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// Human readable, in English
+    pub message: String,
+    /// Structured context, e.g. the offending parameter
+    pub details: Option<Value>,
+}
+
+/// Body of every error response
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct ErrorEnvelope {
+    pub error: ApiError,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// A parameter value that can't be parsed, `expected` describes the accepted values
+    pub fn invalid_parameter(name: &str, value: &str, expected: &str) -> Self {
+        ApiError::new(ErrorCode::InvalidParameter, format!("Invalid '{}' parameter, expected {}", name, expected))
+            .with_details(json!({
+                "parameter": name,
+                "value": value,
+                "expected": expected
+            }))
+    }
+
+    pub fn missing_parameter(name: &str) -> Self {
+        ApiError::new(ErrorCode::InvalidParameter, format!("Missing '{}' parameter", name))
+            .with_details(json!({
+                "parameter": name
+            }))
+    }
+
+    pub fn unknown_gym(gym: &str) -> Self {
+        ApiError::new(ErrorCode::UnknownGym, format!("Website not in configured list: {}", gym))
+            .with_details(json!({
+                "gym": gym
+            }))
+    }
+
+    pub fn invalid_body(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::InvalidBody, message)
+    }
+
+    pub fn unauthorized() -> Self {
+        ApiError::new(ErrorCode::Unauthorized, "Unauthorized")
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::NotFound, message)
+    }
+
+    /// A failure on our side, logged together with what was being done
+    ///
+    /// Clients only get a generic message, the details can contain SQL and D1 internals.
+    pub fn internal(context: &str, error: impl Display) -> Self {
+        console_error!("{}: {}", context, error);
+        ApiError::new(ErrorCode::Internal, "Internal server error")
+    }
+
+    pub fn status(&self) -> u16 {
+        self.code.status()
+    }
+
+    pub fn into_response(self) -> Result<Response> {
+        let status = self.status();
+        Ok(Response::from_json(&ErrorEnvelope { error: self })?.with_status(status))
+    }
+}
+
+/// Errors passed up with `?` are unexpected, they become 500s
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        ApiError::internal("Internal error", error)
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::NaiveDateTime;
+use worker::Url;
+
+use crate::i18n::{self, Language};
+use crate::utils;
+
+/// Minutes without a reading during opening hours from which a gap counts as an outage
+pub const OUTAGE_MIN_MINUTES: i64 = 120;
+
+/// Anomalies of the same kind at most this far apart are reported as one period
+pub const ANOMALY_PERIOD_GAP_MINUTES: i64 = 30;
+
+/// Kinds of notable events published in the feed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    UnusuallyLow,
+    UnusuallyHigh,
+    Outage,
+    NewPeak,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::UnusuallyLow => "unusually_low",
+            EventKind::UnusuallyHigh => "unusually_high",
+            EventKind::Outage => "outage",
+            EventKind::NewPeak => "new_peak",
+        }
+    }
+
+    /// Maps the kind stored in the anomalies table
+    pub fn from_anomaly(kind: &str) -> Option<Self> {
+        match kind {
+            "unusually_low" => Some(EventKind::UnusuallyLow),
+            "unusually_high" => Some(EventKind::UnusuallyHigh),
+            _ => None,
+        }
+    }
+}
+
+/// Something worth telling subscribers about, times in UTC
+#[derive(Debug, Clone)]
+pub struct NotableEvent {
+    pub kind: EventKind,
+    pub gym_slug: String,
+    pub website_url: String,
+    pub website_name: String,
+    pub start: NaiveDateTime,
+    /// Last reading of a period, for new peaks the time of the highest one that day
+    pub end: NaiveDateTime,
+    /// Most extreme level of an anomaly period, or the highest new peak of the day
+    pub percentage: Option<f64>,
+    /// Typical level during an anomaly period
+    pub expected_percentage: Option<f64>,
+}
+
+impl NotableEvent {
+    /// Stable entry id, an event keeps its id while it grows (e.g. an ongoing outage)
+    pub fn id(&self, authority: &str) -> String {
+        let gym = Url::parse(&self.website_url).ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_else(|| self.website_name.replace(' ', "-"));
+
+        format!(
+            "tag:{},{}:{}/{}/{}",
+            authority,
+            self.start.format("%Y-%m-%d"),
+            gym,
+            self.kind.as_str(),
+            self.start.format("%Y%m%dT%H%M%SZ")
+        )
+    }
+
+    fn title(&self, language: Language) -> String {
+        let key = match self.kind {
+            EventKind::UnusuallyLow => "feed.unusually_low",
+            EventKind::UnusuallyHigh => "feed.unusually_high",
+            EventKind::Outage => "feed.outage",
+            EventKind::NewPeak => "feed.new_peak",
+        };
+        i18n::text(language, key).replacen("{}", &self.website_name, 1)
+    }
+
+    fn summary(&self, language: Language) -> String {
+        let local = |time: NaiveDateTime| utils::utc_to_local(time).format("%Y-%m-%d %H:%M").to_string();
+        let percentage = self.percentage.map(|value| value.round().to_string()).unwrap_or_default();
+
+        match self.kind {
+            EventKind::UnusuallyLow | EventKind::UnusuallyHigh => i18n::text(language, "feed.anomaly_summary")
+                .replacen("{}", &local(self.start), 1)
+                .replacen("{}", &local(self.end), 1)
+                .replacen("{}", &percentage, 1)
+                .replacen("{}", &self.expected_percentage.map(|value| value.round().to_string()).unwrap_or_default(), 1),
+            EventKind::Outage => i18n::text(language, "feed.outage_summary")
+                .replacen("{}", &local(self.start), 1)
+                .replacen("{}", &local(self.end), 1)
+                .replacen("{}", &(self.end - self.start).num_minutes().to_string(), 1),
+            EventKind::NewPeak => i18n::text(language, "feed.new_peak_summary")
+                .replacen("{}", &percentage, 1)
+                .replacen("{}", &local(self.end), 1),
+        }
+    }
+}
+
+/// Renders the events as an Atom feed (RFC 4287)
+///
+/// `origin` is the public origin of the worker, used for links and the entry ids.
+pub fn render_atom(events: &[NotableEvent], self_url: &str, origin: &str, language: Language, now: NaiveDateTime) -> String {
+    let authority = Url::parse(origin).ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+        .unwrap_or_else(|| "localhost".to_string());
+    let updated = events.iter().map(|event| event.end).max().unwrap_or(now);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str(&format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n", language.code()));
+    xml.push_str(&format!("  <id>{}</id>\n", escape_xml(self_url)));
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(i18n::text(language, "feed.title"))));
+    xml.push_str(&format!("  <updated>{}</updated>\n", format_rfc3339(updated)));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", escape_xml(self_url)));
+    xml.push_str(&format!("  <link rel=\"alternate\" href=\"{}/graph\"/>\n", escape_xml(origin)));
+    xml.push_str("  <author><name>Boulderwelt Crowd Level Scraper</name></author>\n");
+
+    for event in events {
+        let mut link = format!("{}/graph", origin);
+        if let Ok(mut url) = Url::parse(&link) {
+            url.query_pairs_mut()
+                .append_pair("url", &event.gym_slug)
+                .append_pair("days", "1");
+            link = url.to_string();
+        }
+
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", escape_xml(&event.id(&authority))));
+        xml.push_str(&format!("    <title>{}</title>\n", escape_xml(&event.title(language))));
+        xml.push_str(&format!("    <published>{}</published>\n", format_rfc3339(event.start)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", format_rfc3339(event.end)));
+        xml.push_str(&format!("    <link rel=\"alternate\" href=\"{}\"/>\n", escape_xml(&link)));
+        xml.push_str(&format!("    <category term=\"{}\"/>\n", event.kind.as_str()));
+        xml.push_str(&format!("    <summary>{}</summary>\n", escape_xml(&event.summary(language))));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn format_rfc3339(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn escape_xml(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+use worker::*;
+
+use crate::api::{self, Gym, Page, Reading, TimeAverage};
+use crate::caching;
+use crate::db;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::params::{self, check_range, FromQuery, GymFilter, Timestamp};
+use crate::i18n::Language;
+use crate::scraper;
+use crate::utils;
+
+/// Handler for GET /api/v1/gyms - the configured gyms
+pub async fn gyms_handler(_req: Request, _env: Env) -> ApiResult<Response> {
+    let local_now = utils::utc_to_local(utils::now_utc());
+    let gyms: Vec<Gym> = scraper::get_configured_websites().iter()
+        .map(|website| Gym::from_config(website, local_now))
+        .collect();
+
+    Ok(Response::from_json(&Page::complete(gyms))?)
+}
+
+/// Query parameters selecting at most one gym
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GymQuery {
+    gym: GymFilter,
+}
+
+impl FromQuery for GymQuery {}
+
+fn default_page_limit() -> usize {
+    api::DEFAULT_PAGE_LIMIT
+}
+
+#[derive(Deserialize)]
+struct ReadingsQuery {
+    #[serde(default)]
+    gym: GymFilter,
+    #[serde(default)]
+    since: Option<Timestamp>,
+    #[serde(default)]
+    until: Option<Timestamp>,
+    #[serde(default = "default_page_limit")]
+    limit: usize,
+    /// The id of the last reading of the previous page
+    #[serde(default)]
+    cursor: Option<i64>,
+    #[serde(default)]
+    include_flagged: bool,
+}
+
+impl FromQuery for ReadingsQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("limit", self.limit, 1..=api::MAX_PAGE_LIMIT)
+    }
+}
+
+/// Handler for GET /api/v1/readings - stored readings, newest first, paged with `limit` and `cursor`
+pub async fn readings_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: ReadingsQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    let since_timestamp = query.since.map(|since| since.0);
+    let until_timestamp = query.until.map(|until| until.0);
+    let limit = query.limit;
+
+    let state = match db::get_readings_state(&env, query.gym.url(), since_timestamp, until_timestamp, query.include_flagged, query.cursor).await {
+        Ok(state) => state,
+        Err(e) => return Err(ApiError::internal("Error retrieving readings", e)),
+    };
+    let freshness = caching::Freshness::new(&req, &state, caching::seconds_until_new_readings(utils::now_utc(), &query.gym.websites()))?;
+    if let Some(response) = freshness.not_modified(&req)? {
+        return Ok(response);
+    }
+
+    let rows = match db::get_readings(&env, query.gym.url(), since_timestamp, until_timestamp, query.include_flagged, query.cursor, Some(limit)).await {
+        Ok(rows) => rows,
+        Err(e) => return Err(ApiError::internal("Error retrieving readings", e)),
+    };
+
+    // A full page may be followed by another one
+    let next_cursor = if rows.len() == limit {
+        rows.last().map(|row| row.id.to_string())
+    } else {
+        None
+    };
+
+    let websites = scraper::get_configured_websites();
+    let page = Page {
+        data: rows.iter().map(|row| Reading::from_row(row, &websites, language)).collect(),
+        next_cursor,
+    };
+
+    let mut response = Response::from_json(&page)?;
+    freshness.apply(&mut response)?;
+    Ok(response)
+}
+
+/// Handler for GET /api/v1/readings/latest - the newest reading of every gym
+pub async fn latest_readings_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: GymQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    match db::get_latest_readings(&env, query.gym.url()).await {
+        Ok(rows) => {
+            let websites = scraper::get_configured_websites();
+            let readings: Vec<Reading> = rows.iter().map(|row| Reading::from_row(row, &websites, language)).collect();
+            Ok(Response::from_json(&Page::complete(readings))?)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving latest readings", e)),
+    }
+}
+
+/// Handler for GET /api/v1/time-averages - the typical level per gym, weekday and hour
+pub async fn time_averages_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: GymQuery = params::extract(&req)?;
+
+    let state = match db::get_time_averages_state(&env, query.gym.url()).await {
+        Ok(state) => state,
+        Err(e) => return Err(ApiError::internal("Error retrieving time averages", e)),
+    };
+    let freshness = caching::Freshness::new(&req, &state, caching::seconds_until_next_averages_update(utils::now_utc()))?;
+    if let Some(response) = freshness.not_modified(&req)? {
+        return Ok(response);
+    }
+
+    match db::get_time_average_rows(&env, query.gym.url()).await {
+        Ok(rows) => {
+            let averages: Vec<TimeAverage> = rows.iter().filter_map(TimeAverage::from_row).collect();
+
+            let mut response = Response::from_json(&Page::complete(averages))?;
+            // Averages are recalculated by the midnight job
+            freshness.apply(&mut response)?;
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving time averages", e)),
+    }
+}
@@ -0,0 +1,273 @@
+use crate::i18n::{self, Language};
+use crate::scraper::WebsiteConfig;
+
+/// Generate HTML for the gym comparison view
+pub fn generate_html(websites: &[WebsiteConfig], language: Language) -> String {
+    let t = |key| i18n::text(language, key);
+
+    // Generate a checkbox per gym, all selected by default
+    let mut gym_checkboxes = String::new();
+    for website in websites {
+        gym_checkboxes.push_str(&format!(
+            "<label><input type=\"checkbox\" class=\"gym-checkbox\" value=\"{}\" checked> {}</label>",
+            website.slug,
+            website.name
+        ));
+    }
+
+    // Build the HTML
+    format!(r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+    <style>
+        body {{
+            font-family: Arial, sans-serif;
+            margin: 0;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+        .container {{
+            max-width: 1200px;
+            margin: 0 auto;
+            background-color: white;
+            padding: 20px;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+        }}
+        .nav-bar {{
+            display: flex;
+            justify-content: center;
+            gap: 20px;
+            margin-bottom: 20px;
+            padding: 10px;
+            border-bottom: 1px solid #ddd;
+        }}
+        .nav-link {{
+            text-decoration: none;
+            color: #4CAF50;
+            padding: 5px 10px;
+            border-radius: 4px;
+            transition: background-color 0.2s;
+        }}
+        .nav-link.active {{
+            background-color: #4CAF50;
+            color: white;
+        }}
+        .nav-link:hover:not(.active) {{
+            background-color: #e8f5e9;
+        }}
+        h1 {{
+            color: #333;
+            text-align: center;
+            margin-bottom: 30px;
+        }}
+        .controls {{
+            display: flex;
+            justify-content: space-between;
+            flex-wrap: wrap;
+            margin-bottom: 20px;
+            gap: 10px;
+        }}
+        .controls label {{
+            margin-right: 10px;
+        }}
+        input[type="time"], button {{
+            padding: 8px 12px;
+            border-radius: 4px;
+            border: 1px solid #ddd;
+        }}
+        button {{
+            background-color: #4CAF50;
+            color: white;
+            border: none;
+            cursor: pointer;
+        }}
+        button:hover {{
+            background-color: #45a049;
+        }}
+        .gym-list {{
+            display: flex;
+            flex-direction: column;
+            gap: 10px;
+        }}
+        .gym-card {{
+            display: flex;
+            justify-content: space-between;
+            align-items: center;
+            padding: 15px;
+            border: 2px solid #ddd;
+            border-radius: 8px;
+        }}
+        .gym-card.best {{
+            border-color: #4CAF50;
+            background-color: #e8f5e9;
+        }}
+        .gym-card.closed {{
+            opacity: 0.6;
+        }}
+        .gym-name {{
+            font-size: 1.1rem;
+            font-weight: bold;
+        }}
+        .gym-status {{
+            color: #666;
+            font-size: 0.9rem;
+        }}
+        .badge {{
+            display: inline-block;
+            margin-left: 10px;
+            padding: 2px 8px;
+            border-radius: 4px;
+            background-color: #4CAF50;
+            color: white;
+            font-size: 0.8rem;
+        }}
+        .levels {{
+            display: flex;
+            gap: 20px;
+            text-align: center;
+        }}
+        .level-value {{
+            font-size: 1.4rem;
+            font-weight: bold;
+        }}
+        .level-label {{
+            color: #666;
+            font-size: 0.8rem;
+        }}
+        .empty {{
+            text-align: center;
+            color: #666;
+        }}
+        @media (max-width: 768px) {{
+            .controls, .gym-card {{
+                flex-direction: column;
+            }}
+        }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        {nav_bar}
+
+        <h1>{title}</h1>
+
+        <div class="controls">
+            <div>
+                {gym_checkboxes}
+            </div>
+            <div>
+                <label for="atTime">{label_at}</label>
+                <input type="time" id="atTime">
+                <button onclick="document.getElementById('atTime').value = ''; loadComparison()">{button_now}</button>
+                <button onclick="loadComparison()">{button_compare}</button>
+            </div>
+        </div>
+
+        <div class="gym-list" id="gymList">
+            <p class="empty">{status_loading}</p>
+        </div>
+    </div>
+
+    <script>
+        function formatLevel(value) {{
+            return value === null || value === undefined ? '–' : Math.round(value) + '%';
+        }}
+
+        function renderGym(gym, isBest) {{
+            const classes = ['gym-card'];
+            if (isBest) classes.push('best');
+            if (!gym.open) classes.push('closed');
+
+            return `
+                <div class="${{classes.join(' ')}}">
+                    <div>
+                        <div class="gym-name">
+                            ${{gym.rank}}. ${{gym.location}}
+                            ${{isBest ? '<span class="badge">{least_crowded}</span>' : ''}}
+                        </div>
+                        <div class="gym-status">${{gym.opening_status.message}}</div>
+                        <a href="/graph?url=${{encodeURIComponent(gym.slug)}}&days=1&lang={lang}">{show_graph}</a>
+                    </div>
+                    <div class="levels">
+                        <div>
+                            <div class="level-value">${{formatLevel(gym.expected_percentage)}}</div>
+                            <div class="level-label">{level_now}</div>
+                        </div>
+                        <div>
+                            <div class="level-value">${{formatLevel(gym.typical_percentage)}}</div>
+                            <div class="level-label">{level_typical}</div>
+                        </div>
+                        <div>
+                            <div class="level-value">${{formatLevel(gym.forecast_percentage)}}</div>
+                            <div class="level-label">{level_in_one_hour}</div>
+                        </div>
+                    </div>
+                </div>`;
+        }}
+
+        async function loadComparison() {{
+            const gymList = document.getElementById('gymList');
+            const gyms = Array.from(document.querySelectorAll('.gym-checkbox'))
+                .filter(checkbox => checkbox.checked)
+                .map(checkbox => checkbox.value);
+
+            if (gyms.length === 0) {{
+                gymList.innerHTML = '<p class="empty">{status_select_gym}</p>';
+                return;
+            }}
+
+            const atTime = document.getElementById('atTime').value;
+            let url = '/compare?lang={lang}&gyms=' + encodeURIComponent(gyms.join(','));
+            if (atTime) {{
+                url += '&at=' + encodeURIComponent(atTime);
+            }}
+
+            try {{
+                const response = await fetch(url);
+                const result = await response.json();
+
+                if (!result.gyms || !Array.isArray(result.gyms)) {{
+                    throw new Error('Invalid data received from server');
+                }}
+
+                gymList.innerHTML = result.gyms
+                    .map(gym => renderGym(gym, gym.website_url === result.least_crowded))
+                    .join('');
+            }} catch (error) {{
+                console.error('Error loading comparison:', error);
+                gymList.innerHTML = '<p class="empty">{status_error}</p>';
+            }}
+        }}
+
+        document.querySelectorAll('.gym-checkbox').forEach(checkbox => {{
+            checkbox.addEventListener('change', loadComparison);
+        }});
+
+        // Load the comparison when page loads
+        document.addEventListener('DOMContentLoaded', function() {{
+            loadComparison();
+        }});
+    </script>
+</body>
+</html>"#,
+        gym_checkboxes = gym_checkboxes,
+        lang = language.code(),
+        nav_bar = i18n::nav_bar(language, "compare"),
+        title = t("compare.title"),
+        label_at = t("label.at"),
+        button_now = t("button.now"),
+        button_compare = t("button.compare"),
+        status_loading = t("status.loading"),
+        status_select_gym = t("status.select_gym"),
+        status_error = t("status.error"),
+        least_crowded = t("compare.least_crowded"),
+        show_graph = t("compare.show_graph"),
+        level_now = t("compare.now"),
+        level_typical = t("compare.typical"),
+        level_in_one_hour = t("compare.in_one_hour")
+    )
+}
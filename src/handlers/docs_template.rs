@@ -0,0 +1,366 @@
+/// Interactive API reference, renders /openapi.json and lets readers try the endpoints
+///
+/// Self-contained on purpose, the page works without any external scripts.
+pub const HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>API Reference - Boulderwelt Crowd Level Scraper</title>
+    <style>
+        body {
+            font-family: Arial, sans-serif;
+            margin: 0;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }
+        .container {
+            max-width: 1200px;
+            margin: 0 auto;
+            background-color: white;
+            padding: 20px;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+        }
+        h1 {
+            color: #333;
+            text-align: center;
+            margin-bottom: 10px;
+        }
+        .intro {
+            text-align: center;
+            color: #666;
+            margin-bottom: 20px;
+        }
+        .token {
+            display: flex;
+            justify-content: flex-end;
+            gap: 10px;
+            align-items: center;
+            margin-bottom: 20px;
+        }
+        h2 {
+            color: #4CAF50;
+            border-bottom: 1px solid #ddd;
+            padding-bottom: 5px;
+            margin-top: 30px;
+        }
+        details.operation {
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            margin-bottom: 8px;
+        }
+        details.operation.deprecated summary .path {
+            text-decoration: line-through;
+        }
+        details.operation summary {
+            cursor: pointer;
+            padding: 8px 12px;
+            display: flex;
+            gap: 12px;
+            align-items: center;
+        }
+        .method {
+            display: inline-block;
+            min-width: 60px;
+            text-align: center;
+            padding: 3px 6px;
+            border-radius: 4px;
+            color: white;
+            font-weight: bold;
+            font-size: 12px;
+        }
+        .method.get { background-color: #4CAF50; }
+        .method.post { background-color: #2196F3; }
+        .method.put { background-color: #FF9800; }
+        .method.delete { background-color: #F44336; }
+        .path {
+            font-family: monospace;
+            font-size: 14px;
+        }
+        .summary {
+            color: #666;
+        }
+        .body {
+            padding: 0 12px 12px;
+        }
+        table {
+            border-collapse: collapse;
+            width: 100%;
+            margin-bottom: 10px;
+        }
+        th, td {
+            text-align: left;
+            padding: 6px;
+            border-bottom: 1px solid #eee;
+            vertical-align: top;
+        }
+        input[type="text"], textarea {
+            padding: 6px;
+            border-radius: 4px;
+            border: 1px solid #ddd;
+            width: 100%;
+            box-sizing: border-box;
+            font-family: monospace;
+        }
+        textarea {
+            min-height: 100px;
+        }
+        button {
+            padding: 8px 12px;
+            border-radius: 4px;
+            border: none;
+            background-color: #4CAF50;
+            color: white;
+            cursor: pointer;
+        }
+        pre {
+            background-color: #f5f5f5;
+            padding: 10px;
+            border-radius: 4px;
+            overflow: auto;
+            max-height: 400px;
+            font-size: 12px;
+        }
+        .note {
+            color: #666;
+            font-style: italic;
+        }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1 id="title">API Reference</h1>
+        <p class="intro" id="intro"></p>
+        <div class="token">
+            <label for="token">Admin token</label>
+            <input type="text" id="token" placeholder="only needed for /admin routes" style="max-width: 300px;">
+        </div>
+        <div id="operations">Loading /openapi.json ...</div>
+    </div>
+
+    <script>
+        let spec = null;
+
+        function element(tag, attributes, children) {
+            const node = document.createElement(tag);
+            Object.entries(attributes || {}).forEach(([key, value]) => {
+                if (key === 'text') {
+                    node.textContent = value;
+                } else {
+                    node.setAttribute(key, value);
+                }
+            });
+            (children || []).forEach(child => node.appendChild(child));
+            return node;
+        }
+
+        // Replaces references to the components by the referenced schemas, once per schema
+        function resolve(schema, seen) {
+            if (Array.isArray(schema)) {
+                return schema.map(item => resolve(item, seen));
+            }
+            if (!schema || typeof schema !== 'object') {
+                return schema;
+            }
+            if (schema.$ref) {
+                const name = schema.$ref.split('/').pop();
+                if (seen.includes(name)) {
+                    return { $ref: name };
+                }
+                return resolve(spec.components.schemas[name], seen.concat([name]));
+            }
+            const resolved = {};
+            Object.entries(schema).forEach(([key, value]) => {
+                resolved[key] = resolve(value, seen);
+            });
+            return resolved;
+        }
+
+        // Builds an example value from a schema, used to prefill request bodies
+        function example(schema) {
+            schema = resolve(schema, []);
+            if (!schema) {
+                return null;
+            }
+            if (schema.default !== undefined) {
+                return schema.default;
+            }
+            if (schema.enum) {
+                return schema.enum[0];
+            }
+            if (schema.oneOf || schema.anyOf) {
+                return example((schema.oneOf || schema.anyOf)[0]);
+            }
+            const type = Array.isArray(schema.type) ? schema.type.find(t => t !== 'null') : schema.type;
+            switch (type) {
+                case 'object': {
+                    const value = {};
+                    Object.entries(schema.properties || {}).forEach(([key, property]) => {
+                        if ((schema.required || []).includes(key)) {
+                            value[key] = example(property);
+                        }
+                    });
+                    return value;
+                }
+                case 'array': return [];
+                case 'integer': return 0;
+                case 'number': return 0;
+                case 'boolean': return false;
+                case 'string': return '';
+                default: return null;
+            }
+        }
+
+        function schemaBlock(title, schema) {
+            return element('details', {}, [
+                element('summary', { text: title }),
+                element('pre', { text: JSON.stringify(resolve(schema, []), null, 2) })
+            ]);
+        }
+
+        function renderOperation(path, method, operation) {
+            const classes = 'operation' + (operation.deprecated ? ' deprecated' : '');
+            const body = element('div', { class: 'body' });
+            const inputs = {};
+
+            const parameters = operation.parameters || [];
+            if (parameters.length > 0) {
+                const rows = parameters.map(parameter => {
+                    const input = element('input', { type: 'text', placeholder: parameter.schema.type });
+                    inputs[parameter.name] = { parameter, input };
+                    return element('tr', {}, [
+                        element('td', {}, [element('code', { text: parameter.name + (parameter.required ? ' *' : '') })]),
+                        element('td', { text: parameter.in }),
+                        element('td', { text: parameter.description || '' }),
+                        element('td', {}, [input])
+                    ]);
+                });
+                body.appendChild(element('table', {}, [
+                    element('tr', {}, ['Name', 'In', 'Description', 'Value'].map(text => element('th', { text })))
+                ].concat(rows)));
+            }
+
+            let requestBody = null;
+            if (operation.requestBody) {
+                const schema = operation.requestBody.content['application/json'].schema;
+                body.appendChild(schemaBlock('Request body schema', schema));
+                requestBody = element('textarea', {});
+                requestBody.value = JSON.stringify(example(schema), null, 2);
+                body.appendChild(requestBody);
+            }
+
+            Object.entries(operation.responses).forEach(([status, response]) => {
+                const json = response.content && response.content['application/json'];
+                if (json) {
+                    body.appendChild(schemaBlock('Response ' + status + ' schema', json.schema));
+                }
+            });
+
+            const streaming = operation.responses['101'] || (operation.responses['200'] && operation.responses['200'].content
+                && operation.responses['200'].content['text/event-stream']);
+            if (streaming) {
+                body.appendChild(element('p', { class: 'note', text: 'Streaming endpoint, connect with an EventSource or WebSocket client.' }));
+            } else {
+                const output = element('pre', { text: '' });
+                const button = element('button', { text: 'Send request' });
+                button.addEventListener('click', () => send(path, method, operation, inputs, requestBody, output));
+                body.appendChild(button);
+                body.appendChild(output);
+            }
+
+            return element('details', { class: classes }, [
+                element('summary', {}, [
+                    element('span', { class: 'method ' + method, text: method.toUpperCase() }),
+                    element('span', { class: 'path', text: path }),
+                    element('span', { class: 'summary', text: operation.summary || '' })
+                ]),
+                body
+            ]);
+        }
+
+        async function send(path, method, operation, inputs, requestBody, output) {
+            let url = path;
+            const query = new URLSearchParams();
+            const headers = {};
+            for (const { parameter, input } of Object.values(inputs)) {
+                if (input.value === '') {
+                    continue;
+                }
+                if (parameter.in === 'path') {
+                    url = url.replace('{' + parameter.name + '}', encodeURIComponent(input.value));
+                } else if (parameter.in === 'header') {
+                    headers[parameter.name] = input.value;
+                } else {
+                    query.append(parameter.name, input.value);
+                }
+            }
+            if ([...query].length > 0) {
+                url += '?' + query.toString();
+            }
+
+            const token = document.getElementById('token').value;
+            if (operation.security && token) {
+                headers['Authorization'] = 'Bearer ' + token;
+            }
+
+            const init = { method: method.toUpperCase(), headers };
+            if (requestBody) {
+                headers['Content-Type'] = 'application/json';
+                init.body = requestBody.value;
+            }
+
+            output.textContent = init.method + ' ' + url + ' ...';
+            try {
+                const response = await fetch(url, init);
+                const text = await response.text();
+                let content = text;
+                if ((response.headers.get('Content-Type') || '').includes('json')) {
+                    try {
+                        content = JSON.stringify(JSON.parse(text), null, 2);
+                    } catch (e) {
+                        // Not valid JSON after all, show the raw text
+                    }
+                }
+                output.textContent = init.method + ' ' + url + '\n' + response.status + ' ' + response.statusText + '\n\n' + content;
+            } catch (error) {
+                output.textContent = 'Request failed: ' + error;
+            }
+        }
+
+        async function load() {
+            const container = document.getElementById('operations');
+            try {
+                spec = await (await fetch('/openapi.json')).json();
+            } catch (error) {
+                container.textContent = 'Error loading /openapi.json: ' + error;
+                return;
+            }
+
+            document.getElementById('title').textContent = spec.info.title + ' ' + spec.info.version;
+            document.getElementById('intro').textContent = spec.info.description;
+            container.textContent = '';
+
+            // Group the operations by their tag
+            const groups = new Map();
+            Object.entries(spec.paths).forEach(([path, item]) => {
+                Object.entries(item).forEach(([method, operation]) => {
+                    const tag = (operation.tags || ['Other'])[0];
+                    if (!groups.has(tag)) {
+                        groups.set(tag, []);
+                    }
+                    groups.get(tag).push(renderOperation(path, method, operation));
+                });
+            });
+
+            groups.forEach((operations, tag) => {
+                container.appendChild(element('h2', { text: tag }));
+                operations.forEach(operation => container.appendChild(operation));
+            });
+        }
+
+        document.addEventListener('DOMContentLoaded', load);
+    </script>
+</body>
+</html>
+"#;
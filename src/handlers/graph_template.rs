@@ -1,7 +1,11 @@
+use crate::analysis;
+use crate::i18n::{self, Language};
 use crate::scraper::WebsiteConfig;
 
-/// Generate HTML for the graph visualization
-pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>, days: u32) -> String {
+/// Generate HTML for the graph visualization, `selected_website` is the slug of the selected gym
+pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>, days: u32, language: Language) -> String {
+    let t = |key| i18n::text(language, key);
+
     // Generate website options HTML
     let mut website_options = String::new();
 
@@ -10,40 +14,42 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
     if selected_website.is_none() || selected_website == Some("all") {
         website_options.push_str("selected");
     }
-    website_options.push_str(">All Gyms</option>");
+    website_options.push_str(&format!(">{}</option>", t("option.all_gyms")));
 
     for website in websites {
-        let selected = selected_website.is_some_and(|s| s == website.url);
+        let selected = selected_website.is_some_and(|s| s == website.slug);
         website_options.push_str(&format!(
             "<option value=\"{}\" {}>{}</option>",
-            website.url,
+            website.slug,
             if selected { "selected" } else { "" },
             website.name
         ));
     }
 
     // Generate time range options HTML
-    let time_options = [(1, "1 Day"), (3, "3 Days"), (7, "7 Days"), (14, "14 Days"), (30, "30 Days")];
+    let time_options = [1, 3, 7, 14, 30];
     let mut time_options_html = String::new();
-    for &(value, label) in &time_options {
+    for &value in &time_options {
         time_options_html.push_str(&format!(
-            "<option value=\"{}\" {}>{}</option>",
+            "<option value=\"{}\" {}>{} {}</option>",
             value,
             if value == days { "selected" } else { "" },
-            label
+            value,
+            if value == 1 { t("option.day") } else { t("option.days") }
         ));
     }
 
     // Build the HTML
     format!(r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{lang}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Climbing Gym Crowd Levels</title>
+    <title>{title}</title>
     <script src="https://cdn.jsdelivr.net/npm/chart.js"></script>
     <script src="https://cdn.jsdelivr.net/npm/chartjs-adapter-date-fns"></script>
     <script src="https://cdn.jsdelivr.net/npm/chartjs-plugin-zoom"></script>
+    <script src="https://cdn.jsdelivr.net/npm/chartjs-plugin-annotation"></script>
     <style>
         body {{
             font-family: Arial, sans-serif;
@@ -146,6 +152,20 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
             0% {{ transform: rotate(0deg); }}
             100% {{ transform: rotate(360deg); }}
         }}
+        .push-controls {{
+            display: none;
+            justify-content: center;
+            align-items: center;
+            gap: 10px;
+            margin-top: 10px;
+            font-size: 0.9rem;
+        }}
+        .push-controls input {{
+            width: 60px;
+            padding: 6px;
+            border-radius: 4px;
+            border: 1px solid #ddd;
+        }}
         @media (max-width: 768px) {{
             .controls {{
                 flex-direction: column;
@@ -155,22 +175,19 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
 </head>
 <body>
     <div class="container">
-        <div class="nav-bar">
-            <a href="/graph?url=all&days=1&offset=0" class="nav-link active">Live Graph</a>
-            <a href="/time-averages-view" class="nav-link">Time Averages</a>
-        </div>
+        {nav_bar}
 
-        <h1>Climbing Gym Crowd Levels</h1>
+        <h1>{title}</h1>
 
         <div class="controls">
             <div>
-                <label for="website">Gym Location:</label>
+                <label for="website">{label_gym}</label>
                 <select id="website">
                     {website_options}
                 </select>
             </div>
             <div>
-                <label for="timeRange">Time Range:</label>
+                <label for="timeRange">{label_time_range}</label>
                 <select id="timeRange">
                     {time_options_html}
                 </select>
@@ -178,20 +195,30 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
             <div>
                 <label for="showOffset">
                     <input type="checkbox" id="showOffset" onchange="loadData()">
-                    Compare with last week
+                    {label_compare_last_week}
                 </label>
             </div>
-            <button onclick="loadData()">Update Graph</button>
-            <button id="resetZoom" onclick="resetZoom()" style="display: none;">Reset Zoom</button>
+            <button onclick="loadData()">{button_update_graph}</button>
+            <button id="resetZoom" onclick="resetZoom()" style="display: none;">{button_reset_zoom}</button>
         </div>
         <div style="text-align: center; margin-top: 5px; font-size: 0.9rem; color: #666;">
-            <p>Tip: Click and drag on the graph to zoom into a specific area</p>
+            <p>{tip_zoom}</p>
+        </div>
+        <div class="push-controls" id="pushControls">
+            <label for="pushDirection">{push_notify_me}</label>
+            <select id="pushDirection">
+                <option value="below">{direction_below}</option>
+                <option value="above">{direction_above}</option>
+            </select>
+            <input type="number" id="pushThreshold" min="0" max="100" value="30">%
+            <button onclick="subscribePush()">{push_subscribe}</button>
+            <button onclick="unsubscribePush()">{push_unsubscribe}</button>
         </div>
 
         <div class="chart-wrapper">
             <div class="loading-overlay" id="loadingOverlay">
                 <div class="spinner"></div>
-                <span>Loading data...</span>
+                <span>{status_loading}</span>
             </div>
             <div class="chart-container">
                 <canvas id="crowdChart"></canvas>
@@ -208,18 +235,139 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
         // Chart reference
         let chart = null;
 
+        // Readings further apart than this are not connected by the line
+        const gapThresholdMinutes = {gap_threshold_minutes};
+
+        // Inserts an empty point between readings that are too far apart,
+        // so Chart.js breaks the line instead of drawing across missing hours
+        function breakAtGaps(points) {{
+            const result = [];
+            points.forEach((point, index) => {{
+                const previous = points[index - 1];
+                if (previous && point.x - previous.x > gapThresholdMinutes * 60 * 1000) {{
+                    result.push({{ x: new Date((previous.x.getTime() + point.x.getTime()) / 2), y: null }});
+                }}
+                result.push(point);
+            }});
+            return result;
+        }}
+
+        // Time range of a series, offset moves it back by that many days
+        function historyRange(days, offset = 0) {{
+            const since = new Date();
+            since.setDate(since.getDate() - days - offset);
+
+            const until = new Date();
+            until.setDate(until.getDate() - offset);
+
+            return {{ since, until }};
+        }}
+
+        // Local date (Europe/Berlin) as YYYY-MM-DD, the days of the coverage endpoint
+        function localDate(date) {{
+            return date.toLocaleDateString('sv-SE', {{ timeZone: 'Europe/Berlin' }});
+        }}
+
+        // UTC time in the database format, so shifted gaps parse like the stored ones
+        function dbTimestamp(date) {{
+            return date.toISOString().slice(0, 19).replace('T', ' ');
+        }}
+
+        // Loads the gaps in the series during opening hours, shaded in the chart
+        // Covers the same range as loadGymData, gaps of an offset series are moved forward to line up with it
+        async function loadGaps(website, days, offset = 0) {{
+            try {{
+                const {{ since, until }} = historyRange(days, offset);
+                const shift = time => {{
+                    const date = new Date(time.replace(' ', 'T') + 'Z');
+                    date.setDate(date.getDate() + offset);
+                    return dbTimestamp(date);
+                }};
+
+                const response = await fetch('/coverage?url=' + encodeURIComponent(website)
+                    + '&since=' + localDate(since) + '&until=' + localDate(until));
+                const result = await response.json();
+
+                return (result.data || [])
+                    .flatMap(gym => gym.days)
+                    .flatMap(day => day.gaps)
+                    .filter(gap => new Date(gap.end_at.replace(' ', 'T') + 'Z') > since
+                        && new Date(gap.start_at.replace(' ', 'T') + 'Z') < until)
+                    .map((gap, index) => ({{
+                        ...gap,
+                        start_at: shift(gap.start_at),
+                        end_at: shift(gap.end_at),
+                        id: 'gap' + offset + '-' + index,
+                        gap: true
+                    }}));
+            }} catch (error) {{
+                console.error('Error loading gaps:', error);
+                return [];
+            }}
+        }}
+
+        // Converts annotations from the history endpoint into chart markers,
+        // point annotations become vertical lines and ranges shaded regions
+        function buildAnnotations(annotations) {{
+            const markers = {{}};
+            annotations.forEach(annotation => {{
+                const start = new Date(annotation.start_at.replace(' ', 'T') + 'Z');
+
+                if (annotation.gap) {{
+                    markers[annotation.id] = {{
+                        type: 'box',
+                        xMin: start,
+                        xMax: new Date(annotation.end_at.replace(' ', 'T') + 'Z'),
+                        backgroundColor: 'rgba(158, 158, 158, 0.2)',
+                        borderWidth: 0
+                    }};
+                    return;
+                }}
+
+                const color = annotation.abnormal ? '255, 87, 34' : '33, 150, 243';
+                const label = {{
+                    display: true,
+                    content: annotation.title,
+                    position: 'start',
+                    font: {{ size: 11 }}
+                }};
+
+                if (annotation.end_at) {{
+                    markers['annotation' + annotation.id] = {{
+                        type: 'box',
+                        xMin: start,
+                        xMax: new Date(annotation.end_at.replace(' ', 'T') + 'Z'),
+                        backgroundColor: 'rgba(' + color + ', 0.12)',
+                        borderWidth: 0,
+                        label: {{ ...label, color: 'rgb(' + color + ')', position: {{ x: 'center', y: 'start' }} }}
+                    }};
+                }} else {{
+                    markers['annotation' + annotation.id] = {{
+                        type: 'line',
+                        xMin: start,
+                        xMax: start,
+                        borderColor: 'rgb(' + color + ')',
+                        borderWidth: 2,
+                        borderDash: [4, 4],
+                        label: {{ ...label, backgroundColor: 'rgb(' + color + ')' }}
+                    }};
+                }}
+            }});
+            return markers;
+        }}
+
         // Initialize empty chart for a single gym
-        function initChart(data, timeUnit = 'hour') {{
+        function initChart(data, timeUnit = 'hour', annotations = []) {{
             const ctx = document.getElementById('crowdChart').getContext('2d');
 
             if (chart) {{
                 chart.destroy();
             }}
 
-            // Filter out invalid data points
+            // Filter out invalid data points, empty points only mark gaps
             const validData = data.filter(point => 
                 point.x instanceof Date && !isNaN(point.x) && 
-                !isNaN(point.y) && point.y >= 0 && point.y <= 100
+                (point.y === null || (!isNaN(point.y) && point.y >= 0 && point.y <= 100))
             );
 
             if (validData.length === 0) {{
@@ -257,7 +405,7 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                             }},
                             title: {{
                                 display: true,
-                                text: 'Time'
+                                text: '{chart_time}'
                             }}
                         }},
                         y: {{
@@ -265,11 +413,14 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                             max: 100,
                             title: {{
                                 display: true,
-                                text: 'Crowd Level (%)'
+                                text: '{chart_crowd_level}'
                             }}
                         }}
                     }},
                     plugins: {{
+                        annotation: {{
+                            annotations: buildAnnotations(annotations)
+                        }},
                         tooltip: {{
                             callbacks: {{
                                 title: function(tooltipItems) {{
@@ -307,7 +458,7 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
         }}
 
         // Initialize chart for multiple gyms
-        function initMultiChart(datasets, timeUnit = 'hour') {{
+        function initMultiChart(datasets, timeUnit = 'hour', annotations = []) {{
             const ctx = document.getElementById('crowdChart').getContext('2d');
 
             if (chart) {{
@@ -339,7 +490,7 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                             }},
                             title: {{
                                 display: true,
-                                text: 'Time'
+                                text: '{chart_time}'
                             }}
                         }},
                         y: {{
@@ -347,11 +498,14 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                             max: 100,
                             title: {{
                                 display: true,
-                                text: 'Crowd Level (%)'
+                                text: '{chart_crowd_level}'
                             }}
                         }}
                     }},
                     plugins: {{
+                        annotation: {{
+                            annotations: buildAnnotations(annotations)
+                        }},
                         tooltip: {{
                             callbacks: {{
                                 title: function(tooltipItems) {{
@@ -401,22 +555,17 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
         // Load data from the API for a single gym
         async function loadGymData(website, days, offset = 0) {{
             try {{
-                // Calculate the since timestamp based on the requested days
-                const since = new Date();
-                since.setDate(since.getDate() - days - offset);
+                // Calculate the since and until timestamps based on the requested days and offset
+                const {{ since, until }} = historyRange(days, offset);
                 const sinceTimestamp = Math.floor(since.getTime() / 1000);
-
-                // Calculate the until timestamp for offset data
-                const until = new Date();
-                if (offset > 0) {{
-                    until.setDate(until.getDate() - offset);
-                }}
                 const untilTimestamp = Math.floor(until.getTime() / 1000);
 
                 // Fetch data from the history endpoint with the since parameter
                 let url = '/history?url=' + encodeURIComponent(website) + '&since=' + sinceTimestamp;
                 if (offset > 0) {{
                     url += '&until=' + untilTimestamp;
+                }} else {{
+                    url += '&include_annotations=true';
                 }}
 
                 const response = await fetch(url);
@@ -520,7 +669,8 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
 
                 // We don't need to filter by date range anymore since the server does it
                 return {{
-                    data: filteredData,
+                    data: breakAtGaps(filteredData),
+                    annotations: result.annotations || [],
                     total: newDataPoints.length,
                     filtered: filteredData.length
                 }};
@@ -528,6 +678,7 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                 console.error('Error loading data:', error);
                 return {{
                     data: [],
+                    annotations: [],
                     total: 0,
                     error: error.message
                 }};
@@ -581,6 +732,7 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
 
                         const currentDataset = {{
                             label: website.name,
+                            websiteUrl: website.url,
                             data: result[0].data,
                             borderColor: 'hsl(' + hue + ', 70%, 50%)',
                             backgroundColor: 'hsla(' + hue + ', 70%, 50%, 0.1)',
@@ -621,23 +773,36 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                         return [currentDataset];
                     }});
 
+                    // Annotations for all gyms are returned once per gym
+                    const annotations = [...new Map(results
+                        .flatMap(result => result[0].annotations)
+                        .map(annotation => [annotation.id, annotation])).values()];
+
                     // Initialize chart with multiple datasets
-                    initMultiChart(datasets, timeUnit);
+                    initMultiChart(datasets, timeUnit, annotations);
 
                     hideLoading();
                 }} catch (error) {{
                     console.error('Error loading data for all gyms:', error);
                     hideLoading();
-                    alert('Error loading data. Please try again.');
+                    alert('{status_error}');
                 }}
             }} else {{
                 // Fetch data for a single gym
                 try {{
-                    const currentData = await loadGymData(website, days);
+                    const [currentData, gaps] = await Promise.all([
+                        loadGymData(website, days),
+                        loadGaps(website, days)
+                    ]);
+                    const annotations = [...currentData.annotations, ...gaps];
                     let datasets = [];
 
                     if (showOffset) {{
-                        const offsetData = await loadGymData(website, days, 7);
+                        const [offsetData, offsetGaps] = await Promise.all([
+                            loadGymData(website, days, 7),
+                            loadGaps(website, days, 7)
+                        ]);
+                        annotations.push(...offsetGaps);
 
                         // Adjust timestamps for offset data to align with current data
                         const adjustedOffsetData = offsetData.data.map(point => {{
@@ -674,16 +839,16 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
                                 pointHoverRadius: 5
                             }}
                         ];
-                        initMultiChart(datasets, timeUnit);
+                        initMultiChart(datasets, timeUnit, annotations);
                     }} else {{
-                        initChart(currentData.data, timeUnit);
+                        initChart(currentData.data, timeUnit, annotations);
                     }}
 
                     hideLoading();
                 }} catch (error) {{
                     console.error('Error loading data:', error);
                     hideLoading();
-                    alert('Error loading data. Please try again.');
+                    alert('{status_error}');
                 }}
             }}
 
@@ -723,15 +888,167 @@ pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>,
             updateURL();
         }});
 
+        // Appends a reading pushed by /live to the gym's line without refetching the history
+        function appendLiveReading(reading) {{
+            if (!chart || reading.quality !== 'ok') {{
+                return;
+            }}
+
+            const website = document.getElementById('website').value;
+            const dataset = website === 'all'
+                ? chart.data.datasets.find(d => d.websiteUrl === reading.website_url)
+                : (website === reading.gym_slug ? chart.data.datasets[0] : null);
+            if (!dataset) {{
+                return;
+            }}
+
+            const point = {{ x: new Date(reading.created_at.replace(' ', 'T') + 'Z'), y: reading.percentage }};
+            const last = dataset.data[dataset.data.length - 1];
+            if (last && point.x <= last.x) {{
+                return;
+            }}
+
+            // Same gap handling as the initial load, the last point is already in the dataset
+            breakAtGaps(last ? [last, point] : [point])
+                .slice(last ? 1 : 0)
+                .forEach(p => dataset.data.push(p));
+            chart.update('none');
+        }}
+
+        // Subscribes to new readings, EventSource reconnects by itself when the stream drops
+        function connectLive() {{
+            if (!('EventSource' in window)) {{
+                return;
+            }}
+            const source = new EventSource('/live');
+            source.addEventListener('reading', event => appendLiveReading(JSON.parse(event.data)));
+        }}
+
+        // Converts the base64url VAPID key into the format expected by pushManager.subscribe
+        function urlBase64ToUint8Array(base64String) {{
+            const padding = '='.repeat((4 - base64String.length % 4) % 4);
+            const base64 = (base64String + padding).replace(/-/g, '+').replace(/_/g, '/');
+            return Uint8Array.from(atob(base64), c => c.charCodeAt(0));
+        }}
+
+        // Returns the service worker registration, null if the browser can't receive push messages
+        async function pushRegistration() {{
+            if (!('serviceWorker' in navigator) || !('PushManager' in window)) {{
+                return null;
+            }}
+            await navigator.serviceWorker.register('/sw.js');
+            return navigator.serviceWorker.ready;
+        }}
+
+        // Subscribes this browser to the selected gym crossing the chosen level
+        async function subscribePush() {{
+            const website = document.getElementById('website').value;
+            if (website === 'all') {{
+                alert('{push_select_gym}');
+                return;
+            }}
+
+            try {{
+                const registration = await pushRegistration();
+                if (await Notification.requestPermission() !== 'granted') {{
+                    alert('{push_denied}');
+                    return;
+                }}
+
+                let subscription = await registration.pushManager.getSubscription();
+                if (!subscription) {{
+                    const keyResponse = await fetch('/push/key');
+                    if (!keyResponse.ok) {{
+                        throw new Error('Web Push is not configured');
+                    }}
+                    const {{ public_key }} = await keyResponse.json();
+                    subscription = await registration.pushManager.subscribe({{
+                        userVisibleOnly: true,
+                        applicationServerKey: urlBase64ToUint8Array(public_key)
+                    }});
+                }}
+
+                const response = await fetch('/push/subscribe', {{
+                    method: 'POST',
+                    headers: {{ 'Content-Type': 'application/json' }},
+                    body: JSON.stringify({{
+                        website_url: website,
+                        threshold: parseFloat(document.getElementById('pushThreshold').value),
+                        direction: document.getElementById('pushDirection').value,
+                        language: '{lang}',
+                        subscription: subscription.toJSON()
+                    }})
+                }});
+                if (!response.ok) {{
+                    throw new Error(await response.text());
+                }}
+                alert('{push_subscribed}');
+            }} catch (error) {{
+                console.error('Error subscribing to notifications:', error);
+                alert('{status_error}');
+            }}
+        }}
+
+        // Stops the notifications for the selected gym, the browser subscription is kept for other gyms
+        async function unsubscribePush() {{
+            try {{
+                const registration = await pushRegistration();
+                const subscription = await registration.pushManager.getSubscription();
+                if (subscription) {{
+                    const website = document.getElementById('website').value;
+                    await fetch('/push/unsubscribe', {{
+                        method: 'POST',
+                        headers: {{ 'Content-Type': 'application/json' }},
+                        body: JSON.stringify({{
+                            endpoint: subscription.endpoint,
+                            website_url: website === 'all' ? null : website
+                        }})
+                    }});
+                }}
+                alert('{push_unsubscribed}');
+            }} catch (error) {{
+                console.error('Error unsubscribing from notifications:', error);
+                alert('{status_error}');
+            }}
+        }}
+
         // Initialize the chart when page loads
         document.addEventListener('DOMContentLoaded', function() {{
             loadData();
+            connectLive();
+
+            if ('serviceWorker' in navigator && 'PushManager' in window) {{
+                document.getElementById('pushControls').style.display = 'flex';
+            }}
         }});
     </script>
 </body>
 </html>"#,
         website_options = website_options,
         time_options_html = time_options_html,
-        days = days
+        days = days,
+        lang = language.code(),
+        nav_bar = i18n::nav_bar(language, "graph"),
+        title = t("graph.title"),
+        label_gym = t("label.gym"),
+        label_time_range = t("label.time_range"),
+        label_compare_last_week = t("label.compare_last_week"),
+        button_update_graph = t("button.update_graph"),
+        gap_threshold_minutes = analysis::DEFAULT_GAP_THRESHOLD_MINUTES,
+        button_reset_zoom = t("button.reset_zoom"),
+        tip_zoom = t("tip.zoom"),
+        status_loading = t("status.loading"),
+        status_error = t("status.error"),
+        push_notify_me = t("push.notify_me"),
+        push_subscribe = t("push.subscribe"),
+        push_unsubscribe = t("push.unsubscribe"),
+        push_select_gym = t("push.select_gym"),
+        push_denied = t("push.denied"),
+        push_subscribed = t("push.subscribed"),
+        push_unsubscribed = t("push.unsubscribed"),
+        direction_below = t("direction.below"),
+        direction_above = t("direction.above"),
+        chart_time = t("chart.time"),
+        chart_crowd_level = t("chart.crowd_level")
     )
 } 
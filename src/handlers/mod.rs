@@ -1,163 +1,1276 @@
+use chrono::{Datelike, Timelike};
 use worker::*;
+use serde::Deserialize;
 use serde_json::json;
 
+use crate::analysis;
+use crate::annotations::Annotation;
+use crate::caching;
+use crate::calendar::{self, CalendarEvent};
 use crate::db;
+use crate::error::{ApiError, ApiResult, ErrorCode};
+use crate::feed;
+use crate::holidays::{self, SchoolHoliday};
+use crate::i18n::{self, Language};
+use crate::live;
+use crate::openapi;
+use crate::push::{self, PushSubscribeRequest};
 use crate::scraper;
+use crate::subscriptions::formatters::MessageFormat;
+use crate::subscriptions::{self, Subscription};
+use crate::utils;
+
+use params::{check_date_range, check_range, ConfiguredGym, FromQuery, GymFilter, GymList, LocalDate};
 
 // Include modules
 pub mod scheduled;
 pub mod graph_template;
 pub mod time_averages_template;
+pub mod compare_template;
+pub mod trends_template;
+pub mod telegram;
+pub mod service_worker_template;
+pub mod docs_template;
+pub mod api;
+pub mod params;
+
+/// Longest period a `days` parameter can ask for
+const MAX_DAYS: i64 = 365;
+
+fn default_days<const DAYS: i64>() -> i64 {
+    DAYS
+}
+
+/// Query parameters selecting at most one gym
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GymQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+}
+
+impl FromQuery for GymQuery {}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ScrapeQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+    /// Store the result in the database
+    save: bool,
+}
+
+impl FromQuery for ScrapeQuery {}
 
 /// Handler for the /scrape endpoint
-pub async fn scrape_handler(req: Request, env: Env) -> Result<Response> {
-    // Parse the query parameters to get the website URL (optional)
-    let url = req.url()?;
-    let query_params: Vec<(String, String)> = url.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
-    
-    let website_url = query_params.iter()
-        .find(|(k, _)| k == "url")
-        .map(|(_, v)| v.as_str());
-    
+pub async fn scrape_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: ScrapeQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
     // If a specific URL is provided, scrape that website
-    let data = if let Some(url) = website_url {
-        let found_website = scraper::get_configured_websites().into_iter()
-            .find(|site| site.url == url);
-        
-        match found_website {
-            Some(website) => {
-                let mut data = Vec::new();
-                data.push(scraper::fetch_crowd_data(&website).await?);
-                data
-            },
-            None => {
-                // If website not found in predefined list, return error
-                return Response::error("Website not in configured list", 400);
-            }
-        }
+    let mut data = if let Some(website) = query.gym.website() {
+        let mut data = Vec::new();
+        data.push(scraper::fetch_crowd_data(website).await?);
+        data
     } else {
         scraper::fetch_all_data().await?.data
     };
     
     // If query param save=true, store in DB
-    if url.query().unwrap_or("").contains("save=true") {
+    if query.save {
+        let websites = scraper::get_configured_websites();
+        if let Err(e) = db::sync_gyms(&env).await {
+            console_error!("Error syncing configured gyms: {}", e);
+        }
+
         for x in &data {
+            let Some(website) = websites.iter().find(|site| site.url == x.website_url) else {
+                continue;
+            };
+            let quality = scheduled::assess_reading_quality(&env, website, x).await.unwrap_or(analysis::QUALITY_OK);
+
             match db::store_crowd_level(
                 &env,
                 x.crowd_level_percentage.as_str(),
-                x.crowd_level_description.as_str(),
-                x.website_url.as_str(),
-                x.location.as_str()
+                website.id,
+                quality,
+                None
             ).await {
                 Ok(_) => console_log!("Successfully stored data in DB from scrape endpoint"),
                 Err(e) => console_error!("Error storing data in DB from scrape endpoint: {}", e),
             }   
         }
     }
-    
-    Response::from_json(&data)
+
+    // Describe the levels in the requested language
+    let websites = scraper::get_configured_websites();
+    for x in data.iter_mut() {
+        if let Some(website) = websites.iter().find(|site| site.url == x.website_url) {
+            x.crowd_level_description = i18n::describe_level(x.details.raw_percentage, &website.crowd_level_bands, language).to_string();
+        }
+    }
+
+    Ok(Response::from_json(&data)?)
 }
 
-/// Handler for the /history endpoint
-pub async fn history_handler(req: Request, env: Env) -> Result<Response> {
-    // Get query parameters for timestamp and website_url
-    let url = req.url()?;
-    let query_params: Vec<(String, String)> = url.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
-    
-    let since_timestamp = query_params.iter()
-        .find(|(k, _)| k == "since")
-        .and_then(|(_, v)| v.parse::<i64>().ok());
-    
-    let until_timestamp = query_params.iter()
-        .find(|(k, _)| k == "until")
-        .and_then(|(_, v)| v.parse::<i64>().ok());
-    
-    let website_url = query_params.iter()
-        .find(|(k, _)| k == "url")
-        .map(|(_, v)| v.as_str());
-    
-    match db::get_crowd_level_history(&env, since_timestamp, until_timestamp, website_url).await {
-        Ok(data) => {
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HistoryQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+    /// Unix timestamps
+    since: Option<i64>,
+    until: Option<i64>,
+    include_flagged: bool,
+    include_annotations: bool,
+}
+
+impl FromQuery for HistoryQuery {}
+
+/// Handler for the /history endpoint, superseded by /api/v1/readings
+pub async fn history_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: HistoryQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    // Annotations are few and can be edited, so they are part of the version as a whole
+    let annotations = if query.include_annotations {
+        match db::get_annotations(&env, query.since, query.until, query.gym.url()).await {
+            Ok(annotations) => Some(annotations),
+            Err(e) => return Err(ApiError::internal("Error retrieving annotations", e)),
+        }
+    } else {
+        None
+    };
+
+    let mut state = match db::get_readings_state(&env, query.gym.url(), query.since, query.until, query.include_flagged, None).await {
+        Ok(state) => state,
+        Err(e) => return Err(ApiError::internal("Error retrieving history", e)),
+    };
+    if let Some(annotations) = &annotations {
+        state.push_str(&json!(annotations).to_string());
+    }
+
+    let freshness = caching::Freshness::new(&req, &state, caching::seconds_until_new_readings(utils::now_utc(), &query.gym.websites()))?;
+    if let Some(response) = freshness.not_modified(&req)? {
+        return Ok(response);
+    }
+
+    match db::get_crowd_level_history(&env, query.since, query.until, query.gym.url(), query.include_flagged, language).await {
+        Ok(mut data) => {
+            if let Some(annotations) = annotations {
+                data["annotations"] = json!(annotations);
+            }
+
             // Create response with JSON data
             let mut response = Response::from_json(&data)?;
             
-            // Add cache control headers for Cloudflare (10 minutes = 600 seconds)
-            response.headers_mut().set("Cache-Control", "public, max-age=600")?;
+            // Cached until the next scrape, afterwards the ETag tells whether anything changed
+            freshness.apply(&mut response)?;
+            crate::api::link_successor(&mut response, "/readings")?;
             
             Ok(response)
         },
-        Err(e) => Response::error(format!("Error retrieving history: {}", e), 500)
+        Err(e) => Err(ApiError::internal("Error retrieving history", e))
+    }
+}
+
+/// Handler for the /history/latest endpoint, superseded by /api/v1/readings/latest
+pub async fn latest_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: GymQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    match db::get_latest_crowd_level(&env, query.gym.url(), language).await {
+        Ok(None) => Err(ApiError::not_found("No records found")),
+        Ok(Some(mut data)) => {
+            // Annotate the record with whether its gym is currently open
+            let record_url = data["website_url"].as_str().map(|url| url.to_string());
+            let website = record_url.and_then(|record_url| {
+                scraper::get_configured_websites().into_iter().find(|site| site.url == record_url)
+            });
+
+            if let Some(website) = website {
+                let local_now = utils::utc_to_local(utils::now_utc());
+                data["opening_status"] = json!(website.opening_hours.status_at(local_now, language));
+            }
+
+            let mut response = Response::from_json(&data)?;
+            crate::api::link_successor(&mut response, "/readings/latest")?;
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving latest record", e))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct AnomaliesQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+    /// Unix timestamp
+    since: Option<i64>,
+}
+
+impl FromQuery for AnomaliesQuery {}
+
+/// Handler for the /anomalies endpoint - returns readings that deviated strongly from the profile
+pub async fn anomalies_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: AnomaliesQuery = params::extract(&req)?;
+
+    match db::get_anomalies(&env, query.since, query.gym.url()).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+
+            // New anomalies can only appear with a new scrape every 10 minutes
+            response.headers_mut().set("Cache-Control", "public, max-age=600")?;
+
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving anomalies", e))
+    }
+}
+
+/// The `at` query parameter: "now", a unix timestamp or a local "HH:MM" time today, held in UTC
+struct Moment(chrono::NaiveDateTime);
+
+impl<'de> Deserialize<'de> for Moment {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let now = utils::now_utc();
+
+        let moment = if value == "now" {
+            Some(now)
+        } else if let Ok(timestamp) = value.parse::<i64>() {
+            chrono::DateTime::from_timestamp(timestamp, 0).map(|dt| dt.naive_utc())
+        } else {
+            let today = utils::utc_to_local(now).date();
+            chrono::NaiveTime::parse_from_str(&value, "%H:%M").ok()
+                .map(|time| utils::local_to_utc(today.and_time(time)))
+        };
+
+        moment
+            .map(Moment)
+            .ok_or_else(|| serde::de::Error::invalid_value(serde::de::Unexpected::Str(&value), &"now, a unix timestamp or HH:MM"))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CompareQuery {
+    gyms: GymList,
+    at: Option<Moment>,
+}
+
+impl FromQuery for CompareQuery {}
+
+/// Handler for the /compare endpoint - ranks gyms by how crowded they are at a given time
+pub async fn compare_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: CompareQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    let websites = query.gyms.or_all();
+    let at = query.at.map(|at| at.0).unwrap_or_else(utils::now_utc);
+
+    let school_holidays = match db::get_school_holidays(&env).await {
+        Ok(data) => data,
+        Err(e) => return Err(ApiError::internal("Error retrieving school holidays", e)),
+    };
+
+    // Readings can't come from the future, so the deviation is measured at the latest possible point
+    let now = utils::now_utc();
+    let reference = at.min(now);
+    let forecast_at = at + chrono::Duration::hours(1);
+    let hours_to_at = (at - reference).num_minutes() as f64 / 60.0;
+    let hours_to_forecast = (forecast_at - reference).num_minutes() as f64 / 60.0;
+
+    let (reference_day, reference_hour) = holidays::profile_slot(reference, &school_holidays);
+    let (at_day, at_hour) = holidays::profile_slot(at, &school_holidays);
+    let (forecast_day, forecast_hour) = holidays::profile_slot(forecast_at, &school_holidays);
+
+    let mut gyms = Vec::new();
+
+    for website in websites {
+        let typical = |day: i64, hour: i64| db::get_time_average_slot(&env, &website.url, day, hour);
+
+        let reading = db::get_reading_near(&env, &website.url, &utils::format_db_timestamp(reference), 30).await?;
+        let current = reading.as_ref()
+            .and_then(|record| record["percentage"].as_str())
+            .and_then(|percentage| percentage.parse::<f64>().ok());
+
+        let typical_reference = typical(reference_day, reference_hour).await?
+            .and_then(|record| record["average_percentage"].as_f64());
+        let typical_at = typical(at_day, at_hour).await?
+            .and_then(|record| record["average_percentage"].as_f64());
+        let typical_forecast = typical(forecast_day, forecast_hour).await?
+            .and_then(|record| record["average_percentage"].as_f64());
+
+        let expected = match (current, typical_at) {
+            (Some(current), _) if hours_to_at == 0.0 => Some(current),
+            (_, Some(typical_at)) => Some(analysis::forecast_level(current, typical_reference, typical_at, hours_to_at)),
+            _ => None,
+        };
+        let forecast = typical_forecast
+            .map(|typical_forecast| analysis::forecast_level(current, typical_reference, typical_forecast, hours_to_forecast));
+
+        let opening_status = website.opening_hours.status_at(utils::utc_to_local(at), language);
+        let description = expected
+            .map(|expected| i18n::describe_level(expected, &website.crowd_level_bands, language));
+
+        gyms.push(json!({
+            "location": website.name,
+            "slug": website.slug,
+            "website_url": website.url,
+            "open": opening_status.open,
+            "opening_status": opening_status,
+            "current_percentage": current,
+            "current_created_at": reading.as_ref().map(|record| record["created_at"].clone()),
+            "typical_percentage": typical_at,
+            "expected_percentage": expected,
+            "expected_description": description,
+            "forecast_percentage": forecast,
+            "forecast_at": format!("{}Z", forecast_at.format("%Y-%m-%dT%H:%M:%S"))
+        }));
+    }
+
+    // Open gyms first, then the least crowded, gyms without any data last
+    gyms.sort_by(|a, b| {
+        let key = |gym: &serde_json::Value| (
+            !gym["open"].as_bool().unwrap_or(false),
+            gym["expected_percentage"].as_f64().unwrap_or(f64::MAX),
+        );
+        let (a_closed, a_level) = key(a);
+        let (b_closed, b_level) = key(b);
+        a_closed.cmp(&b_closed).then(a_level.total_cmp(&b_level))
+    });
+
+    for (index, gym) in gyms.iter_mut().enumerate() {
+        gym["rank"] = json!(index + 1);
+    }
+
+    let least_crowded = gyms.first()
+        .filter(|gym| gym["open"].as_bool().unwrap_or(false) && !gym["expected_percentage"].is_null())
+        .map(|gym| gym["website_url"].clone());
+
+    let mut response = Response::from_json(&json!({
+        "at": format!("{}Z", at.format("%Y-%m-%dT%H:%M:%S")),
+        "least_crowded": least_crowded,
+        "gyms": gyms
+    }))?;
+
+    // Comparisons change with every scrape
+    response.headers_mut().set("Cache-Control", "public, max-age=300")?;
+
+    Ok(response)
+}
+
+/// Handler for the compare view - returns HTML comparing all gyms at a glance
+pub async fn compare_view_handler(req: Request, _env: Env) -> ApiResult<Response> {
+    let websites = scraper::get_configured_websites();
+    let language = Language::from_request(&req);
+
+    let html = compare_template::generate_html(&websites, language);
+
+    Ok(Response::from_html(&html)?)
+}
+
+fn default_busy_threshold() -> f64 {
+    analysis::DEFAULT_BUSY_THRESHOLD
+}
+
+#[derive(Deserialize)]
+struct TrendsQuery {
+    #[serde(rename = "url")]
+    gym: ConfiguredGym,
+    #[serde(default)]
+    period: analysis::TrendPeriod,
+    /// Hourly average from which an hour counts as busy
+    #[serde(default = "default_busy_threshold")]
+    threshold: f64,
+}
+
+impl FromQuery for TrendsQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("threshold", self.threshold, 0.0..=100.0)
+    }
+}
+
+/// Handler for the /trends endpoint - returns per-week or per-month occupancy trends for a gym
+pub async fn trends_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: TrendsQuery = params::extract(&req)?;
+
+    match db::get_trends(&env, &query.gym.0.url, query.period, query.threshold).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+
+            // Trends only shift noticeably over days, cache for an hour
+            response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error calculating trends", e))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TrendsViewQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+    period: analysis::TrendPeriod,
+}
+
+impl FromQuery for TrendsViewQuery {}
+
+/// Handler for the trends view - returns HTML with week/month trend charts
+pub async fn trends_view_handler(req: Request, _env: Env) -> ApiResult<Response> {
+    let query: TrendsViewQuery = params::extract(&req)?;
+
+    let websites = scraper::get_configured_websites();
+    let language = Language::from_request(&req);
+
+    let slug = query.gym.website().map(|website| website.slug.as_str());
+    let html = trends_template::generate_html(&websites, slug, query.period, language);
+
+    Ok(Response::from_html(&html)?)
+}
+
+#[derive(Deserialize)]
+struct SummariesQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    #[serde(default = "default_days::<30>")]
+    days: i64,
+    /// Explicit dates take precedence over the number of days
+    #[serde(default)]
+    since: Option<LocalDate>,
+    #[serde(default)]
+    until: Option<LocalDate>,
+}
+
+impl SummariesQuery {
+    /// First and last local day, the range ends today unless `until` is given
+    fn date_range(&self) -> (chrono::NaiveDate, chrono::NaiveDate) {
+        let today = utils::utc_to_local(utils::now_utc()).date();
+        let since = self.since.map(|date| date.0).unwrap_or(today - chrono::Duration::days(self.days));
+        let until = self.until.map(|date| date.0).unwrap_or(today.max(since));
+        (since, until)
+    }
+}
+
+impl FromQuery for SummariesQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=MAX_DAYS)?;
+        let (since, until) = self.date_range();
+        check_date_range(since, until, MAX_DAYS)
+    }
+}
+
+/// Handler for the /summaries endpoint - returns per-day summary records
+pub async fn summaries_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: SummariesQuery = params::extract(&req)?;
+
+    let (since, until) = query.date_range();
+    let since_date = since.format("%Y-%m-%d").to_string();
+    let until_date = until.format("%Y-%m-%d").to_string();
+
+    match db::get_daily_summaries(&env, query.gym.url(), Some(&since_date), Some(&until_date)).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+
+            // Summaries are only written once a day by the midnight job
+            response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving daily summaries", e))
+    }
+}
+
+#[derive(Deserialize)]
+struct WeatherCorrelationQuery {
+    #[serde(rename = "url")]
+    gym: ConfiguredGym,
+    #[serde(default = "default_days::<90>")]
+    days: i64,
+}
+
+impl FromQuery for WeatherCorrelationQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=MAX_DAYS)
+    }
+}
+
+/// Handler for the /weather/correlation endpoint - correlates a gym's crowd levels with the weather
+pub async fn weather_correlation_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: WeatherCorrelationQuery = params::extract(&req)?;
+
+    match db::get_weather_correlation(&env, &query.gym.0.url, query.days).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+            response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error calculating weather correlation", e))
+    }
+}
+
+#[derive(Deserialize)]
+struct WeatherTimeAveragesQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    #[serde(default = "default_days::<90>")]
+    days: i64,
+}
+
+impl FromQuery for WeatherTimeAveragesQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=MAX_DAYS)
+    }
+}
+
+/// Handler for the /weather/time-averages endpoint - time averages split into rainy and dry readings
+pub async fn weather_time_averages_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: WeatherTimeAveragesQuery = params::extract(&req)?;
+
+    match db::get_weather_time_averages(&env, query.gym.url(), query.days).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+            response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error fetching weather time averages", e))
+    }
+}
+
+fn default_gap_threshold() -> i64 {
+    analysis::DEFAULT_GAP_THRESHOLD_MINUTES
+}
+
+#[derive(Deserialize)]
+struct CoverageQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    #[serde(default = "default_days::<7>")]
+    days: i64,
+    /// Explicit dates take precedence over the number of days
+    #[serde(default)]
+    since: Option<LocalDate>,
+    #[serde(default)]
+    until: Option<LocalDate>,
+    /// Minutes without a reading from which a stretch counts as a gap
+    #[serde(default = "default_gap_threshold")]
+    threshold: i64,
+}
+
+impl CoverageQuery {
+    /// First and last local day, the range ends today unless `until` is given
+    fn date_range(&self) -> (chrono::NaiveDate, chrono::NaiveDate) {
+        let today = utils::utc_to_local(utils::now_utc()).date();
+        let since = self.since.map(|date| date.0).unwrap_or(today - chrono::Duration::days(self.days));
+        let until = self.until.map(|date| date.0).unwrap_or(today.max(since));
+        (since, until)
+    }
+}
+
+impl FromQuery for CoverageQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=MAX_DAYS)?;
+        check_range("threshold", self.threshold, 1..=24 * 60)?;
+        let (since, until) = self.date_range();
+        check_date_range(since, until, MAX_DAYS)
+    }
+}
+
+/// Handler for the /coverage endpoint - reports data coverage and gaps per gym and day
+pub async fn coverage_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: CoverageQuery = params::extract(&req)?;
+
+    let (since_date, until_date) = query.date_range();
+
+    match db::get_coverage(&env, query.gym.url(), since_date, until_date, query.threshold).await {
+        Ok(data) => {
+            let mut response = Response::from_json(&data)?;
+
+            // Coverage of the current day changes with every scrape
+            response.headers_mut().set("Cache-Control", "public, max-age=600")?;
+
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error calculating coverage", e))
+    }
+}
+
+fn default_min_duration() -> i64 {
+    90
+}
+
+fn default_max_level() -> f64 {
+    40.0
+}
+
+#[derive(Deserialize)]
+struct CalendarQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    /// Minutes
+    #[serde(default = "default_min_duration")]
+    min_duration: i64,
+    /// Percent
+    #[serde(default = "default_max_level")]
+    max_level: f64,
+}
+
+impl FromQuery for CalendarQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("min_duration", self.min_duration, 1..=24 * 60)?;
+        check_range("max_level", self.max_level, 0.0..=100.0)
     }
 }
 
-/// Handler for the /history/latest endpoint
-pub async fn latest_handler(req: Request, env: Env) -> Result<Response> {
-    // Get website_url parameter if provided
+/// Handler for the /calendar.ics endpoint - an iCalendar feed of predicted quiet sessions
+///
+/// Quiet windows are the stretches of opening hours in the next 7 days whose typical
+/// level (from `time_averages`) stays at or below `max_level` for at least `min_duration` minutes.
+pub async fn calendar_handler(req: Request, env: Env) -> ApiResult<Response> {
     let url = req.url()?;
-    let query_params: Vec<(String, String)> = url.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
-    
-    let website_url = query_params.iter()
-        .find(|(k, _)| k == "url")
-        .map(|(_, v)| v.as_str());
-    
-    match db::get_latest_crowd_level(&env, website_url).await {
-        Ok(data) => Response::from_json(&data),
-        Err(e) => Response::error(format!("Error retrieving latest record: {}", e), 500)
+    let query: CalendarQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    let websites: Vec<scraper::WebsiteConfig> = match query.gym {
+        GymFilter::Gym(website) => vec![*website],
+        GymFilter::All => scraper::get_configured_websites(),
+    };
+    let (min_duration, max_level) = (query.min_duration, query.max_level);
+
+    let school_holidays = match db::get_school_holidays(&env).await {
+        Ok(data) => data,
+        Err(e) => return Err(ApiError::internal("Error retrieving school holidays", e)),
+    };
+
+    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Holiday"];
+    let now = utils::now_utc();
+    let local_now = utils::utc_to_local(now);
+    let origin = url.origin().ascii_serialization();
+
+    let mut events = Vec::new();
+
+    for website in websites {
+        let averages = match db::get_time_averages(&env, Some(&website.url)).await {
+            Ok(data) => data,
+            Err(e) => return Err(ApiError::internal("Error retrieving time averages", e)),
+        };
+        let profile = &averages["data"][&website.name];
+
+        // Hourly slots of the opening hours that haven't ended yet with their typical level, in local time
+        // Slots aren't clipped to the current time, so a window keeps its start and uid while it is running
+        let mut slots = Vec::new();
+        for offset in 0..7 {
+            let date = local_now.date() + chrono::Duration::days(offset);
+            let Some((open, close)) = website.opening_hours.hours_on(date) else {
+                continue;
+            };
+            let (opening, closing) = (date.and_time(open), date.and_time(close));
+
+            let mut hour_start = opening.with_minute(0).unwrap_or(opening);
+            while hour_start < closing {
+                let hour_end = hour_start + chrono::Duration::hours(1);
+                let slot_start = hour_start.max(opening);
+                let slot_end = hour_end.min(closing);
+
+                let (day_of_week, utc_hour) = holidays::profile_slot(utils::local_to_utc(hour_start), &school_holidays);
+                let average = profile[weekdays[day_of_week as usize]][utc_hour.to_string()]["average"].as_f64();
+
+                if let (true, Some(average)) = (local_now < slot_end, average) {
+                    slots.push((slot_start, slot_end, average));
+                }
+                hour_start = hour_end;
+            }
+        }
+
+        let mut graph_url = Url::parse(&origin).and_then(|origin| origin.join("/graph")).map_err(Error::from)?;
+        graph_url.query_pairs_mut()
+            .append_pair("url", &website.slug)
+            .append_pair("days", "1");
+
+        for window in analysis::predicted_quiet_windows(&slots, max_level, min_duration) {
+            let window_start = utils::local_to_utc(window.start);
+
+            events.push(CalendarEvent {
+                uid: format!("{}-{}@boulderwelt-scraper", window_start.format("%Y%m%dT%H%M%SZ"), website.slug),
+                start: utils::local_to_utc(window.start.max(local_now)),
+                end: utils::local_to_utc(window.end),
+                summary: i18n::text(language, "calendar.summary").replacen("{}", &website.name, 1),
+                description: i18n::text(language, "calendar.description")
+                    .replacen("{}", &window.mean_percentage.round().to_string(), 1)
+                    .replacen("{}", &window.max_percentage.round().to_string(), 1),
+                location: website.name.clone(),
+                url: Some(graph_url.to_string()),
+            });
+        }
+    }
+
+    events.sort_by_key(|event| event.start);
+
+    let mut response = Response::ok(calendar::render(i18n::text(language, "calendar.name"), &events, now))?;
+    response.headers_mut().set("Content-Type", "text/calendar; charset=utf-8")?;
+
+    // The profile only changes with the daily averages update
+    response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    #[serde(default = "default_days::<30>")]
+    days: i64,
+}
+
+impl FromQuery for FeedQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=90)
+    }
+}
+
+/// Handler for the /feed.atom endpoint - an Atom feed of notable crowd events per gym
+pub async fn feed_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let url = req.url()?;
+    let query: FeedQuery = params::extract(&req)?;
+    let language = Language::from_request(&req);
+
+    let now = utils::now_utc();
+    let events = match db::get_notable_events(&env, query.gym.url(), now - chrono::Duration::days(query.days)).await {
+        Ok(events) => events,
+        Err(e) => return Err(ApiError::internal("Error collecting notable events", e)),
+    };
+
+    let origin = url.origin().ascii_serialization();
+    let mut response = Response::ok(feed::render_atom(&events, url.as_str(), &origin, language, now))?;
+    response.headers_mut().set("Content-Type", "application/atom+xml; charset=utf-8")?;
+
+    // New events appear at most with every scrape
+    response.headers_mut().set("Cache-Control", "public, max-age=600")?;
+
+    Ok(response)
+}
+
+/// Handler for the /live endpoint - a Server-Sent Events stream of new readings
+///
+/// The stream is held by the live Durable Object, `?url=` limits it to one gym.
+pub async fn live_handler(req: Request, env: Env) -> ApiResult<Response> {
+    // Unknown gyms are rejected here, the hub only sees the resolved website URLs
+    let query: GymQuery = params::extract(&req)?;
+    let gyms = query.gym.url().map(|url| vec![url.to_string()]);
+
+    Ok(live::connect(&env, req, gyms).await?)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct WebSocketQuery {
+    gyms: Option<GymList>,
+}
+
+impl FromQuery for WebSocketQuery {}
+
+/// Handler for the /ws endpoint - a WebSocket for dashboards with readings, anomalies and forecasts
+///
+/// The socket is held by the live Durable Object, `?gyms=` (comma separated) subscribes right away.
+pub async fn websocket_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: WebSocketQuery = params::extract(&req)?;
+    let gyms = query.gyms.map(|gyms| gyms.0.into_iter().map(|website| website.url).collect());
+
+    Ok(live::connect(&env, req, gyms).await?)
+}
+
+/// Handler for the /time-averages endpoint, superseded by /api/v1/time-averages
+pub async fn time_averages_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: GymQuery = params::extract(&req)?;
+
+    let state = match db::get_time_averages_state(&env, query.gym.url()).await {
+        Ok(state) => state,
+        Err(e) => return Err(ApiError::internal("Error fetching time averages", e)),
+    };
+    let freshness = caching::Freshness::new(&req, &state, caching::seconds_until_next_averages_update(utils::now_utc()))?;
+    if let Some(response) = freshness.not_modified(&req)? {
+        return Ok(response);
+    }
+
+    match db::get_time_averages(&env, query.gym.url()).await {
+        Ok(data) => {
+            // Create response with JSON data
+            let mut response = Response::from_json(&data)?;
+            
+            // Cached until the midnight job recalculates the averages
+            freshness.apply(&mut response)?;
+            crate::api::link_successor(&mut response, "/time-averages")?;
+            
+            Ok(response)
+        },
+        Err(e) => Err(ApiError::internal("Error fetching time averages", e))
     }
 }
 
 /// Handler for the /websites endpoint - returns list of configured websites
-pub async fn websites_handler(_req: Request, _env: Env) -> Result<Response> {
+pub async fn websites_handler(_req: Request, _env: Env) -> ApiResult<Response> {
     let websites = scraper::get_configured_websites();
     let websites_json = json!({
         "websites": websites
     });
-    Response::from_json(&websites_json)
+    Ok(Response::from_json(&websites_json)?)
+}
+
+#[derive(Deserialize)]
+struct GraphQuery {
+    #[serde(default, rename = "url")]
+    gym: GymFilter,
+    #[serde(default = "default_days::<3>")]
+    days: i64,
+}
+
+impl FromQuery for GraphQuery {
+    fn validate(&self) -> ApiResult<()> {
+        check_range("days", self.days, 1..=MAX_DAYS)
+    }
 }
 
 /// Handler for the /graph endpoint - returns HTML with interactive graph visualization
-pub async fn graph_handler(req: Request, _env: Env) -> Result<Response> {
-    // Parse query parameters
-    let url = req.url()?;
-    let query_params: Vec<(String, String)> = url.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
-    
-    let website_url = query_params.iter()
-        .find(|(k, _)| k == "url")
-        .map(|(_, v)| v.as_str());
-    
-    // Handle the "all" option
-    let normalized_website_url = if website_url == Some("all") {
-        None // None will show all gyms, as modified in the graph_template
-    } else {
-        website_url
-    };
-    
-    let days = query_params.iter()
-        .find(|(k, _)| k == "days")
-        .map(|(_, v)| v.parse::<u32>().unwrap_or(3))
-        .unwrap_or(3);
-    
+pub async fn graph_handler(req: Request, _env: Env) -> ApiResult<Response> {
+    // "all" selects no gym, which shows all gyms in the graph_template
+    let query: GraphQuery = params::extract(&req)?;
+
     // Get list of available websites for the dropdown
     let websites = scraper::get_configured_websites();
 
+    let language = Language::from_request(&req);
+
     // Create HTML with the graph
-    let html = graph_template::generate_html(&websites, normalized_website_url, days);
-    
+    let slug = query.gym.website().map(|website| website.slug.as_str());
+    let html = graph_template::generate_html(&websites, slug, query.days as u32, language);
+
     // Return the HTML response
-    Response::from_html(&html)
+    Ok(Response::from_html(&html)?)
+}
+
+/// Removes hours outside each gym's regular opening hours from the time averages data
+///
+/// Averages are keyed by UTC weekday and hour, so each slot is shifted into local
+/// time at its latest occurrence, which keeps slots on either side of a DST change
+/// apart. The holiday profile uses the Sunday schedule.
+fn clip_to_opening_hours(data: &mut serde_json::Value) {
+    let websites = scraper::get_configured_websites();
+    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Holiday"];
+    let now = utils::now_utc();
+    let today = utils::weekday_index(now.weekday());
+
+    let Some(gyms) = data["data"].as_object_mut() else {
+        return;
+    };
+
+    for (gym_name, days) in gyms.iter_mut() {
+        let Some(website) = websites.iter().find(|site| &site.name == gym_name) else {
+            continue;
+        };
+        let Some(days) = days.as_object_mut() else {
+            continue;
+        };
+
+        for (day_name, hours) in days.iter_mut() {
+            let Some(day_idx) = weekdays.iter().position(|day| day == day_name) else {
+                continue;
+            };
+            let Some(hours) = hours.as_object_mut() else {
+                continue;
+            };
+
+            let is_holiday = day_idx as i64 == holidays::HOLIDAY_DAY_OF_WEEK;
+            let day_of_week = if is_holiday { today } else { day_idx as i64 };
+
+            hours.retain(|hour, _| {
+                let hour = hour.parse::<u32>().unwrap_or(0);
+                let local = utils::utc_to_local(utils::latest_utc_slot(now, day_of_week, hour));
+                let local_day = if is_holiday { 0 } else { utils::weekday_index(local.weekday()) };
+                website.opening_hours.is_open_during_hour(local_day, local.hour())
+            });
+        }
+    }
 }
 
 /// Handler for the time averages view
-pub async fn time_averages_view_handler(_req: Request, env: Env) -> Result<Response> {
+pub async fn time_averages_view_handler(req: Request, env: Env) -> ApiResult<Response> {
     // Get the time averages data
-    let data = crate::db::get_time_averages(&env, None).await?;
+    let mut data = crate::db::get_time_averages(&env, None).await?;
+
+    // Only show the hours during which the gyms are open
+    clip_to_opening_hours(&mut data);
     
     // Generate the HTML using the template
-    let html = time_averages_template::get_time_averages_html(data);
+    let html = time_averages_template::get_time_averages_html(data, Language::from_request(&req));
     
     // Return the response with HTML content type
     let mut headers = Headers::new();
     headers.set("Content-Type", "text/html")?;
     
     Ok(Response::ok(html)?.with_headers(headers))
-}
\ No newline at end of file
+}
+
+/// Checks the request's bearer token against the ADMIN_TOKEN secret
+pub fn is_admin_request(req: &Request, env: &Env) -> bool {
+    let expected = match env.secret("ADMIN_TOKEN") {
+        Ok(secret) => secret.to_string(),
+        Err(_) => {
+            console_error!("ADMIN_TOKEN secret is not configured, rejecting admin request");
+            return false;
+        }
+    };
+
+    match req.headers().get("Authorization") {
+        Ok(Some(header)) => header.strip_prefix("Bearer ").is_some_and(|token| utils::secrets_match(token, &expected)),
+        _ => false,
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HolidaysQuery {
+    /// Current year if not given
+    year: Option<i32>,
+}
+
+impl FromQuery for HolidaysQuery {
+    fn validate(&self) -> ApiResult<()> {
+        match self.year {
+            Some(year) => check_range("year", year, 1970..=2100),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Handler for the /holidays endpoint - returns public and school holidays for a year
+pub async fn holidays_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: HolidaysQuery = params::extract(&req)?;
+
+    let today = utils::utc_to_local(utils::now_utc()).date();
+    let year = query.year.unwrap_or(chrono::Datelike::year(&today));
+
+    let school_holidays = match db::get_school_holidays(&env).await {
+        Ok(data) => data,
+        Err(e) => return Err(ApiError::internal("Error retrieving school holidays", e)),
+    };
+
+    let profile_day = holidays::profile_day(today, &school_holidays);
+
+    Ok(Response::from_json(&json!({
+        "today": {
+            "date": today.format("%Y-%m-%d").to_string(),
+            "is_holiday": profile_day == holidays::HOLIDAY_DAY_OF_WEEK,
+            "profile_day_of_week": profile_day
+        },
+        "public_holidays": holidays::public_holidays_for_year(year),
+        "school_holidays": school_holidays
+    }))?)
+}
+
+/// Handler for PUT /admin/school-holidays - replaces the list of school holiday periods
+pub async fn school_holidays_upload_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let school_holidays: Vec<SchoolHoliday> = match req.json().await {
+        Ok(data) => data,
+        Err(e) => return Err(ApiError::invalid_body(format!("Invalid school holiday list: {}", e))),
+    };
+
+    if let Some(invalid) = school_holidays.iter().find(|holiday| holiday.range().is_none()) {
+        return Err(ApiError::invalid_body(format!("Invalid date range for school holiday '{}'", invalid.name)));
+    }
+
+    match db::replace_school_holidays(&env, &school_holidays).await {
+        Ok(_) => Ok(Response::from_json(&json!({
+            "school_holidays": school_holidays
+        }))?),
+        Err(e) => Err(ApiError::internal("Error storing school holidays", e))
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct AnnotationsQuery {
+    #[serde(rename = "url")]
+    gym: GymFilter,
+    /// Unix timestamps
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+impl FromQuery for AnnotationsQuery {}
+
+/// Handler for the /annotations endpoint - lists annotations overlapping a time range
+pub async fn annotations_handler(req: Request, env: Env) -> ApiResult<Response> {
+    let query: AnnotationsQuery = params::extract(&req)?;
+
+    match db::get_annotations(&env, query.since, query.until, query.gym.url()).await {
+        Ok(annotations) => Ok(Response::from_json(&json!({
+            "data": annotations
+        }))?),
+        Err(e) => Err(ApiError::internal("Error retrieving annotations", e))
+    }
+}
+
+/// Reads and validates an annotation from the request body
+async fn parse_annotation(req: &mut Request) -> ApiResult<Annotation> {
+    let mut annotation: Annotation = req.json().await
+        .map_err(|e| ApiError::invalid_body(format!("Invalid annotation: {}", e)))?;
+
+    annotation.validate().map_err(ApiError::invalid_body)?;
+    // A slug is stored as the gym's URL, annotations without a gym apply to all gyms
+    if let Some(gym) = annotation.website_url.take() {
+        annotation.website_url = Some(params::configured_gym(&gym)?.url);
+    }
+
+    Ok(annotation)
+}
+
+/// Parses the `:id` segment of an admin route
+fn id_parameter(id: &str) -> ApiResult<i64> {
+    id.parse::<i64>().map_err(|_| ApiError::invalid_parameter("id", id, "an integer"))
+}
+
+/// Handler for POST /admin/annotations - creates an annotation
+pub async fn annotation_create_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let annotation = parse_annotation(&mut req).await?;
+
+    match db::store_annotation(&env, &annotation).await {
+        Ok(id) => Ok(Response::from_json(&json!({
+            "id": id,
+            "annotation": annotation
+        }))?.with_status(201)),
+        Err(e) => Err(ApiError::internal("Error storing annotation", e))
+    }
+}
+
+/// Handler for PUT /admin/annotations/:id - replaces an annotation
+pub async fn annotation_update_handler(mut req: Request, env: Env, id: &str) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let id = id_parameter(id)?;
+    let annotation = parse_annotation(&mut req).await?;
+
+    match db::update_annotation(&env, id, &annotation).await {
+        Ok(true) => Ok(Response::from_json(&json!({
+            "id": id,
+            "annotation": annotation
+        }))?),
+        Ok(false) => Err(ApiError::not_found("Annotation not found")),
+        Err(e) => Err(ApiError::internal("Error updating annotation", e))
+    }
+}
+
+/// Handler for DELETE /admin/annotations/:id - removes an annotation
+pub async fn annotation_delete_handler(req: Request, env: Env, id: &str) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let id = id_parameter(id)?;
+
+    match db::delete_annotation(&env, id).await {
+        Ok(true) => Ok(Response::from_json(&json!({
+            "deleted": id
+        }))?),
+        Ok(false) => Err(ApiError::not_found("Annotation not found")),
+        Err(e) => Err(ApiError::internal("Error deleting annotation", e))
+    }
+}
+
+/// Handler for GET /admin/subscriptions - lists all threshold alert subscriptions
+pub async fn subscriptions_handler(req: Request, env: Env) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    match db::get_subscriptions(&env, None).await {
+        Ok(records) => {
+            let subscriptions: Vec<serde_json::Value> = records.iter()
+                .map(|record| json!({
+                    "id": record.id,
+                    "subscription": record.subscription(),
+                    "matching": record.matching == 1,
+                    "last_notified_at": record.last_notified_at
+                }))
+                .collect();
+
+            Ok(Response::from_json(&json!({
+                "data": subscriptions
+            }))?)
+        },
+        Err(e) => Err(ApiError::internal("Error retrieving subscriptions", e))
+    }
+}
+
+/// Handler for POST /admin/subscriptions - creates a threshold alert subscription
+pub async fn subscription_create_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let mut subscription: Subscription = match req.json().await {
+        Ok(subscription) => subscription,
+        Err(e) => return Err(ApiError::invalid_body(format!("Invalid subscription: {}", e))),
+    };
+
+    subscription.validate().map_err(ApiError::invalid_body)?;
+    // A slug is stored as the gym's URL
+    subscription.website_url = params::configured_gym(&subscription.website_url)?.url;
+
+    match db::store_subscription(&env, &subscription).await {
+        Ok(id) => Ok(Response::from_json(&json!({
+            "id": id,
+            "subscription": subscription
+        }))?.with_status(201)),
+        Err(e) => Err(ApiError::internal("Error storing subscription", e))
+    }
+}
+
+/// Handler for DELETE /admin/subscriptions/:id - removes a subscription
+pub async fn subscription_delete_handler(req: Request, env: Env, id: &str) -> ApiResult<Response> {
+    if !is_admin_request(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let id = id_parameter(id)?;
+
+    match db::delete_subscription(&env, id).await {
+        Ok(true) => Ok(Response::from_json(&json!({
+            "deleted": id
+        }))?),
+        Ok(false) => Err(ApiError::not_found("Subscription not found")),
+        Err(e) => Err(ApiError::internal("Error deleting subscription", e))
+    }
+}
+
+/// Handler for the /sw.js endpoint - the service worker showing push notifications
+pub async fn service_worker_handler(_req: Request, _env: Env) -> ApiResult<Response> {
+    let mut response = Response::ok(service_worker_template::SCRIPT)?;
+    response.headers_mut().set("Content-Type", "application/javascript")?;
+    // Browsers check for service worker updates, a stale copy would delay them
+    response.headers_mut().set("Cache-Control", "no-cache")?;
+    Ok(response)
+}
+
+/// Handler for GET /openapi.json - the OpenAPI document generated from the route table
+pub async fn openapi_handler(req: Request, _env: Env) -> ApiResult<Response> {
+    let origin = req.url()?.origin().ascii_serialization();
+    let document = openapi::document(&crate::routes(), &origin);
+
+    let mut response = Response::from_json(&document)?;
+    // Only changes with a deployment
+    response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+    response.headers_mut().set("Access-Control-Allow-Origin", "*")?;
+    Ok(response)
+}
+
+/// Handler for GET /docs - interactive reference rendered from /openapi.json
+pub async fn docs_handler(_req: Request, _env: Env) -> ApiResult<Response> {
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "text/html")?;
+
+    Ok(Response::ok(docs_template::HTML)?.with_headers(headers))
+}
+
+/// Handler for GET /push/key - returns the VAPID public key for subscribing in the browser
+pub async fn push_key_handler(_req: Request, env: Env) -> ApiResult<Response> {
+    match push::vapid_public_key(&env) {
+        Ok(public_key) => Ok(Response::from_json(&json!({
+            "public_key": public_key
+        }))?),
+        Err(e) => {
+            console_error!("Web Push is not available: {}", e);
+            Err(ApiError::new(ErrorCode::Unavailable, "Web Push is not configured"))
+        }
+    }
+}
+
+/// Handler for POST /push/subscribe - subscribes a browser to a gym crossing a threshold
+///
+/// Subscribing the same browser to a gym again replaces the previous threshold.
+pub async fn push_subscribe_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    let request: PushSubscribeRequest = match req.json().await {
+        Ok(request) => request,
+        Err(e) => return Err(ApiError::invalid_body(format!("Invalid push subscription: {}", e))),
+    };
+
+    let website = params::configured_gym(&request.website_url)?;
+
+    let subscription = Subscription {
+        website_url: website.url,
+        threshold: request.threshold,
+        direction: request.direction,
+        windows: Vec::new(),
+        webhook_url: None,
+        telegram_chat_id: None,
+        push: Some(request.subscription),
+        format: MessageFormat::Json,
+        cooldown_minutes: subscriptions::DEFAULT_COOLDOWN_MINUTES,
+        language: request.language,
+    };
+
+    subscription.validate().map_err(ApiError::invalid_body)?;
+
+    let endpoint = subscription.push.as_ref().map(|push| push.endpoint.as_str()).unwrap_or_default();
+    if let Err(e) = db::delete_push_subscriptions(&env, endpoint, Some(&subscription.website_url)).await {
+        return Err(ApiError::internal("Error replacing push subscription", e));
+    }
+
+    match db::count_push_subscriptions(&env, endpoint).await {
+        Ok(count) if count >= push::MAX_SUBSCRIPTIONS_PER_ENDPOINT => {
+            return Err(ApiError::invalid_body(format!(
+                "A push endpoint can hold at most {} subscriptions",
+                push::MAX_SUBSCRIPTIONS_PER_ENDPOINT
+            )));
+        },
+        Ok(_) => {},
+        Err(e) => return Err(ApiError::internal("Error counting push subscriptions", e)),
+    }
+
+    match db::store_subscription(&env, &subscription).await {
+        Ok(id) => Ok(Response::from_json(&json!({
+            "id": id,
+            "website_url": subscription.website_url,
+            "threshold": subscription.threshold,
+            "direction": subscription.direction
+        }))?.with_status(201)),
+        Err(e) => Err(ApiError::internal("Error storing push subscription", e))
+    }
+}
+
+/// Handler for POST /push/unsubscribe - removes a browser's push subscriptions
+///
+/// Body: {"endpoint": "...", "website_url": "..."}, without website_url all gyms are removed.
+pub async fn push_unsubscribe_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    let body: serde_json::Value = match req.json().await {
+        Ok(body) => body,
+        Err(e) => return Err(ApiError::invalid_body(format!("Invalid request body: {}", e))),
+    };
+
+    let Some(endpoint) = body["endpoint"].as_str() else {
+        return Err(ApiError::invalid_body("Missing endpoint"));
+    };
+
+    let website = body["website_url"].as_str().map(params::configured_gym).transpose()?;
+
+    match db::delete_push_subscriptions(&env, endpoint, website.as_ref().map(|site| site.url.as_str())).await {
+        Ok(count) => Ok(Response::from_json(&json!({
+            "deleted": count
+        }))?),
+        Err(e) => Err(ApiError::internal("Error removing push subscriptions", e))
+    }
+}
@@ -0,0 +1,518 @@
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+use chrono::NaiveDate;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::Deserialize;
+use worker::*;
+
+use crate::api;
+use crate::error::{ApiError, ApiResult, ErrorCode};
+use crate::scraper::{self, WebsiteConfig};
+
+// Typed extraction of query parameters.
+//
+// Every endpoint declares its parameters as a struct deriving `Deserialize`, with serde defaults
+// for optional values. Values are parsed strictly: a parameter that is present but can't be
+// parsed is rejected with a 400 naming the parameter instead of being ignored.
+
+/// Query parameters of an endpoint
+pub trait FromQuery: DeserializeOwned {
+    /// Checks what the types can't express, e.g. value ranges
+    fn validate(&self) -> ApiResult<()> {
+        Ok(())
+    }
+}
+
+/// Deserializes and validates the query string of a request
+///
+/// Of a repeated parameter only the first value counts, later ones are ignored.
+pub fn extract<T: FromQuery>(req: &Request) -> ApiResult<T> {
+    from_url(&req.url()?)
+}
+
+fn from_url<T: FromQuery>(url: &Url) -> ApiResult<T> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for (name, value) in url.query_pairs() {
+        if !pairs.iter().any(|(existing, _)| *existing == name) {
+            pairs.push((name.into_owned(), value.into_owned()));
+        }
+    }
+
+    let query = T::deserialize(QueryDeserializer { pairs: &pairs }).map_err(QueryError::into_api_error)?;
+    query.validate()?;
+    Ok(query)
+}
+
+/// Rejects a number outside `range`
+pub fn check_range<T: PartialOrd + Display>(name: &str, value: T, range: RangeInclusive<T>) -> ApiResult<()> {
+    if range.contains(&value) {
+        return Ok(());
+    }
+
+    Err(ApiError::invalid_parameter(
+        name,
+        &value.to_string(),
+        &format!("a number from {} to {}", range.start(), range.end()),
+    ))
+}
+
+/// Rejects a date range that ends before it starts or spans more than `max_days` days
+pub fn check_date_range(since: NaiveDate, until: NaiveDate, max_days: i64) -> ApiResult<()> {
+    if until < since {
+        return Err(ApiError::invalid_parameter(
+            "until",
+            &until.format("%Y-%m-%d").to_string(),
+            &format!("a date on or after {}", since.format("%Y-%m-%d")),
+        ));
+    }
+
+    if (until - since).num_days() > max_days {
+        return Err(ApiError::invalid_parameter(
+            "since",
+            &since.format("%Y-%m-%d").to_string(),
+            &format!("a date at most {} days before {}", max_days, until.format("%Y-%m-%d")),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Looks up a gym given by slug or website URL, which has to be configured
+pub fn configured_gym(gym: &str) -> ApiResult<WebsiteConfig> {
+    scraper::find_website(gym).ok_or_else(|| ApiError::unknown_gym(gym))
+}
+
+/// A configured gym given as parameter
+#[derive(Debug, Clone)]
+pub struct ConfiguredGym(pub WebsiteConfig);
+
+impl<'de> Deserialize<'de> for ConfiguredGym {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let gym = String::deserialize(deserializer)?;
+        scraper::find_website(&gym)
+            .map(ConfiguredGym)
+            .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&gym), &"the slug or website URL of a configured gym"))
+    }
+}
+
+/// An optional gym filter, missing, empty and "all" select all gyms
+#[derive(Debug, Clone, Default)]
+pub enum GymFilter {
+    #[default]
+    All,
+    Gym(Box<WebsiteConfig>),
+}
+
+impl GymFilter {
+    pub fn website(&self) -> Option<&WebsiteConfig> {
+        match self {
+            GymFilter::All => None,
+            GymFilter::Gym(website) => Some(website),
+        }
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.website().map(|website| website.url.as_str())
+    }
+
+    /// The selected gym, or all configured gyms
+    pub fn websites(&self) -> Vec<WebsiteConfig> {
+        match self {
+            GymFilter::All => scraper::get_configured_websites(),
+            GymFilter::Gym(website) => vec![(**website).clone()],
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GymFilter {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)?.as_deref() {
+            None | Some("all") => Ok(GymFilter::All),
+            Some(gym) => ConfiguredGym::deserialize(gym.into_deserializer()).map(|gym| GymFilter::Gym(Box::new(gym.0))),
+        }
+    }
+}
+
+/// A comma separated list of gyms, an empty list selects all gyms
+#[derive(Debug, Clone, Default)]
+pub struct GymList(pub Vec<WebsiteConfig>);
+
+impl GymList {
+    /// The selected gyms, or all configured gyms if none were selected
+    pub fn or_all(self) -> Vec<WebsiteConfig> {
+        if self.0.is_empty() {
+            scraper::get_configured_websites()
+        } else {
+            self.0
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GymList {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let gyms = Vec::<ConfiguredGym>::deserialize(deserializer)?;
+        Ok(GymList(gyms.into_iter().map(|gym| gym.0).collect()))
+    }
+}
+
+/// A local date written as YYYY-MM-DD
+#[derive(Debug, Clone, Copy)]
+pub struct LocalDate(pub NaiveDate);
+
+impl<'de> Deserialize<'de> for LocalDate {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+            .map(LocalDate)
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&value), &"a date as YYYY-MM-DD"))
+    }
+}
+
+/// A unix timestamp or an ISO 8601 date-time, held as unix timestamp
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp(pub i64);
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        api::parse_timestamp(&value)
+            .map(Timestamp)
+            .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&value), &"a unix timestamp or an ISO 8601 date-time"))
+    }
+}
+
+/// Error while deserializing the query string
+#[derive(Debug)]
+enum QueryError {
+    /// A value didn't parse, with a description of the accepted values
+    Expected(String),
+    Missing(&'static str),
+    /// An error already tied to its parameter
+    Parameter(ApiError),
+}
+
+impl QueryError {
+    /// Ties an error to the parameter and value it occurred in
+    fn at(self, name: &str, value: &str) -> Self {
+        match self {
+            QueryError::Expected(expected) => QueryError::Parameter(ApiError::invalid_parameter(name, value, &expected)),
+            error => error,
+        }
+    }
+
+    fn into_api_error(self) -> ApiError {
+        match self {
+            QueryError::Expected(message) => ApiError::new(ErrorCode::InvalidParameter, message),
+            QueryError::Missing(name) => ApiError::missing_parameter(name),
+            QueryError::Parameter(error) => error,
+        }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Expected(expected) => write!(f, "expected {}", expected),
+            QueryError::Missing(name) => write!(f, "missing '{}' parameter", name),
+            QueryError::Parameter(error) => write!(f, "{}", error.message),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl de::Error for QueryError {
+    fn custom<T: Display>(msg: T) -> Self {
+        QueryError::Expected(msg.to_string())
+    }
+
+    fn invalid_type(_unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        QueryError::Expected(expected.to_string())
+    }
+
+    fn invalid_value(_unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        QueryError::Expected(expected.to_string())
+    }
+
+    fn unknown_variant(_variant: &str, expected: &'static [&'static str]) -> Self {
+        QueryError::Expected(expected.join(" or "))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        QueryError::Missing(field)
+    }
+}
+
+/// Deserializes the whole query string as a map of parameter names to values
+struct QueryDeserializer<'a> {
+    pairs: &'a [(String, String)],
+}
+
+impl<'de> de::Deserializer<'de> for QueryDeserializer<'_> {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        visitor.visit_map(QueryMap { pairs: self.pairs.iter(), current: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct QueryMap<'a> {
+    pairs: std::slice::Iter<'a, (String, String)>,
+    current: Option<&'a (String, String)>,
+}
+
+impl<'de> de::MapAccess<'de> for QueryMap<'_> {
+    type Error = QueryError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, QueryError> {
+        let Some(pair) = self.pairs.next() else {
+            return Ok(None);
+        };
+        self.current = Some(pair);
+        seed.deserialize(IntoDeserializer::<QueryError>::into_deserializer(pair.0.as_str())).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> std::result::Result<V::Value, QueryError> {
+        let Some((name, value)) = self.current.take() else {
+            return Err(de::Error::custom("a value after each parameter name"));
+        };
+        seed.deserialize(ValueDeserializer { name, value })
+            .map_err(|e| e.at(name, value))
+    }
+}
+
+/// Deserializes the value of one parameter, numbers and booleans are parsed from the text
+struct ValueDeserializer<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl ValueDeserializer<'_> {
+    fn parse<T: std::str::FromStr>(&self, expected: &str) -> std::result::Result<T, QueryError> {
+        self.value.parse::<T>()
+            .map_err(|_| QueryError::Expected(expected.to_string()))
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident: $type:ty, $expected:literal;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+                visitor.$visit(self.parse::<$type>($expected)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        visitor.visit_str(self.value)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        match self.value {
+            "true" | "1" => visitor.visit_bool(true),
+            "false" | "0" => visitor.visit_bool(false),
+            _ => Err(QueryError::Expected("true or false".to_string())),
+        }
+    }
+
+    deserialize_number! {
+        deserialize_i8 => visit_i8: i8, "an integer";
+        deserialize_i16 => visit_i16: i16, "an integer";
+        deserialize_i32 => visit_i32: i32, "an integer";
+        deserialize_i64 => visit_i64: i64, "an integer";
+        deserialize_u8 => visit_u8: u8, "a positive integer";
+        deserialize_u16 => visit_u16: u16, "a positive integer";
+        deserialize_u32 => visit_u32: u32, "a positive integer";
+        deserialize_u64 => visit_u64: u64, "a positive integer";
+        deserialize_f32 => visit_f32: f32, "a number";
+        deserialize_f64 => visit_f64: f64, "a number";
+    }
+
+    /// An empty value counts as not given, as sent by forms with an empty field
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        if self.value.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Lists are comma separated
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, QueryError> {
+        visitor.visit_seq(ListItems { name: self.name, items: self.value.split(',') })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, QueryError> {
+        visitor.visit_enum(IntoDeserializer::<QueryError>::into_deserializer(self.value))
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Items of a comma separated list, empty items are skipped
+struct ListItems<'a> {
+    name: &'a str,
+    items: std::str::Split<'a, char>,
+}
+
+impl<'de> de::SeqAccess<'de> for ListItems<'_> {
+    type Error = QueryError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> std::result::Result<Option<T::Value>, QueryError> {
+        let Some(value) = self.items.by_ref().map(|item| item.trim()).find(|item| !item.is_empty()) else {
+            return Ok(None);
+        };
+        seed.deserialize(ValueDeserializer { name: self.name, value })
+            .map(Some)
+            .map_err(|e| e.at(self.name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug)]
+    struct TestQuery {
+        limit: u32,
+        #[serde(default)]
+        flag: bool,
+        since: Option<i64>,
+        ratio: Option<f64>,
+        #[serde(default)]
+        gym: GymFilter,
+        #[serde(default)]
+        gyms: GymList,
+    }
+
+    impl FromQuery for TestQuery {
+        fn validate(&self) -> ApiResult<()> {
+            check_range("limit", self.limit, 1..=100)
+        }
+    }
+
+    fn parse(query: &str) -> ApiResult<TestQuery> {
+        from_url(&Url::parse(&format!("https://example.com/?{}", query)).unwrap())
+    }
+
+    fn slugs(gyms: &GymList) -> Vec<&str> {
+        gyms.0.iter().map(|gym| gym.slug.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_booleans_and_numbers() {
+        let query = parse("limit=5&flag=true&since=-3&ratio=0.5").unwrap();
+        assert_eq!(query.limit, 5);
+        assert!(query.flag);
+        assert_eq!(query.since, Some(-3));
+        assert_eq!(query.ratio, Some(0.5));
+
+        assert!(parse("limit=5&flag=1").unwrap().flag);
+        assert!(!parse("limit=5&flag=0").unwrap().flag);
+        assert!(!parse("limit=5&flag=false").unwrap().flag);
+    }
+
+    #[test]
+    fn treats_empty_values_as_not_given() {
+        let query = parse("limit=5&since=&ratio=&gym=&gyms=").unwrap();
+        assert_eq!(query.since, None);
+        assert_eq!(query.ratio, None);
+        assert!(query.gym.website().is_none());
+        assert!(query.gyms.0.is_empty());
+    }
+
+    #[test]
+    fn splits_gym_lists_on_commas() {
+        let query = parse("limit=5&gyms=muc-ost,,%20muc-west").unwrap();
+        assert_eq!(slugs(&query.gyms), vec!["muc-ost", "muc-west"]);
+
+        let error = parse("limit=5&gyms=muc-ost,nowhere").unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParameter);
+        assert_eq!(error.details.unwrap()["value"], json!("nowhere"));
+    }
+
+    #[test]
+    fn uses_the_first_of_repeated_parameters() {
+        assert_eq!(parse("limit=5&limit=50").unwrap().limit, 5);
+        assert_eq!(parse("limit=5&limit=abc").unwrap().limit, 5);
+        assert!(parse("limit=abc&limit=5").is_err());
+    }
+
+    #[test]
+    fn reports_missing_parameters() {
+        let error = parse("flag=true").unwrap_err();
+        assert_eq!(error.status(), 400);
+        assert_eq!(error.code, ErrorCode::InvalidParameter);
+        assert_eq!(error.message, "Missing 'limit' parameter");
+        assert_eq!(error.details, Some(json!({"parameter": "limit"})));
+    }
+
+    #[test]
+    fn reports_values_that_do_not_parse() {
+        let error = parse("limit=abc").unwrap_err();
+        assert_eq!(error.status(), 400);
+        assert_eq!(error.message, "Invalid 'limit' parameter, expected a positive integer");
+        assert_eq!(error.details, Some(json!({"parameter": "limit", "value": "abc", "expected": "a positive integer"})));
+
+        let error = parse("limit=5&flag=yes").unwrap_err();
+        assert_eq!(error.details, Some(json!({"parameter": "flag", "value": "yes", "expected": "true or false"})));
+
+        let error = parse("limit=5&gym=nowhere").unwrap_err();
+        assert_eq!(error.details.unwrap()["parameter"], json!("gym"));
+    }
+
+    #[test]
+    fn validates_after_parsing() {
+        let error = parse("limit=500").unwrap_err();
+        assert_eq!(error.status(), 400);
+        assert_eq!(error.details, Some(json!({"parameter": "limit", "value": "500", "expected": "a number from 1 to 100"})));
+    }
+
+    #[test]
+    fn checks_inclusive_ranges() {
+        assert!(check_range("limit", 1, 1..=100).is_ok());
+        assert!(check_range("limit", 100, 1..=100).is_ok());
+        assert!(check_range("limit", 0, 1..=100).is_err());
+        assert!(check_range("limit", 101, 1..=100).is_err());
+        assert!(check_range("ratio", 0.5, 0.0..=1.0).is_ok());
+        assert!(check_range("ratio", 1.5, 0.0..=1.0).is_err());
+    }
+
+    #[test]
+    fn checks_date_ranges() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+
+        assert!(check_date_range(day(1), day(1), 7).is_ok());
+        assert!(check_date_range(day(1), day(8), 7).is_ok());
+
+        let error = check_date_range(day(1), day(9), 7).unwrap_err();
+        assert_eq!(error.details.unwrap()["parameter"], json!("since"));
+
+        let error = check_date_range(day(2), day(1), 7).unwrap_err();
+        assert_eq!(error.details.unwrap()["parameter"], json!("until"));
+    }
+}
@@ -1,7 +1,17 @@
 use worker::*;
 
+use crate::analysis::{self, Anomaly};
 use crate::db;
-use crate::scraper;
+use crate::holidays::{self, SchoolHoliday};
+use crate::i18n;
+use crate::live::{self, LiveAnomaly, LiveForecast, LiveReading, LiveUpdate};
+use crate::push::{self, Delivery};
+use crate::scraper::{self, ScrapedWebsiteData, WebsiteConfig};
+use crate::subscriptions::formatters::{self, Alert, CrowdUpdate, Trend};
+use crate::subscriptions::{self, SubscriptionRecord};
+use crate::telegram;
+use crate::utils;
+use crate::weather::{self, WeatherObservation};
 
 /// Handler for scheduled CRON events
 pub async fn scheduled_handler(_event: ScheduledEvent, env: Env, cron: String) -> Result<()> {
@@ -16,11 +26,11 @@ pub async fn scheduled_handler(_event: ScheduledEvent, env: Env, cron: String) -
     handle_scraping_job(&env).await
 }
 
-/// Handles the daily job to calculate average crowd levels
+/// Handles the daily job to calculate average crowd levels and summarize the previous day
 async fn handle_daily_job(env: &Env) -> Result<()> {
     console_log!("Starting time-based averages calculation job");
     
-    match db::update_time_averages(&env).await {
+    let averages_result = match db::update_time_averages(env).await {
         Ok(_) => {
             console_log!("Successfully updated time-based averages");
             Ok(())
@@ -29,7 +39,20 @@ async fn handle_daily_job(env: &Env) -> Result<()> {
             console_error!("Error updating time-based averages: {}", e);
             Err(e)
         }
+    };
+
+    // The job runs shortly after local midnight, so yesterday is the last complete day
+    let yesterday = utils::utc_to_local(utils::now_utc()).date() - chrono::Duration::days(1);
+
+    match db::update_daily_summaries(env, yesterday).await {
+        Ok(_) => console_log!("Successfully stored daily summaries for {}", yesterday),
+        Err(e) => {
+            console_error!("Error storing daily summaries: {}", e);
+            return Err(e);
+        }
     }
+
+    averages_result
 }
 
 /// Handles the regular scraping job that runs every 10 minutes
@@ -37,12 +60,40 @@ async fn handle_scraping_job(env: &Env) -> Result<()> {
     // Get all configured websites
     let websites = scraper::get_configured_websites();
     let timestamp = Date::now().to_string();
+
+    // School holidays decide which profile new readings are compared against
+    let school_holidays = db::get_school_holidays(env).await.unwrap_or_else(|e| {
+        console_error!("Error loading school holidays, assuming none: {}", e);
+        Vec::new()
+    });
     
     // Track overall success
     let mut success_count = 0;
     
+    let local_now = utils::utc_to_local(utils::now_utc());
+
+    // Threshold alerts are evaluated against every new reading
+    let subscriptions = db::get_subscriptions(env, None).await.unwrap_or_else(|e| {
+        console_error!("Error loading subscriptions, skipping alerts: {}", e);
+        Vec::new()
+    });
+
+    // Weather ingestion is optional and only runs when an API URL is configured
+    let weather_api_url = weather::weather_api_url(env);
+
+    // Readings reference their gym, so newly configured gyms have to exist first
+    if let Err(e) = db::sync_gyms(env).await {
+        console_error!("Error syncing configured gyms: {}", e);
+    }
+
     // Fetch data for all websites
     for website in websites {
+        // Nothing meaningful to record while the gym is closed
+        if !website.opening_hours.is_open_at(local_now) {
+            console_log!("Skipping {} as it is closed at {}", website.name, local_now.format("%H:%M"));
+            continue;
+        }
+
         match scraper::fetch_crowd_data(&website).await {
             Ok(data) => {
                 // Log the data in a structured format
@@ -55,17 +106,67 @@ async fn handle_scraping_job(env: &Env) -> Result<()> {
                     website.url
                 );
                 
+                // Flag flat-lined readings and readings taken while the gym is closed
+                let quality = match assess_reading_quality(env, &website, &data).await {
+                    Ok(quality) => quality,
+                    Err(e) => {
+                        console_error!("Error assessing reading quality for {}: {}", website.name, e);
+                        analysis::QUALITY_OK
+                    }
+                };
+
+                let observation = match weather_api_url.as_deref() {
+                    Some(api_url) => fetch_weather(api_url, &website).await,
+                    None => None,
+                };
+
                 // Store data in D1 database
                 match db::store_crowd_level(
                     env, 
                     &data.crowd_level_percentage,
-                    &data.crowd_level_description,
-                    website.url.as_str(),
-                    website.name.as_str()
+                    website.id,
+                    quality,
+                    observation.as_ref()
                 ).await {
-                    Ok(_) => {
+                    Ok(id) => {
                         console_log!("Successfully stored data for {} in DB", website.name);
                         success_count += 1;
+
+                        let mut update = LiveUpdate {
+                            reading: LiveReading {
+                                gym_slug: website.slug.clone(),
+                                website_url: website.url.clone(),
+                                website_name: website.name.clone(),
+                                percentage: data.details.raw_percentage,
+                                quality: quality.to_string(),
+                                created_at: utils::format_db_timestamp(utils::now_utc()),
+                            },
+                            anomaly: None,
+                            forecast: None,
+                        };
+
+                        // Flagged readings say nothing about how busy the gym really is
+                        if quality == analysis::QUALITY_OK {
+                            match check_for_anomaly(env, id, &website, &data, &school_holidays).await {
+                                Ok(anomaly) => update.anomaly = anomaly.map(|anomaly| LiveAnomaly {
+                                    kind: anomaly.kind.to_string(),
+                                    expected_percentage: anomaly.expected_percentage,
+                                    z_score: anomaly.z_score,
+                                }),
+                                Err(e) => console_error!("Error checking {} reading for anomalies: {}", website.name, e),
+                            }
+
+                            update.forecast = forecast_next_hour(env, &website, &data, &school_holidays).await.unwrap_or_else(|e| {
+                                console_error!("Error forecasting the level of {}: {}", website.name, e);
+                                None
+                            });
+
+                            notify_subscribers(env, &subscriptions, &website, &data).await;
+                        }
+
+                        if let Err(e) = live::publish(env, &update).await {
+                            console_error!("Error publishing live update for {}: {}", website.name, e);
+                        }
                     },
                     Err(e) => console_error!("Error storing data for {} in DB: {}", website.name, e),
                 }
@@ -82,4 +183,209 @@ async fn handle_scraping_job(env: &Env) -> Result<()> {
     console_log!("Scheduled task completed, processed {} websites successfully", success_count);
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Fetches the current weather at a gym, a failure only costs the weather columns of the reading
+async fn fetch_weather(api_url: &str, website: &WebsiteConfig) -> Option<WeatherObservation> {
+    match weather::fetch_current_weather(api_url, website.latitude, website.longitude).await {
+        Ok(observation) => Some(observation),
+        Err(e) => {
+            console_error!("Error fetching weather for {}: {}", website.name, e);
+            None
+        }
+    }
+}
+
+/// Compares a freshly stored reading with the expected profile value and records anomalies
+async fn check_for_anomaly(
+    env: &Env,
+    crowd_level_id: i64,
+    website: &WebsiteConfig,
+    data: &ScrapedWebsiteData,
+    school_holidays: &[SchoolHoliday],
+) -> Result<Option<Anomaly>> {
+    let (day_of_week, hour) = holidays::profile_slot(utils::now_utc(), school_holidays);
+
+    let Some(profile) = db::get_time_average_slot(env, &website.url, day_of_week, hour).await? else {
+        return Ok(None);
+    };
+
+    let expected = profile["average_percentage"].as_f64().unwrap_or(0.0);
+    let stddev = profile["stddev_percentage"].as_f64().unwrap_or(0.0);
+    let sample_count = profile["sample_count"].as_i64().unwrap_or(0);
+
+    let anomaly = analysis::detect_anomaly(data.details.raw_percentage, expected, stddev, sample_count);
+    if let Some(anomaly) = &anomaly {
+        db::store_anomaly(env, crowd_level_id, website, anomaly).await?;
+    }
+
+    Ok(anomaly)
+}
+
+/// Forecasts the level an hour after a fresh reading, like the /compare endpoint does
+async fn forecast_next_hour(
+    env: &Env,
+    website: &WebsiteConfig,
+    data: &ScrapedWebsiteData,
+    school_holidays: &[SchoolHoliday],
+) -> Result<Option<LiveForecast>> {
+    let now = utils::now_utc();
+    let forecast_at = now + chrono::Duration::hours(1);
+
+    let (now_day, now_hour) = holidays::profile_slot(now, school_holidays);
+    let (forecast_day, forecast_hour) = holidays::profile_slot(forecast_at, school_holidays);
+
+    let typical_now = db::get_time_average_slot(env, &website.url, now_day, now_hour).await?
+        .and_then(|record| record["average_percentage"].as_f64());
+    let Some(typical_forecast) = db::get_time_average_slot(env, &website.url, forecast_day, forecast_hour).await?
+        .and_then(|record| record["average_percentage"].as_f64()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(LiveForecast {
+        at: utils::format_db_timestamp(forecast_at),
+        percentage: analysis::forecast_level(Some(data.details.raw_percentage), typical_now, typical_forecast, 1.0),
+    }))
+}
+
+/// Builds the link to a gym's graph from the `PUBLIC_BASE_URL` variable, if configured
+fn public_graph_url(env: &Env, slug: &str) -> Option<String> {
+    formatters::graph_url(&env.var("PUBLIC_BASE_URL").ok()?.to_string(), slug)
+}
+
+/// Evaluates the subscriptions of a gym against a new reading and notifies the matching ones
+async fn notify_subscribers(env: &Env, subscriptions: &[SubscriptionRecord], website: &WebsiteConfig, data: &ScrapedWebsiteData) {
+    let now = utils::now_utc();
+    let local_now = utils::utc_to_local(now);
+    let percentage = data.details.raw_percentage;
+
+    let gym_subscriptions: Vec<&SubscriptionRecord> = subscriptions.iter()
+        .filter(|record| record.website_url == website.url)
+        .collect();
+    if gym_subscriptions.is_empty() {
+        return;
+    }
+
+    // The trend compares with the reading about half an hour ago, the newest one is this reading
+    let previous = match db::get_recent_readings(env, &website.url, 35).await {
+        Ok(recent) => recent.iter()
+            .skip(1)
+            .last()
+            .and_then(|record| record["percentage"].as_str()?.parse::<f64>().ok()),
+        Err(e) => {
+            console_error!("Error loading recent readings of {} for the trend: {}", website.name, e);
+            None
+        }
+    };
+    let trend = Trend::between(previous, percentage);
+
+    for record in gym_subscriptions {
+        let Some(subscription) = record.subscription() else {
+            console_error!("Skipping invalid subscription {}", record.id);
+            continue;
+        };
+
+        let matching = subscription.matches(percentage, local_now);
+        let notify = matching && record.should_notify(now);
+
+        let mut delivered = false;
+        if notify {
+            let language = subscription.language;
+            let description = i18n::describe_level(percentage, &website.crowd_level_bands, language);
+
+            let update = CrowdUpdate {
+                website_name: website.name.clone(),
+                website_url: website.url.clone(),
+                percentage,
+                description: description.to_string(),
+                trend,
+                graph_url: public_graph_url(env, &website.slug),
+                timestamp: now.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                alert: Some(Alert {
+                    subscription_id: record.id,
+                    direction: subscription.direction,
+                    threshold: subscription.threshold,
+                }),
+            };
+
+            let result = match (subscription.telegram_chat_id, subscription.webhook_url.as_deref(), &subscription.push) {
+                (Some(chat_id), _, _) => {
+                    let text = i18n::text(language, "bot.alert")
+                        .replacen("{}", &website.name, 1)
+                        .replacen("{}", i18n::text(language, subscription.direction.text_key()), 1)
+                        .replacen("{}", &subscription.threshold.to_string(), 1)
+                        .replacen("{}", &percentage.to_string(), 1)
+                        .replacen("{}", description, 1);
+                    telegram::send_message(env, chat_id, &text).await
+                },
+                (None, Some(webhook_url), _) => {
+                    let payload = formatters::render(subscription.format, &update, language);
+                    subscriptions::send_webhook(webhook_url, &payload).await
+                },
+                (None, None, Some(target)) => {
+                    let payload = formatters::push_notification(&update, language);
+                    match push::send_notification(env, target, &payload).await {
+                        Ok(Delivery::Sent) => Ok(()),
+                        Ok(Delivery::Expired) => {
+                            // The browser unsubscribed or the permission was revoked
+                            console_log!("Push subscription {} expired, removing it", record.id);
+                            if let Err(e) = db::delete_subscription(env, record.id).await {
+                                console_error!("Error removing expired subscription {}: {}", record.id, e);
+                            }
+                            continue;
+                        },
+                        Err(e) => Err(e),
+                    }
+                },
+                (None, None, None) => Err(Error::from("Subscription has no delivery target")),
+            };
+
+            match result {
+                Ok(_) => {
+                    console_log!("Notified subscription {} about {} at {}%", record.id, website.name, percentage);
+                    delivered = true;
+                },
+                Err(e) => console_error!("Error notifying subscription {}: {}", record.id, e),
+            }
+        }
+
+        // A failed delivery is retried with the next reading
+        let still_matching = matching && (!notify || delivered);
+        if still_matching != (record.matching == 1) || delivered {
+            if let Err(e) = db::update_subscription_state(env, record.id, still_matching, delivered).await {
+                console_error!("Error updating state of subscription {}: {}", record.id, e);
+            }
+        }
+    }
+}
+
+/// Determines the quality flag for a new reading before it is stored
+///
+/// When the reading completes a flat-lined sequence, the earlier readings of that
+/// sequence are re-flagged as stuck, or as closed for a run of zeros, as well.
+/// Outside the configured opening hours nothing is scraped, so closed readings
+/// are usually inferred from such runs.
+pub async fn assess_reading_quality(env: &Env, website: &WebsiteConfig, data: &ScrapedWebsiteData) -> Result<&'static str> {
+    let is_open = website.opening_hours.is_open_at(utils::utc_to_local(utils::now_utc()));
+    let window_minutes = (analysis::STUCK_MIN_READINGS as i64) * 10 + 5;
+
+    let recent = db::get_recent_readings(env, &website.url, window_minutes).await?;
+    let previous: Vec<f64> = recent.iter()
+        .map(|record| record["percentage"].as_str().unwrap_or("").parse::<f64>().unwrap_or(-1.0))
+        .collect();
+
+    let quality = analysis::classify_reading(data.details.raw_percentage, is_open, &previous);
+
+    if is_open && quality != analysis::QUALITY_OK {
+        let flagged_ids: Vec<i64> = recent.iter()
+            .take(analysis::STUCK_MIN_READINGS - 1)
+            .filter(|record| record["quality"].as_str() == Some(analysis::QUALITY_OK))
+            .filter_map(|record| record["id"].as_i64())
+            .collect();
+
+        db::mark_readings_quality(env, &flagged_ids, quality).await?;
+        console_log!("Detected flat-lined readings for {} at {}%, flagged as {}", website.name, data.details.raw_percentage, quality);
+    }
+
+    Ok(quality)
+}
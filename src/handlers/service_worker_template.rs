@@ -0,0 +1,31 @@
+/// Service worker registered by the graph page, shows push notifications and opens the graph on click
+pub const SCRIPT: &str = r#"self.addEventListener('install', () => {
+    self.skipWaiting();
+});
+
+self.addEventListener('activate', event => {
+    event.waitUntil(self.clients.claim());
+});
+
+self.addEventListener('push', event => {
+    if (!event.data) {
+        return;
+    }
+
+    const message = event.data.json();
+    const url = message.url || '/graph?url=' + encodeURIComponent(message.website_url) + '&days=1';
+
+    event.waitUntil(self.registration.showNotification(message.title, {
+        body: message.body,
+        tag: message.website_url,
+        renotify: true,
+        timestamp: Date.parse(message.timestamp) || Date.now(),
+        data: { url: url }
+    }));
+});
+
+self.addEventListener('notificationclick', event => {
+    event.notification.close();
+    event.waitUntil(self.clients.openWindow(event.notification.data.url));
+});
+"#;
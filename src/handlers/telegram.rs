@@ -0,0 +1,200 @@
+use chrono::{NaiveTime, Timelike};
+use worker::*;
+
+use crate::db;
+use crate::error::{ApiError, ApiResult};
+use crate::holidays;
+use crate::i18n::{self, Language};
+use crate::scraper::{self, WebsiteConfig};
+use crate::subscriptions::formatters::MessageFormat;
+use crate::subscriptions::{self, Direction, Subscription};
+use crate::telegram::{self, Update};
+use crate::utils;
+
+/// Handler for the /telegram/webhook endpoint - answers bot commands sent to the chat
+///
+/// Telegram retries updates that don't get a 200 response, so failures are only logged.
+pub async fn telegram_webhook_handler(mut req: Request, env: Env) -> ApiResult<Response> {
+    if !telegram::is_authorized(&req, &env) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let update: Update = match req.json().await {
+        Ok(update) => update,
+        Err(e) => return Err(ApiError::invalid_body(format!("Invalid Telegram update: {}", e))),
+    };
+
+    let Some(message) = update.message else {
+        return Ok(Response::ok("")?);
+    };
+    let Some((command, args)) = message.text.as_deref().and_then(telegram::parse_command) else {
+        return Ok(Response::ok("")?);
+    };
+
+    let language = message.from
+        .and_then(|user| user.language_code)
+        .and_then(|code| Language::parse(&code))
+        .unwrap_or_default();
+    let chat_id = message.chat.id;
+
+    let reply = match command.as_str() {
+        "start" | "help" => Ok(i18n::text(language, "bot.help").to_string()),
+        "now" => current_levels(&env, language).await,
+        "best" => best_times_today(&env, language).await,
+        "subscribe" => subscribe(&env, chat_id, &args, language).await,
+        "unsubscribe" => db::delete_telegram_subscriptions(&env, chat_id).await
+            .map(|count| i18n::text(language, "bot.unsubscribed").replacen("{}", &count.to_string(), 1)),
+        _ => Ok(i18n::text(language, "bot.unknown_command").to_string()),
+    };
+
+    let reply = reply.unwrap_or_else(|e| {
+        console_error!("Error answering Telegram command /{}: {}", command, e);
+        i18n::text(language, "status.error").to_string()
+    });
+
+    if let Err(e) = telegram::send_message(&env, chat_id, &reply).await {
+        console_error!("Error sending Telegram reply to {}: {}", chat_id, e);
+    }
+
+    Ok(Response::ok("")?)
+}
+
+/// /now - the latest reading of every gym
+async fn current_levels(env: &Env, language: Language) -> Result<String> {
+    let local_now = utils::utc_to_local(utils::now_utc());
+    let mut lines = Vec::new();
+
+    for website in scraper::get_configured_websites() {
+        if !website.opening_hours.is_open_at(local_now) {
+            let status = website.opening_hours.status_at(local_now, language);
+            lines.push(format!("{}: {}", website.name, status.message));
+            continue;
+        }
+
+        // A gym without any record yet is reported as having no data
+        let latest = db::get_latest_crowd_level(env, Some(&website.url), language).await?.unwrap_or_default();
+        let line = match latest["details"]["raw_percentage"].as_f64() {
+            Some(percentage) => format!(
+                "{}: {}% ({})",
+                website.name,
+                percentage,
+                latest["crowd_level_description"].as_str().unwrap_or_default()
+            ),
+            None => format!("{}: {}", website.name, i18n::text(language, "bot.no_data")),
+        };
+        lines.push(line);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// /best today - the hour with the lowest typical level for the rest of today, per gym
+async fn best_times_today(env: &Env, language: Language) -> Result<String> {
+    let weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Holiday"];
+    let school_holidays = db::get_school_holidays(env).await?;
+    let local_now = utils::utc_to_local(utils::now_utc());
+
+    let mut best: Vec<(String, Option<(u32, f64)>)> = Vec::new();
+
+    for website in scraper::get_configured_websites() {
+        let averages = db::get_time_averages(env, Some(&website.url)).await?;
+        let profile = &averages["data"][&website.name];
+
+        // The current hour and every following full hour in which the gym is open
+        let candidates = (local_now.hour()..24).filter_map(|hour| {
+            let slot_start = local_now.date().and_time(NaiveTime::from_hms_opt(hour, 0, 0)?);
+            let at = slot_start.max(local_now);
+            if !website.opening_hours.is_open_at(at) {
+                return None;
+            }
+
+            let (day_of_week, utc_hour) = holidays::profile_slot(utils::local_to_utc(slot_start), &school_holidays);
+            let average = profile[weekdays[day_of_week as usize]][utc_hour.to_string()]["average"].as_f64()?;
+            Some((hour, average))
+        });
+
+        let quietest = candidates.fold(None, |quietest: Option<(u32, f64)>, candidate| match quietest {
+            Some(current) if current.1 <= candidate.1 => Some(current),
+            _ => Some(candidate),
+        });
+
+        best.push((website.name, quietest));
+    }
+
+    // Quietest gym first, gyms without data at the end
+    best.sort_by(|a, b| {
+        let level = |entry: &(String, Option<(u32, f64)>)| entry.1.map(|(_, level)| level).unwrap_or(f64::MAX);
+        level(a).total_cmp(&level(b))
+    });
+
+    let mut lines = vec![i18n::text(language, "bot.best_heading").to_string()];
+    for (name, quietest) in best {
+        lines.push(match quietest {
+            Some((hour, level)) => format!("{}: {:02}:00 (~{}%)", name, hour, level.round()),
+            None => format!("{}: {}", name, i18n::text(language, "bot.best_none")),
+        });
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Finds the configured gym whose name contains the given text, if exactly one does
+fn find_gym(query: &str) -> Option<WebsiteConfig> {
+    let query = query.to_lowercase();
+    let mut matches = scraper::get_configured_websites().into_iter()
+        .filter(|website| website.name.to_lowercase().contains(&query));
+
+    match (matches.next(), matches.next()) {
+        (Some(website), None) => Some(website),
+        _ => None,
+    }
+}
+
+/// /subscribe <gym> below|above <percent> - alerts the chat when the gym crosses a level
+async fn subscribe(env: &Env, chat_id: i64, args: &[String], language: Language) -> Result<String> {
+    let usage = || Ok(i18n::text(language, "bot.subscribe_usage").to_string());
+
+    // The gym name may contain spaces, everything before the direction belongs to it
+    let Some(direction_idx) = args.iter().position(|arg| Direction::parse(&arg.to_lowercase()).is_some()) else {
+        return usage();
+    };
+    let gym_query = args[..direction_idx].join(" ");
+    let direction = Direction::parse(&args[direction_idx].to_lowercase());
+    let threshold = args.get(direction_idx + 1)
+        .and_then(|value| value.trim_end_matches('%').parse::<f64>().ok());
+
+    let (Some(direction), Some(threshold)) = (direction, threshold) else {
+        return usage();
+    };
+    if gym_query.is_empty() {
+        return usage();
+    }
+
+    let Some(website) = find_gym(&gym_query) else {
+        return Ok(i18n::text(language, "bot.unknown_gym").replacen("{}", &gym_query, 1));
+    };
+
+    let subscription = Subscription {
+        website_url: website.url.clone(),
+        threshold,
+        direction,
+        windows: Vec::new(),
+        webhook_url: None,
+        telegram_chat_id: Some(chat_id),
+        push: None,
+        format: MessageFormat::Json,
+        cooldown_minutes: subscriptions::DEFAULT_COOLDOWN_MINUTES,
+        language,
+    };
+
+    if let Err(message) = subscription.validate() {
+        return Ok(message);
+    }
+
+    db::store_subscription(env, &subscription).await?;
+
+    Ok(i18n::text(language, "bot.subscribed")
+        .replacen("{}", &website.name, 1)
+        .replacen("{}", i18n::text(language, direction.text_key()), 1)
+        .replacen("{}", &threshold.to_string(), 1))
+}
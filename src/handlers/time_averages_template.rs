@@ -1,14 +1,31 @@
 use serde_json::Value;
 
-pub fn get_time_averages_html(data: Value) -> String {
+use crate::i18n::{self, Language};
+
+pub fn get_time_averages_html(data: Value, language: Language) -> String {
     let data_str = serde_json::to_string(&data).unwrap();
+    let t = |key| i18n::text(language, key);
+
+    // Dataset labels, indexed like the weekday keys in the data
+    let day_labels: Vec<&str> = (0..8).map(|day| i18n::weekday_name(language, day)).collect();
+
+    // Monday first, with the holiday profile at the end
+    let mut day_options = String::new();
+    for day in [1, 2, 3, 4, 5, 6, 0, 7] {
+        day_options.push_str(&format!(
+            "\n                    <option value=\"{}\">{}</option>",
+            day,
+            i18n::weekday_name(language, day)
+        ));
+    }
+
     format!(
         r##"<!DOCTYPE html>
-<html lang="en">
+<html lang="{lang}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Crowd Level Time Averages</title>
+    <title>{title}</title>
     <script src="https://cdn.jsdelivr.net/npm/chart.js"></script>
     <script src="https://cdn.jsdelivr.net/npm/chartjs-plugin-zoom"></script>
     <style>
@@ -116,43 +133,33 @@ pub fn get_time_averages_html(data: Value) -> String {
 </head>
 <body>
     <div class="container">
-        <div class="nav-bar">
-            <a href="/graph?url=all&days=1&offset=0" class="nav-link">Live Graph</a>
-            <a href="/time-averages-view" class="nav-link active">Time Averages</a>
-        </div>
+        {nav_bar}
 
-        <h1>Crowd Level Time Averages</h1>
+        <h1>{title}</h1>
 
         <div class="controls">
             <div>
-                <label for="gymSelect">Gym Location:</label>
+                <label for="gymSelect">{label_gym}</label>
                 <select id="gymSelect" onchange="updateChart()">
-                    <option value="all">All Gyms</option>
+                    <option value="all">{all_gyms}</option>
                 </select>
             </div>
             <div>
-                <label for="daySelect">Day of Week:</label>
+                <label for="daySelect">{label_day_of_week}</label>
                 <select id="daySelect" onchange="updateChart()">
-                    <option value="all">All Days</option>
-                    <option value="1">Monday</option>
-                    <option value="2">Tuesday</option>
-                    <option value="3">Wednesday</option>
-                    <option value="4">Thursday</option>
-                    <option value="5">Friday</option>
-                    <option value="6">Saturday</option>
-                    <option value="0">Sunday</option>
+                    <option value="all">{all_days}</option>{day_options}
                 </select>
             </div>
-            <button id="resetZoom" onclick="resetZoom()" style="display: none;">Reset Zoom</button>
+            <button id="resetZoom" onclick="resetZoom()" style="display: none;">{button_reset_zoom}</button>
         </div>
         <div style="text-align: center; margin-top: 5px; font-size: 0.9rem; color: #666;">
-            <p>Tip: Click and drag on the graph to zoom into a specific area</p>
+            <p>{tip_zoom}</p>
         </div>
 
         <div class="chart-wrapper">
             <div class="loading-overlay" id="loadingOverlay">
                 <div class="spinner"></div>
-                <span>Loading data...</span>
+                <span>{status_loading}</span>
             </div>
             <div class="chart-container">
                 <canvas id="averagesChart"></canvas>
@@ -161,9 +168,10 @@ pub fn get_time_averages_html(data: Value) -> String {
     </div>
 
     <script>
-        const rawData = {0};
-        const weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
-        const displayWeekdays = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        const rawData = {data};
+        const weekdays = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Holiday"];
+        const displayWeekdays = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday", "Holiday"];
+        const dayLabels = {day_labels};
         let chart = null;
 
         // Color management for consistent gym colors
@@ -300,7 +308,7 @@ pub fn get_time_averages_html(data: Value) -> String {
                                 data[localHour] = value.average;
                             }});
                             datasets.push({{
-                                label: `${{gym}} - ${{day}}`,
+                                label: `${{gym}} - ${{dayLabels[weekdays.indexOf(day)]}}`,
                                 data: data,
                                 borderColor: getColorForGymAndDay(gym, day),
                                 fill: false,
@@ -353,7 +361,7 @@ pub fn get_time_averages_html(data: Value) -> String {
                                 data[localHour] = value.average;
                             }});
                             datasets.push({{
-                                label: day,
+                                label: dayLabels[weekdays.indexOf(day)],
                                 data: data,
                                 borderColor: getColorForGymAndDay(selectedGym, day),
                                 fill: false,
@@ -374,7 +382,7 @@ pub fn get_time_averages_html(data: Value) -> String {
                                 data[localHour] = value.average;
                             }});
                             datasets.push({{
-                                label: day,
+                                label: dayLabels[weekdays.indexOf(day)],
                                 data: data,
                                 borderColor: getGymColor(selectedGym),
                                 fill: false,
@@ -431,13 +439,13 @@ pub fn get_time_averages_html(data: Value) -> String {
                             max: 100,
                             title: {{
                                 display: true,
-                                text: 'Average Crowd Level (%)'
+                                text: '{chart_average_crowd_level}'
                             }}
                         }},
                         x: {{
                             title: {{
                                 display: true,
-                                text: 'Hour of Day'
+                                text: '{chart_hour_of_day}'
                             }},
                             ticks: {{
                                 maxRotation: 0,
@@ -500,6 +508,20 @@ pub fn get_time_averages_html(data: Value) -> String {
     </script>
 </body>
 </html>"##,
-        data_str
+        data = data_str,
+        lang = language.code(),
+        nav_bar = i18n::nav_bar(language, "time_averages"),
+        title = t("time_averages.title"),
+        label_gym = t("label.gym"),
+        all_gyms = t("option.all_gyms"),
+        label_day_of_week = t("label.day_of_week"),
+        all_days = t("option.all_days"),
+        day_options = day_options,
+        day_labels = serde_json::to_string(&day_labels).unwrap(),
+        button_reset_zoom = t("button.reset_zoom"),
+        tip_zoom = t("tip.zoom"),
+        status_loading = t("status.loading"),
+        chart_average_crowd_level = t("chart.average_crowd_level"),
+        chart_hour_of_day = t("chart.hour_of_day")
     )
 } 
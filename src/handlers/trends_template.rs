@@ -0,0 +1,383 @@
+use crate::analysis::TrendPeriod;
+use crate::i18n::{self, Language};
+use crate::scraper::WebsiteConfig;
+
+/// Generate HTML for the trends view, `selected_website` is the slug of the selected gym
+pub fn generate_html(websites: &[WebsiteConfig], selected_website: Option<&str>, period: TrendPeriod, language: Language) -> String {
+    let t = |key| i18n::text(language, key);
+
+    // Generate website options HTML, defaulting to the first gym
+    let selected_website = selected_website
+        .or_else(|| websites.first().map(|website| website.slug.as_str()));
+    let mut website_options = String::new();
+    for website in websites {
+        let selected = selected_website.is_some_and(|s| s == website.slug);
+        website_options.push_str(&format!(
+            "<option value=\"{}\" {}>{}</option>",
+            website.slug,
+            if selected { "selected" } else { "" },
+            website.name
+        ));
+    }
+
+    // Generate period options HTML
+    let mut period_options = String::new();
+    for (value, label) in [(TrendPeriod::Week, t("option.weekly")), (TrendPeriod::Month, t("option.monthly"))] {
+        period_options.push_str(&format!(
+            "<option value=\"{}\" {}>{}</option>",
+            value.as_str(),
+            if value == period { "selected" } else { "" },
+            label
+        ));
+    }
+
+    // Build the HTML
+    format!(r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+    <script src="https://cdn.jsdelivr.net/npm/chart.js"></script>
+    <style>
+        body {{
+            font-family: Arial, sans-serif;
+            margin: 0;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+        .container {{
+            max-width: 1200px;
+            margin: 0 auto;
+            background-color: white;
+            padding: 20px;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+        }}
+        .nav-bar {{
+            display: flex;
+            justify-content: center;
+            gap: 20px;
+            margin-bottom: 20px;
+            padding: 10px;
+            border-bottom: 1px solid #ddd;
+        }}
+        .nav-link {{
+            text-decoration: none;
+            color: #4CAF50;
+            padding: 5px 10px;
+            border-radius: 4px;
+            transition: background-color 0.2s;
+        }}
+        .nav-link.active {{
+            background-color: #4CAF50;
+            color: white;
+        }}
+        .nav-link:hover:not(.active) {{
+            background-color: #e8f5e9;
+        }}
+        h1 {{
+            color: #333;
+            text-align: center;
+            margin-bottom: 30px;
+        }}
+        .controls {{
+            display: flex;
+            justify-content: space-between;
+            margin-bottom: 20px;
+            gap: 10px;
+        }}
+        select, button {{
+            padding: 8px 12px;
+            border-radius: 4px;
+            border: 1px solid #ddd;
+        }}
+        button {{
+            background-color: #4CAF50;
+            color: white;
+            border: none;
+            cursor: pointer;
+        }}
+        button:hover {{
+            background-color: #45a049;
+        }}
+        .chart-container {{
+            height: 500px;
+            margin-top: 20px;
+        }}
+        .loading-overlay {{
+            position: absolute;
+            top: 0;
+            left: 0;
+            right: 0;
+            bottom: 0;
+            background-color: rgba(255, 255, 255, 0.7);
+            display: flex;
+            justify-content: center;
+            align-items: center;
+            z-index: 1000;
+            font-size: 1.2rem;
+            visibility: hidden;
+        }}
+        .chart-wrapper {{
+            position: relative;
+        }}
+        table {{
+            width: 100%;
+            border-collapse: collapse;
+            margin-top: 30px;
+            font-size: 0.9rem;
+        }}
+        th, td {{
+            padding: 6px 8px;
+            border-bottom: 1px solid #ddd;
+            text-align: right;
+        }}
+        th:first-child, td:first-child {{
+            text-align: left;
+        }}
+        .up {{
+            color: #d92626;
+        }}
+        .down {{
+            color: #2E7D32;
+        }}
+        .spinner {{
+            border: 4px solid #f3f3f3;
+            border-top: 4px solid #4CAF50;
+            border-radius: 50%;
+            width: 30px;
+            height: 30px;
+            animation: spin 1s linear infinite;
+            margin-right: 10px;
+        }}
+        @keyframes spin {{
+            0% {{ transform: rotate(0deg); }}
+            100% {{ transform: rotate(360deg); }}
+        }}
+        @media (max-width: 768px) {{
+            .controls {{
+                flex-direction: column;
+            }}
+        }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        {nav_bar}
+
+        <h1>{title}</h1>
+
+        <div class="controls">
+            <div>
+                <label for="website">{label_gym}</label>
+                <select id="website" onchange="loadTrends()">
+                    {website_options}
+                </select>
+            </div>
+            <div>
+                <label for="period">{label_period}</label>
+                <select id="period" onchange="loadTrends()">
+                    {period_options}
+                </select>
+            </div>
+        </div>
+
+        <div class="chart-wrapper">
+            <div class="loading-overlay" id="loadingOverlay">
+                <div class="spinner"></div>
+                <span>{status_loading}</span>
+            </div>
+            <div class="chart-container">
+                <canvas id="trendsChart"></canvas>
+            </div>
+        </div>
+
+        <table>
+            <thead>
+                <tr>
+                    <th>{th_period}</th>
+                    <th>{th_mean}</th>
+                    <th>{th_peak}</th>
+                    <th>{th_busy_hours}</th>
+                    <th>{th_vs_previous}</th>
+                    <th>{th_vs_last_year}</th>
+                </tr>
+            </thead>
+            <tbody id="trendsTable"></tbody>
+        </table>
+    </div>
+
+    <script>
+        let chart = null;
+
+        // Show loading indicator
+        function showLoading() {{
+            document.getElementById('loadingOverlay').style.visibility = 'visible';
+        }}
+
+        // Hide loading indicator
+        function hideLoading() {{
+            document.getElementById('loadingOverlay').style.visibility = 'hidden';
+        }}
+
+        function formatChange(change) {{
+            if (change === null || change === undefined) {{
+                return '–';
+            }}
+            const cssClass = change > 0 ? 'up' : (change < 0 ? 'down' : '');
+            const sign = change > 0 ? '+' : '';
+            return `<span class="${{cssClass}}">${{sign}}${{change.toFixed(1)}}</span>`;
+        }}
+
+        function renderTable(periods) {{
+            document.getElementById('trendsTable').innerHTML = periods.slice().reverse().map(period => `
+                <tr>
+                    <td>${{period.period_start}}</td>
+                    <td>${{period.mean_percentage.toFixed(1)}}%</td>
+                    <td>${{period.peak_percentage.toFixed(0)}}%</td>
+                    <td>${{period.busy_hours}}</td>
+                    <td>${{formatChange(period.change_vs_previous && period.change_vs_previous.mean_percentage)}}</td>
+                    <td>${{formatChange(period.change_vs_last_year && period.change_vs_last_year.mean_percentage)}}</td>
+                </tr>`).join('');
+        }}
+
+        function renderChart(periods) {{
+            const ctx = document.getElementById('trendsChart').getContext('2d');
+
+            if (chart) {{
+                chart.destroy();
+            }}
+
+            chart = new Chart(ctx, {{
+                type: 'bar',
+                data: {{
+                    labels: periods.map(period => period.period_start),
+                    datasets: [
+                        {{
+                            label: 'Mean Occupancy (%)',
+                            data: periods.map(period => period.mean_percentage),
+                            backgroundColor: 'rgba(76, 175, 80, 0.6)',
+                            yAxisID: 'y'
+                        }},
+                        {{
+                            label: 'Peak Occupancy (%)',
+                            data: periods.map(period => period.peak_percentage),
+                            backgroundColor: 'rgba(46, 125, 50, 0.3)',
+                            yAxisID: 'y'
+                        }},
+                        {{
+                            type: 'line',
+                            label: 'Mean Last Year (%)',
+                            data: periods.map(period => period.change_vs_last_year
+                                ? period.mean_percentage - period.change_vs_last_year.mean_percentage
+                                : null),
+                            borderColor: '#8b26d9',
+                            borderDash: [5, 5],
+                            fill: false,
+                            spanGaps: false,
+                            yAxisID: 'y'
+                        }},
+                        {{
+                            type: 'line',
+                            label: 'Busy Hours',
+                            data: periods.map(period => period.busy_hours),
+                            borderColor: '#FF9F40',
+                            fill: false,
+                            tension: 0.2,
+                            yAxisID: 'busy'
+                        }}
+                    ]
+                }},
+                options: {{
+                    responsive: true,
+                    maintainAspectRatio: false,
+                    scales: {{
+                        y: {{
+                            beginAtZero: true,
+                            max: 100,
+                            title: {{
+                                display: true,
+                                text: '{chart_crowd_level}'
+                            }}
+                        }},
+                        busy: {{
+                            beginAtZero: true,
+                            position: 'right',
+                            grid: {{
+                                drawOnChartArea: false
+                            }},
+                            title: {{
+                                display: true,
+                                text: '{th_busy_hours}'
+                            }}
+                        }}
+                    }},
+                    plugins: {{
+                        title: {{
+                            display: true,
+                            text: 'Occupancy per Period',
+                            font: {{
+                                size: 16
+                            }}
+                        }}
+                    }}
+                }}
+            }});
+        }}
+
+        async function loadTrends() {{
+            showLoading();
+
+            const website = document.getElementById('website').value;
+            const period = document.getElementById('period').value;
+
+            // Keep the selection in the URL
+            const url = new URL(window.location);
+            url.searchParams.set('url', website);
+            url.searchParams.set('period', period);
+            window.history.pushState({{}}, '', url);
+
+            try {{
+                const response = await fetch('/trends?url=' + encodeURIComponent(website) + '&period=' + period);
+                const result = await response.json();
+
+                if (!result.data || !Array.isArray(result.data)) {{
+                    throw new Error('Invalid data received from server');
+                }}
+
+                renderChart(result.data);
+                renderTable(result.data);
+            }} catch (error) {{
+                console.error('Error loading trends:', error);
+                alert('{status_error}');
+            }}
+
+            hideLoading();
+        }}
+
+        // Load the trends when page loads
+        document.addEventListener('DOMContentLoaded', function() {{
+            loadTrends();
+        }});
+    </script>
+</body>
+</html>"#,
+        website_options = website_options,
+        period_options = period_options,
+        lang = language.code(),
+        nav_bar = i18n::nav_bar(language, "trends"),
+        title = t("trends.title"),
+        label_gym = t("label.gym"),
+        label_period = t("label.period"),
+        status_loading = t("status.loading"),
+        status_error = t("status.error"),
+        th_period = t("trends.period"),
+        th_mean = t("trends.mean"),
+        th_peak = t("trends.peak"),
+        th_busy_hours = t("trends.busy_hours"),
+        th_vs_previous = t("trends.vs_previous"),
+        th_vs_last_year = t("trends.vs_last_year"),
+        chart_crowd_level = t("chart.crowd_level")
+    )
+}
@@ -0,0 +1,223 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Pseudo day of week under which the holiday profile is stored in `time_averages`
+pub const HOLIDAY_DAY_OF_WEEK: i64 = 7;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PublicHoliday {
+    pub date: String,
+    pub name: &'static str,
+}
+
+/// A school holiday period as uploaded by an admin, both dates inclusive (YYYY-MM-DD)
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct SchoolHoliday {
+    pub name: String,
+    pub start_date: String,
+    pub end_date: String,
+}
+
+impl SchoolHoliday {
+    /// Returns the parsed inclusive date range, or None if the dates are invalid
+    pub fn range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let start = NaiveDate::parse_from_str(&self.start_date, "%Y-%m-%d").ok()?;
+        let end = NaiveDate::parse_from_str(&self.end_date, "%Y-%m-%d").ok()?;
+        if end < start {
+            return None;
+        }
+        Some((start, end))
+    }
+}
+
+/// Calculates Easter Sunday for the given year (anonymous Gregorian algorithm)
+pub fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap_or_default()
+}
+
+/// Returns the public holidays observed in Munich for the given year
+pub fn bavarian_public_holidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let fixed = |month: u32, day: u32| NaiveDate::from_ymd_opt(year, month, day).unwrap_or_default();
+    let easter = easter_sunday(year);
+
+    let mut holidays = vec![
+        (fixed(1, 1), "Neujahr"),
+        (fixed(1, 6), "Heilige Drei Könige"),
+        (easter - Duration::days(2), "Karfreitag"),
+        (easter + Duration::days(1), "Ostermontag"),
+        (fixed(5, 1), "Tag der Arbeit"),
+        (easter + Duration::days(39), "Christi Himmelfahrt"),
+        (easter + Duration::days(50), "Pfingstmontag"),
+        (easter + Duration::days(60), "Fronleichnam"),
+        (fixed(8, 15), "Mariä Himmelfahrt"),
+        (fixed(10, 3), "Tag der Deutschen Einheit"),
+        (fixed(11, 1), "Allerheiligen"),
+        (fixed(12, 25), "1. Weihnachtstag"),
+        (fixed(12, 26), "2. Weihnachtstag"),
+    ];
+    holidays.sort_by_key(|(date, _)| *date);
+    holidays
+}
+
+/// Returns the public holidays for a year in their serializable form
+pub fn public_holidays_for_year(year: i32) -> Vec<PublicHoliday> {
+    bavarian_public_holidays(year)
+        .into_iter()
+        .map(|(date, name)| PublicHoliday {
+            date: date.format("%Y-%m-%d").to_string(),
+            name,
+        })
+        .collect()
+}
+
+/// Checks whether a local date is a public holiday or falls into a school holiday
+pub fn is_holiday(date: NaiveDate, school_holidays: &[SchoolHoliday]) -> bool {
+    let is_public = bavarian_public_holidays(date.year())
+        .iter()
+        .any(|(holiday, _)| *holiday == date);
+
+    is_public || school_holidays.iter()
+        .filter_map(|holiday| holiday.range())
+        .any(|(start, end)| date >= start && date <= end)
+}
+
+/// Returns the `time_averages` day of week whose profile applies to the given local date
+pub fn profile_day(date: NaiveDate, school_holidays: &[SchoolHoliday]) -> i64 {
+    if is_holiday(date, school_holidays) {
+        HOLIDAY_DAY_OF_WEEK
+    } else {
+        utils::weekday_index(date.weekday())
+    }
+}
+
+/// Returns all holidays between `from` and `to` (inclusive) merged into
+/// contiguous half-open local date ranges
+pub fn holiday_ranges(from: NaiveDate, to: NaiveDate, school_holidays: &[SchoolHoliday]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    let mut day = from;
+
+    while day <= to {
+        if is_holiday(day, school_holidays) {
+            let next = day + Duration::days(1);
+            match ranges.last_mut() {
+                Some((_, end)) if *end == day => *end = next,
+                _ => ranges.push((day, next)),
+            }
+        }
+        day += Duration::days(1);
+    }
+
+    ranges
+}
+
+/// Returns the `time_averages` (day_of_week, hour) slot that describes the given UTC instant
+///
+/// Averages are bucketed by UTC weekday and hour, except on local holidays
+/// where the holiday profile applies instead.
+pub fn profile_slot(utc: NaiveDateTime, school_holidays: &[SchoolHoliday]) -> (i64, i64) {
+    let local_date = utils::utc_to_local(utc).date();
+    let hour = utc.hour() as i64;
+
+    if is_holiday(local_date, school_holidays) {
+        (HOLIDAY_DAY_OF_WEEK, hour)
+    } else {
+        (utils::weekday_index(utc.weekday()), hour)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        date(year, month, day).and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn summer_holidays() -> Vec<SchoolHoliday> {
+        vec![SchoolHoliday {
+            name: "Sommerferien".to_string(),
+            start_date: "2025-08-01".to_string(),
+            end_date: "2025-09-15".to_string(),
+        }]
+    }
+
+    #[test]
+    fn calculates_easter_sunday() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2038), date(2038, 4, 25));
+    }
+
+    #[test]
+    fn lists_the_bavarian_public_holidays() {
+        let holidays: Vec<(String, &str)> = public_holidays_for_year(2025)
+            .into_iter()
+            .map(|holiday| (holiday.date, holiday.name))
+            .collect();
+
+        let expected = [
+            ("2025-01-01", "Neujahr"),
+            ("2025-01-06", "Heilige Drei Könige"),
+            ("2025-04-18", "Karfreitag"),
+            ("2025-04-21", "Ostermontag"),
+            ("2025-05-01", "Tag der Arbeit"),
+            ("2025-05-29", "Christi Himmelfahrt"),
+            ("2025-06-09", "Pfingstmontag"),
+            ("2025-06-19", "Fronleichnam"),
+            ("2025-08-15", "Mariä Himmelfahrt"),
+            ("2025-10-03", "Tag der Deutschen Einheit"),
+            ("2025-11-01", "Allerheiligen"),
+            ("2025-12-25", "1. Weihnachtstag"),
+            ("2025-12-26", "2. Weihnachtstag"),
+        ];
+        let expected: Vec<(String, &str)> = expected.iter()
+            .map(|(date, name)| (date.to_string(), *name))
+            .collect();
+
+        assert_eq!(holidays, expected);
+    }
+
+    #[test]
+    fn uses_the_holiday_profile_on_holidays() {
+        // Heilige Drei Könige, a Monday
+        assert_eq!(profile_slot(utc(2025, 1, 6, 12, 0), &[]), (HOLIDAY_DAY_OF_WEEK, 12));
+        // A Tuesday in the summer school holidays
+        assert_eq!(profile_slot(utc(2025, 8, 5, 10, 0), &summer_holidays()), (HOLIDAY_DAY_OF_WEEK, 10));
+        // The same Tuesday without school holidays, and an ordinary Tuesday
+        assert_eq!(profile_slot(utc(2025, 8, 5, 10, 0), &[]), (2, 10));
+        assert_eq!(profile_slot(utc(2025, 1, 7, 12, 0), &[]), (2, 12));
+    }
+
+    #[test]
+    fn decides_holidays_by_the_local_date() {
+        // 00:30 on Mariä Himmelfahrt in Munich (CEST) is still the 14th in UTC
+        assert_eq!(profile_slot(utc(2025, 8, 14, 22, 30), &[]), (HOLIDAY_DAY_OF_WEEK, 22));
+        // 00:30 on the day after is still the holiday in UTC, but not locally
+        assert_eq!(profile_slot(utc(2025, 8, 15, 22, 30), &[]), (5, 22));
+        // The last evening before the school holidays start locally
+        assert_eq!(profile_slot(utc(2025, 7, 31, 21, 59), &summer_holidays()), (4, 21));
+        assert_eq!(profile_slot(utc(2025, 7, 31, 22, 0), &summer_holidays()), (HOLIDAY_DAY_OF_WEEK, 22));
+    }
+}
@@ -0,0 +1,257 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Upper bounds of the Very low / Low / Moderate / High bands, anything above is Very high
+pub const DEFAULT_CROWD_LEVEL_BANDS: [f64; 4] = [20.0, 40.0, 60.0, 80.0];
+
+/// Languages the API and HTML views can be rendered in
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+        }
+    }
+
+    /// Parses a language tag such as "de", "de-DE" or "en-US"
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::En),
+            "de" => Some(Language::De),
+            _ => None,
+        }
+    }
+
+    /// Determines the language from the `lang` query parameter, falling back to
+    /// the preferred supported entry of the Accept-Language header and then English
+    pub fn from_request(req: &Request) -> Self {
+        let from_query = req.url().ok().and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "lang")
+                .and_then(|(_, value)| Language::parse(&value))
+        });
+
+        if let Some(language) = from_query {
+            return language;
+        }
+
+        let accept_language = req.headers().get("Accept-Language").ok().flatten().unwrap_or_default();
+        Language::from_accept_language(&accept_language).unwrap_or(Language::En)
+    }
+
+    /// Picks the supported language with the highest q-value (RFC 9110 12.5.4), earlier entries
+    /// win ties and entries with q=0 or a malformed q-value are not acceptable
+    fn from_accept_language(header: &str) -> Option<Self> {
+        let mut candidates: Vec<(Language, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let language = Language::parse(parts.next()?)?;
+                let quality = match parts.find_map(|param| param.trim().strip_prefix("q=")) {
+                    Some(value) => value.trim().parse::<f32>().ok()?,
+                    None => 1.0,
+                };
+                Some((language, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+
+        // sort_by is stable, so the header order decides between equal q-values
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.first().map(|(language, _)| *language)
+    }
+}
+
+/// Describes a crowd level using the given band boundaries
+pub fn describe_level(level: f64, bands: &[f64; 4], language: Language) -> &'static str {
+    let band = bands.iter().position(|upper| level < *upper).unwrap_or(bands.len());
+
+    match (language, band) {
+        (Language::En, 0) => "Very low",
+        (Language::En, 1) => "Low",
+        (Language::En, 2) => "Moderate",
+        (Language::En, 3) => "High",
+        (Language::En, _) => "Very high",
+        (Language::De, 0) => "Sehr niedrig",
+        (Language::De, 1) => "Niedrig",
+        (Language::De, 2) => "Mäßig",
+        (Language::De, 3) => "Hoch",
+        (Language::De, _) => "Sehr hoch",
+    }
+}
+
+/// User-facing texts of the HTML views and chat messages as (key, English, German)
+const TEXTS: &[(&str, &str, &str)] = &[
+    ("nav.graph", "Live Graph", "Live-Verlauf"),
+    ("nav.time_averages", "Time Averages", "Durchschnittswerte"),
+    ("nav.compare", "Compare", "Vergleich"),
+    ("nav.trends", "Trends", "Trends"),
+    ("graph.title", "Climbing Gym Crowd Levels", "Auslastung der Kletterhallen"),
+    ("time_averages.title", "Crowd Level Time Averages", "Durchschnittliche Auslastung"),
+    ("compare.title", "Which Gym Is Quietest?", "Welche Halle ist am leersten?"),
+    ("trends.title", "Crowd Level Trends", "Entwicklung der Auslastung"),
+    ("label.gym", "Gym Location:", "Halle:"),
+    ("label.time_range", "Time Range:", "Zeitraum:"),
+    ("label.day_of_week", "Day of Week:", "Wochentag:"),
+    ("label.period", "Period:", "Zeitraum:"),
+    ("label.at", "At:", "Um:"),
+    ("label.compare_last_week", "Compare with last week", "Mit letzter Woche vergleichen"),
+    ("option.all_gyms", "All Gyms", "Alle Hallen"),
+    ("option.all_days", "All Days", "Alle Tage"),
+    ("option.weekly", "Weekly", "Wöchentlich"),
+    ("option.monthly", "Monthly", "Monatlich"),
+    ("option.day", "Day", "Tag"),
+    ("option.days", "Days", "Tage"),
+    ("day.0", "Sunday", "Sonntag"),
+    ("day.1", "Monday", "Montag"),
+    ("day.2", "Tuesday", "Dienstag"),
+    ("day.3", "Wednesday", "Mittwoch"),
+    ("day.4", "Thursday", "Donnerstag"),
+    ("day.5", "Friday", "Freitag"),
+    ("day.6", "Saturday", "Samstag"),
+    ("day.7", "Holiday", "Feiertag/Ferien"),
+    ("button.update_graph", "Update Graph", "Aktualisieren"),
+    ("button.reset_zoom", "Reset Zoom", "Zoom zurücksetzen"),
+    ("button.now", "Now", "Jetzt"),
+    ("button.compare", "Compare", "Vergleichen"),
+    ("tip.zoom", "Tip: Click and drag on the graph to zoom into a specific area", "Tipp: Mit gedrückter Maustaste einen Bereich markieren, um hineinzuzoomen"),
+    ("status.loading", "Loading data...", "Daten werden geladen..."),
+    ("status.error", "Error loading data. Please try again.", "Fehler beim Laden der Daten. Bitte erneut versuchen."),
+    ("status.select_gym", "Select at least one gym.", "Mindestens eine Halle auswählen."),
+    ("compare.now", "Now", "Jetzt"),
+    ("compare.typical", "Typical", "Üblich"),
+    ("compare.in_one_hour", "In 1 hour", "In 1 Stunde"),
+    ("compare.least_crowded", "Least crowded", "Am leersten"),
+    ("compare.show_graph", "Show graph", "Verlauf anzeigen"),
+    ("chart.crowd_level", "Crowd Level (%)", "Auslastung (%)"),
+    ("chart.average_crowd_level", "Average Crowd Level (%)", "Durchschnittliche Auslastung (%)"),
+    ("chart.time", "Time", "Zeit"),
+    ("chart.hour_of_day", "Hour of Day", "Uhrzeit"),
+    ("trends.period", "Period", "Zeitraum"),
+    ("trends.mean", "Mean", "Durchschnitt"),
+    ("trends.peak", "Peak", "Spitze"),
+    ("trends.busy_hours", "Busy hours", "Volle Stunden"),
+    ("trends.vs_previous", "Mean vs previous", "Durchschnitt vs. vorher"),
+    ("trends.vs_last_year", "Mean vs last year", "Durchschnitt vs. Vorjahr"),
+    ("opening.open", "Closes in {} minutes", "Schließt in {} Minuten"),
+    ("opening.closed", "Closed", "Geschlossen"),
+    ("opening.closed_until", "Closed, opens {} at {}", "Geschlossen, öffnet {} um {}"),
+    ("bot.help", "Commands:\n/now - current crowd levels\n/best today - quietest time today per gym\n/subscribe <gym> below|above <percent> - get a message when a gym crosses a level\n/unsubscribe - stop all alerts for this chat", "Befehle:\n/now - aktuelle Auslastung\n/best today - ruhigste Zeit heute pro Halle\n/subscribe <Halle> below|above <Prozent> - Nachricht, wenn eine Halle eine Auslastung unter- oder überschreitet\n/unsubscribe - alle Benachrichtigungen für diesen Chat beenden"),
+    ("bot.unknown_command", "Unknown command, send /help for a list", "Unbekannter Befehl, /help zeigt alle Befehle"),
+    ("bot.no_data", "no data", "keine Daten"),
+    ("bot.best_heading", "Quietest time today (typical level):", "Ruhigste Zeit heute (übliche Auslastung):"),
+    ("bot.best_none", "no typical levels left for today", "heute keine Werte mehr"),
+    ("bot.subscribe_usage", "Usage: /subscribe <gym> below|above <percent>, e.g. /subscribe Ost below 30", "Verwendung: /subscribe <Halle> below|above <Prozent>, z.B. /subscribe Ost below 30"),
+    ("bot.unknown_gym", "Unknown gym '{}'", "Unbekannte Halle '{}'"),
+    ("bot.subscribed", "You will be notified when {} is {} {}%", "Du wirst benachrichtigt, wenn {} {} {}% liegt"),
+    ("bot.unsubscribed", "Removed {} subscription(s)", "{} Benachrichtigung(en) entfernt"),
+    ("bot.alert", "{} is {} {}% (now {}%, {})", "{} liegt {} {}% (jetzt {}%, {})"),
+    ("message.alert", "Crowd level is {} {}%", "Auslastung liegt {} {}%"),
+    ("message.current_level", "Current level", "Aktuelle Auslastung"),
+    ("message.description", "Description", "Einschätzung"),
+    ("push.notify_me", "Notify me when the level is", "Benachrichtigen, wenn die Auslastung"),
+    ("push.subscribe", "Notify me", "Benachrichtigen"),
+    ("push.unsubscribe", "Stop notifications", "Benachrichtigungen beenden"),
+    ("push.select_gym", "Select a single gym to get notifications.", "Für Benachrichtigungen eine einzelne Halle auswählen."),
+    ("push.denied", "Notifications are blocked in this browser.", "Benachrichtigungen sind in diesem Browser blockiert."),
+    ("push.subscribed", "You will be notified when this gym crosses the level.", "Du wirst benachrichtigt, wenn diese Halle die Auslastung erreicht."),
+    ("push.unsubscribed", "Notifications for this gym stopped.", "Benachrichtigungen für diese Halle beendet."),
+    ("calendar.name", "Quiet climbing sessions", "Ruhige Kletterzeiten"),
+    ("calendar.summary", "Quiet at {}", "Ruhig in {}"),
+    ("calendar.description", "Typically around {}% (at most {}%), predicted from the crowd levels of past weeks.", "Üblicherweise etwa {}% (höchstens {}%), vorhergesagt aus der Auslastung der vergangenen Wochen."),
+    ("feed.title", "Notable crowd events", "Besondere Ereignisse bei der Auslastung"),
+    ("feed.unusually_low", "{} unusually empty", "{} ungewöhnlich leer"),
+    ("feed.unusually_high", "{} unusually full", "{} ungewöhnlich voll"),
+    ("feed.outage", "No data from {}", "Keine Daten von {}"),
+    ("feed.new_peak", "New all-time peak at {}", "Neuer Höchststand in {}"),
+    ("feed.anomaly_summary", "From {} to {} the level reached {}% where about {}% is typical.", "Von {} bis {} lag die Auslastung bei bis zu {}%, üblich sind etwa {}%."),
+    ("feed.outage_summary", "No readings between {} and {} ({} minutes).", "Keine Messwerte zwischen {} und {} ({} Minuten)."),
+    ("feed.new_peak_summary", "{}% at {}, the highest level recorded so far.", "{}% um {}, der bisher höchste gemessene Wert."),
+    ("direction.below", "below", "unter"),
+    ("direction.above", "above", "über"),
+];
+
+/// Looks up a user-facing text, returning the key itself if it is unknown
+pub fn text(language: Language, key: &'static str) -> &'static str {
+    TEXTS.iter()
+        .find(|(text_key, _, _)| *text_key == key)
+        .map(|(_, en, de)| match language {
+            Language::En => *en,
+            Language::De => *de,
+        })
+        .unwrap_or(key)
+}
+
+/// Returns the localised name of a weekday (0 = Sunday), 7 being the holiday profile
+pub fn weekday_name(language: Language, weekday: i64) -> &'static str {
+    const KEYS: [&str; 8] = ["day.0", "day.1", "day.2", "day.3", "day.4", "day.5", "day.6", "day.7"];
+
+    KEYS.get(weekday as usize)
+        .map(|key| text(language, key))
+        .unwrap_or_default()
+}
+
+/// Renders the navigation bar shared by the HTML views, keeping the selected language
+pub fn nav_bar(language: Language, active: &str) -> String {
+    let links = [
+        ("graph", "/graph?url=all&days=1&offset=0&", "nav.graph"),
+        ("time_averages", "/time-averages-view?", "nav.time_averages"),
+        ("compare", "/compare-view?", "nav.compare"),
+        ("trends", "/trends-view?", "nav.trends"),
+    ];
+
+    let mut html = String::from("<div class=\"nav-bar\">");
+    for (name, href, key) in links {
+        html.push_str(&format!(
+            "\n            <a href=\"{}lang={}\" class=\"nav-link{}\">{}</a>",
+            href,
+            language.code(),
+            if name == active { " active" } else { "" },
+            text(language, key)
+        ));
+    }
+    html.push_str("\n        </div>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_first_supported_language_without_q_values() {
+        assert_eq!(Language::from_accept_language("de-DE,de,en"), Some(Language::De));
+        assert_eq!(Language::from_accept_language("fr-FR, en-US, de"), Some(Language::En));
+        assert_eq!(Language::from_accept_language("fr, it"), None);
+        assert_eq!(Language::from_accept_language(""), None);
+    }
+
+    #[test]
+    fn prefers_higher_q_values() {
+        assert_eq!(Language::from_accept_language("en;q=0.1, de;q=0.9"), Some(Language::De));
+        assert_eq!(Language::from_accept_language("fr, en;q=0.5, de;q=0.8"), Some(Language::De));
+    }
+
+    #[test]
+    fn keeps_header_order_for_equal_q_values() {
+        assert_eq!(Language::from_accept_language("de;q=0.5, en;q=0.5"), Some(Language::De));
+        assert_eq!(Language::from_accept_language("en, de;q=1"), Some(Language::En));
+    }
+
+    #[test]
+    fn never_selects_languages_with_q_zero() {
+        assert_eq!(Language::from_accept_language("de;q=0"), None);
+        assert_eq!(Language::from_accept_language("de;q=0, en;q=0.1"), Some(Language::En));
+        assert_eq!(Language::from_accept_language("de;q=0.0"), None);
+    }
+}
@@ -8,11 +8,31 @@ extern crate console_error_panic_hook;
 
 use worker::*;
 
+use api::Page;
+use error::ApiError;
+use openapi::{ParamType, Route};
+
 // Define modules
+mod analysis;
+mod annotations;
+mod api;
+mod caching;
+mod calendar;
 mod db;
+mod error;
+mod feed;
+mod holidays;
+mod i18n;
+mod live;
+mod openapi;
+mod opening_hours;
+mod push;
 mod scraper;
+mod subscriptions;
+mod telegram;
 mod handlers;
 mod utils;
+mod weather;
 
 #[event(fetch)]
 pub async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
@@ -20,71 +40,281 @@ pub async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     console_error_panic_hook::set_once();
 
     // Create the router for normal HTTP requests
-    let router = Router::new();
+    let mut router = Router::new();
+    for route in routes() {
+        let handler = route.handler;
+        let localized = route.localized;
+        // Errors of every route are answered with the same JSON envelope
+        let handle = move |req, ctx| async move {
+            let mut response = handler(req, ctx).await.or_else(ApiError::into_response)?;
+            // Shared caches must not hand one language's response to a client asking for another
+            if localized {
+                response.headers_mut().append("Vary", "Accept-Language")?;
+            }
+            Ok(response)
+        };
+        router = match route.method {
+            Method::Get => router.get_async(route.path, handle),
+            Method::Post => router.post_async(route.path, handle),
+            Method::Put => router.put_async(route.path, handle),
+            Method::Delete => router.delete_async(route.path, handle),
+            _ => router.on_async(route.path, handle),
+        };
+    }
+
     router
-        .get("/", |req, _| {
+        .or_else_any_method_async("/*path", |req, _| async move {
+            ApiError::not_found(format!("No route for {}", req.path())).into_response()
+        })
+        .run(req, env)
+        .await
+}
+
+/// Every route of the worker, the router and the OpenAPI document at /openapi.json are built from this table
+pub(crate) fn routes() -> Vec<Route> {
+    let url = "Slug (e.g. muc-ost) or website URL of a configured gym";
+
+    vec![
+        Route::get("/", |req, _| Box::pin(async move {
             let url = req.url()?;
             let base = url.origin().ascii_serialization();
-            Response::redirect(Url::parse(&format!("{}/graph", base)).unwrap())
-        })
-        .get_async("/scrape", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                handlers::scrape_handler(req, env).await
-            }
+            Ok(Response::redirect(Url::parse(&format!("{}/graph", base)).unwrap())?)
+        }))
+            .summary("Redirects to the graph page")
+            .tag("Views")
+            .redirect(),
+        Route::get("/api/v1/gyms", |req, ctx| Box::pin(handlers::api::gyms_handler(req, ctx.env)))
+            .summary("The configured gyms")
+            .tag("API v1")
+            .json::<Page<api::Gym>>(),
+        Route::get("/api/v1/readings", |req, ctx| Box::pin(handlers::api::readings_handler(req, ctx.env)))
+            .summary("Readings, newest first")
+            .tag("API v1")
+            .query("gym", ParamType::String, url)
+            .query("since", ParamType::String, "Only readings after this unix timestamp or ISO 8601 date-time")
+            .query("until", ParamType::String, "Only readings before this unix timestamp or ISO 8601 date-time")
+            .query("limit", ParamType::Integer, "Page size, 1 to 1000 (default 100)")
+            .query("cursor", ParamType::String, "next_cursor of the previous page")
+            .query("include_flagged", ParamType::Boolean, "Include readings flagged as stuck or taken while the gym was closed")
+            .localized()
+            .json::<Page<api::Reading>>()
+            .conditional(),
+        Route::get("/api/v1/readings/latest", |req, ctx| Box::pin(handlers::api::latest_readings_handler(req, ctx.env)))
+            .summary("The newest reading of every gym")
+            .tag("API v1")
+            .query("gym", ParamType::String, url)
+            .localized()
+            .json::<Page<api::Reading>>(),
+        Route::get("/api/v1/time-averages", |req, ctx| Box::pin(handlers::api::time_averages_handler(req, ctx.env)))
+            .summary("The typical level per gym, weekday and hour")
+            .tag("API v1")
+            .query("gym", ParamType::String, url)
+            .json::<Page<api::TimeAverage>>()
+            .conditional(),
+        Route::get("/scrape", |req, ctx| Box::pin(handlers::scrape_handler(req, ctx.env)))
+            .summary("Scrapes the gyms right now")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("save", ParamType::Boolean, "Store the result in the database")
+            .localized()
+            .json::<Vec<scraper::ScrapedWebsiteData>>(),
+        Route::get("/history", |req, ctx| Box::pin(handlers::history_handler(req, ctx.env)))
+            .summary("Historical readings, superseded by /api/v1/readings")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("since", ParamType::Integer, "Only readings after this unix timestamp")
+            .query("until", ParamType::Integer, "Only readings before this unix timestamp")
+            .query("include_flagged", ParamType::Boolean, "Include readings flagged as stuck or taken while the gym was closed")
+            .query("include_annotations", ParamType::Boolean, "Also return the annotations overlapping the range")
+            .localized()
+            .deprecated()
+            .conditional(),
+        Route::get("/history/latest", |req, ctx| Box::pin(handlers::latest_handler(req, ctx.env)))
+            .summary("The most recent reading, superseded by /api/v1/readings/latest")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .localized()
+            .deprecated(),
+        Route::get("/time-averages", |req, ctx| Box::pin(handlers::time_averages_handler(req, ctx.env)))
+            .summary("Average level per gym, weekday and hour, superseded by /api/v1/time-averages")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .deprecated()
+            .conditional(),
+        Route::get("/websites", |req, ctx| Box::pin(handlers::websites_handler(req, ctx.env)))
+            .summary("The configured websites")
+            .tag("Data"),
+        Route::get("/compare", |req, ctx| Box::pin(handlers::compare_handler(req, ctx.env)))
+            .summary("Gyms ranked by how crowded they are, with a one hour forecast")
+            .tag("Data")
+            .query("gyms", ParamType::String, "Comma-separated gym slugs or website URLs (default: all gyms)")
+            .query("at", ParamType::String, "now (default), a unix timestamp or a local time HH:MM today")
+            .localized(),
+        Route::get("/trends", |req, ctx| Box::pin(handlers::trends_handler(req, ctx.env)))
+            .summary("Mean occupancy, peak occupancy and busy hours per week or month")
+            .tag("Data")
+            .required_query("url", ParamType::String, url)
+            .query("period", ParamType::String, "week (default) or month")
+            .query("threshold", ParamType::Number, "Hourly average from which an hour counts as busy (default 70)"),
+        Route::get("/summaries", |req, ctx| Box::pin(handlers::summaries_handler(req, ctx.env)))
+            .summary("Per-day summary per gym")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("days", ParamType::Integer, "Number of days to return (default 30)")
+            .query("since", ParamType::String, "First local date (YYYY-MM-DD) instead of days")
+            .query("until", ParamType::String, "Last local date (YYYY-MM-DD, default today), at most 365 days after since"),
+        Route::get("/weather/correlation", |req, ctx| Box::pin(handlers::weather_correlation_handler(req, ctx.env)))
+            .summary("Correlation of a gym's level with temperature and precipitation")
+            .tag("Data")
+            .required_query("url", ParamType::String, url)
+            .query("days", ParamType::Integer, "Number of days to look back (default 90)"),
+        Route::get("/weather/time-averages", |req, ctx| Box::pin(handlers::weather_time_averages_handler(req, ctx.env)))
+            .summary("Time averages split into rain and dry readings")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("days", ParamType::Integer, "Number of days to look back (default 90)"),
+        Route::get("/coverage", |req, ctx| Box::pin(handlers::coverage_handler(req, ctx.env)))
+            .summary("Completeness of the 10-minute series per gym and day, with the gaps found")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("days", ParamType::Integer, "Number of days to report (default 7)")
+            .query("since", ParamType::String, "First local date (YYYY-MM-DD) instead of days")
+            .query("until", ParamType::String, "Last local date (YYYY-MM-DD, default today), at most 365 days after since")
+            .query("threshold", ParamType::Integer, "Minutes without a reading from which a stretch counts as a gap (default 30)"),
+        Route::get("/anomalies", |req, ctx| Box::pin(handlers::anomalies_handler(req, ctx.env)))
+            .summary("Readings that deviated strongly from the time average for their slot")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("since", ParamType::Integer, "Only anomalies after this unix timestamp"),
+        Route::get("/holidays", |req, ctx| Box::pin(handlers::holidays_handler(req, ctx.env)))
+            .summary("Public and school holidays, and which profile applies today")
+            .tag("Data")
+            .query("year", ParamType::Integer, "Year to list public holidays for (default: current year)"),
+        Route::get("/annotations", |req, ctx| Box::pin(handlers::annotations_handler(req, ctx.env)))
+            .summary("Events marked on the timeline")
+            .tag("Data")
+            .query("url", ParamType::String, url)
+            .query("since", ParamType::Integer, "Only annotations overlapping the range after this unix timestamp")
+            .query("until", ParamType::Integer, "Only annotations overlapping the range before this unix timestamp"),
+        Route::get("/calendar.ics", |req, ctx| Box::pin(handlers::calendar_handler(req, ctx.env)))
+            .summary("iCalendar feed of predicted quiet sessions in the next 7 days")
+            .tag("Feeds")
+            .query("url", ParamType::String, url)
+            .query("min_duration", ParamType::Integer, "Minimum length of a session in minutes (default 90)")
+            .query("max_level", ParamType::Number, "Highest typical level in percent during a session (default 40)")
+            .localized()
+            .media("text/calendar"),
+        Route::get("/feed.atom", |req, ctx| Box::pin(handlers::feed_handler(req, ctx.env)))
+            .summary("Atom feed of notable events")
+            .tag("Feeds")
+            .query("url", ParamType::String, url)
+            .query("days", ParamType::Integer, "Number of days to look back (default 30, at most 90)")
+            .localized()
+            .media("application/atom+xml"),
+        Route::get("/live", |req, ctx| Box::pin(handlers::live_handler(req, ctx.env)))
+            .summary("Server-Sent Events stream of new readings")
+            .tag("Feeds")
+            .query("url", ParamType::String, url)
+            .media("text/event-stream"),
+        Route::get("/ws", |req, ctx| Box::pin(handlers::websocket_handler(req, ctx.env)))
+            .summary("WebSocket with readings, anomaly flags and forecasts for dashboards")
+            .tag("Feeds")
+            .query("gyms", ParamType::String, "Comma-separated gym slugs or website URLs to subscribe to right away")
+            .websocket(),
+        Route::get("/graph", |req, ctx| Box::pin(handlers::graph_handler(req, ctx.env)))
+            .summary("Interactive graph of the crowd levels")
+            .tag("Views")
+            .query("url", ParamType::String, "Slug or website URL of a configured gym, or all")
+            .query("days", ParamType::Integer, "Number of days to show (default 3)")
+            .localized()
+            .media("text/html"),
+        Route::get("/time-averages-view", |req, ctx| Box::pin(handlers::time_averages_view_handler(req, ctx.env)))
+            .summary("Heatmap of the time averages")
+            .tag("Views")
+            .localized()
+            .media("text/html"),
+        Route::get("/compare-view", |req, ctx| Box::pin(handlers::compare_view_handler(req, ctx.env)))
+            .summary("Comparison page highlighting the least crowded gym")
+            .tag("Views")
+            .localized()
+            .media("text/html"),
+        Route::get("/trends-view", |req, ctx| Box::pin(handlers::trends_view_handler(req, ctx.env)))
+            .summary("Trend charts for a single gym")
+            .tag("Views")
+            .query("url", ParamType::String, url)
+            .query("period", ParamType::String, "week (default) or month")
+            .localized()
+            .media("text/html"),
+        Route::get("/sw.js", |req, ctx| Box::pin(handlers::service_worker_handler(req, ctx.env)))
+            .summary("Service worker showing push notifications")
+            .tag("Push")
+            .media("application/javascript"),
+        Route::get("/push/key", |req, ctx| Box::pin(handlers::push_key_handler(req, ctx.env)))
+            .summary("The VAPID public key used as applicationServerKey")
+            .tag("Push"),
+        Route::post("/push/subscribe", |req, ctx| Box::pin(handlers::push_subscribe_handler(req, ctx.env)))
+            .summary("Subscribes a browser to threshold alerts")
+            .tag("Push")
+            .body::<push::PushSubscribeRequest>()
+            .created(),
+        Route::post("/push/unsubscribe", |req, ctx| Box::pin(handlers::push_unsubscribe_handler(req, ctx.env)))
+            .summary("Removes a browser's push subscriptions")
+            .tag("Push")
+            .body::<serde_json::Value>(),
+        Route::put("/admin/school-holidays", |req, ctx| Box::pin(handlers::school_holidays_upload_handler(req, ctx.env)))
+            .summary("Replaces the school holiday periods")
+            .tag("Admin")
+            .body::<Vec<holidays::SchoolHoliday>>()
+            .admin(),
+        Route::post("/admin/annotations", |req, ctx| Box::pin(handlers::annotation_create_handler(req, ctx.env)))
+            .summary("Creates an annotation")
+            .tag("Admin")
+            .body::<annotations::Annotation>()
+            .created()
+            .admin(),
+        Route::put("/admin/annotations/:id", |req, ctx| {
+            let id = ctx.param("id").cloned().unwrap_or_default();
+            Box::pin(async move { handlers::annotation_update_handler(req, ctx.env, &id).await })
         })
-        .get_async("/history", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                handlers::history_handler(req, env).await
-            }
-        })
-        .get_async("/history/latest", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                handlers::latest_handler(req, env).await
-            }
-        })
-        .get_async("/websites", |req, ctx| {
-            async move {
-                handlers::websites_handler(req, ctx.env).await
-            }
-        })
-        .get_async("/graph", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                handlers::graph_handler(req, env).await
-            }
-        })
-        .get_async("/time-averages", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                let website_url = req.url()?.query_pairs()
-                    .find(|(key, _)| key == "url")
-                    .map(|(_, value)| value.to_string());
-                
-                match db::get_time_averages(&env, website_url.as_deref()).await {
-                    Ok(data) => {
-                        // Create response with JSON data
-                        let mut response = Response::from_json(&data)?;
-                        
-                        // Add cache control headers for 24 hours (86400 seconds)
-                        response.headers_mut().set("Cache-Control", "public, max-age=86400")?;
-                        
-                        Ok(response)
-                    },
-                    Err(e) => Response::error(format!("Error fetching time averages: {}", e), 500)
-                }
-            }
+            .summary("Replaces an annotation")
+            .tag("Admin")
+            .body::<annotations::Annotation>()
+            .admin(),
+        Route::delete("/admin/annotations/:id", |req, ctx| {
+            let id = ctx.param("id").cloned().unwrap_or_default();
+            Box::pin(async move { handlers::annotation_delete_handler(req, ctx.env, &id).await })
         })
-        .get_async("/time-averages-view", |req, ctx| {
-            let env = ctx.env.clone();
-            async move {
-                handlers::time_averages_view_handler(req, env).await
-            }
+            .summary("Deletes an annotation")
+            .tag("Admin")
+            .admin(),
+        Route::get("/admin/subscriptions", |req, ctx| Box::pin(handlers::subscriptions_handler(req, ctx.env)))
+            .summary("Lists the threshold alert subscriptions")
+            .tag("Admin")
+            .admin(),
+        Route::post("/admin/subscriptions", |req, ctx| Box::pin(handlers::subscription_create_handler(req, ctx.env)))
+            .summary("Creates a threshold alert subscription")
+            .tag("Admin")
+            .body::<subscriptions::Subscription>()
+            .created()
+            .admin(),
+        Route::delete("/admin/subscriptions/:id", |req, ctx| {
+            let id = ctx.param("id").cloned().unwrap_or_default();
+            Box::pin(async move { handlers::subscription_delete_handler(req, ctx.env, &id).await })
         })
-        .run(req, env)
-        .await
+            .summary("Deletes a subscription")
+            .tag("Admin")
+            .admin(),
+        Route::post("/telegram/webhook", |req, ctx| Box::pin(handlers::telegram::telegram_webhook_handler(req, ctx.env)))
+            .summary("Webhook for the Telegram bot")
+            .tag("Telegram"),
+        Route::get("/openapi.json", |req, ctx| Box::pin(handlers::openapi_handler(req, ctx.env)))
+            .summary("This OpenAPI document")
+            .tag("Meta"),
+        Route::get("/docs", |req, ctx| Box::pin(handlers::docs_handler(req, ctx.env)))
+            .summary("Interactive API reference")
+            .tag("Meta")
+            .media("text/html"),
+    ]
 }
 
 #[event(scheduled)]
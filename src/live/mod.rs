@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+use futures_channel::mpsc::{self, UnboundedSender};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::scraper;
+
+/// Binding of the Durable Object namespace in wrangler.toml
+pub const BINDING: &str = "LIVE";
+
+/// All clients connect to the same instance, there are only a few gyms and readings
+pub const HUB_NAME: &str = "live";
+
+/// Interval of the comments that keep idle SSE connections from being closed
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Milliseconds after which browsers reconnect a dropped stream
+const RECONNECT_MILLIS: u32 = 10_000;
+
+/// Header carrying the gyms a client asked for, as JSON list of website URLs resolved by the worker
+const GYMS_HEADER: &str = "X-Live-Gyms";
+
+/// A freshly stored reading as pushed to live clients
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LiveReading {
+    pub gym_slug: String,
+    pub website_url: String,
+    pub website_name: String,
+    pub percentage: f64,
+    pub quality: String,
+    /// UTC, same format as `created_at` in the history
+    pub created_at: String,
+}
+
+/// Anomaly flag of a reading, see `analysis::detect_anomaly`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LiveAnomaly {
+    pub kind: String,
+    pub expected_percentage: f64,
+    pub z_score: f64,
+}
+
+/// Expected level an hour after the reading
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LiveForecast {
+    /// UTC, same format as `created_at` in the history
+    pub at: String,
+    pub percentage: f64,
+}
+
+/// Everything known about a gym after a new reading, flagged readings carry no anomaly or forecast
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LiveUpdate {
+    pub reading: LiveReading,
+    pub anomaly: Option<LiveAnomaly>,
+    pub forecast: Option<LiveForecast>,
+}
+
+/// Messages WebSocket clients send to the hub
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    /// Adds gyms to the subscription, an empty list subscribes to all of them
+    Subscribe {
+        #[serde(default)]
+        gyms: Vec<String>,
+    },
+    /// Removes gyms from the subscription, an empty list removes all of them
+    Unsubscribe {
+        #[serde(default)]
+        gyms: Vec<String>,
+    },
+    Ping,
+}
+
+/// Messages the hub sends to WebSocket clients
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage<'a> {
+    /// Last known values of newly subscribed gyms
+    Snapshot { gyms: Vec<LiveUpdate> },
+    /// The gyms the client is subscribed to after a change
+    Subscribed { gyms: &'a [String] },
+    Update(&'a LiveUpdate),
+    Pong,
+    Error { message: String },
+}
+
+/// Kept as attachment of a WebSocket, so subscriptions survive the hub hibernating
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct SocketClient {
+    gyms: Vec<String>,
+}
+
+/// A connected SSE client, optionally only interested in one gym
+struct StreamClient {
+    sender: UnboundedSender<Result<Vec<u8>>>,
+    website_url: Option<String>,
+}
+
+/// Durable Object fanning out new readings to the connected clients and keeping the last update of every gym
+#[durable_object]
+pub struct LiveHub {
+    state: State,
+    streams: Vec<StreamClient>,
+}
+
+#[durable_object]
+impl DurableObject for LiveHub {
+    fn new(state: State, _env: Env) -> Self {
+        Self {
+            state,
+            streams: Vec::new(),
+        }
+    }
+
+    async fn fetch(&mut self, mut req: Request) -> Result<Response> {
+        match (req.method(), req.path().as_str()) {
+            (Method::Get, "/live") => self.open_stream(&req).await,
+            (Method::Get, "/ws") => self.open_socket(&req).await,
+            (Method::Post, "/publish") => {
+                let update: LiveUpdate = req.json().await?;
+                let website_url = update.reading.website_url.clone();
+
+                self.state.storage().put(&latest_key(&website_url), &update).await?;
+
+                let event = format!("event: reading\ndata: {}\n\n", serde_json::to_string(&update.reading)?);
+                self.send_to_streams(Some(&website_url), &event);
+                self.send_to_sockets(&update);
+                Response::ok("")
+            },
+            _ => ApiError::not_found("Not Found").into_response(),
+        }
+    }
+
+    async fn alarm(&mut self) -> Result<Response> {
+        self.send_to_streams(None, ": keepalive\n\n");
+        if !self.streams.is_empty() {
+            self.state.storage().set_alarm(KEEPALIVE_INTERVAL).await?;
+        }
+        Response::ok("")
+    }
+
+    async fn websocket_message(&mut self, ws: WebSocket, message: WebSocketIncomingMessage) -> Result<()> {
+        let WebSocketIncomingMessage::String(text) = message else {
+            return ws.send(&ServerMessage::Error { message: "Binary messages are not supported".to_string() });
+        };
+
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Subscribe { gyms }) => self.subscribe(&ws, gyms).await,
+            Ok(ClientMessage::Unsubscribe { gyms }) => {
+                let mut client = ws.deserialize_attachment::<SocketClient>()?.unwrap_or_default();
+                if gyms.is_empty() {
+                    client.gyms.clear();
+                } else {
+                    client.gyms.retain(|gym| !gyms.contains(gym));
+                }
+                ws.serialize_attachment(&client)?;
+                ws.send(&ServerMessage::Subscribed { gyms: &client.gyms })
+            },
+            Ok(ClientMessage::Ping) => ws.send(&ServerMessage::Pong),
+            Err(e) => ws.send(&ServerMessage::Error { message: format!("Invalid message: {}", e) }),
+        }
+    }
+
+    async fn websocket_close(&mut self, ws: WebSocket, code: usize, reason: String, _was_clean: bool) -> Result<()> {
+        // Complete the closing handshake, reserved codes (no status, abnormal closure) can't be sent back
+        let code = match code {
+            1005 | 1006 => 1000,
+            code => code as u16,
+        };
+        ws.close(Some(code), Some(reason))
+    }
+
+    async fn websocket_error(&mut self, _ws: WebSocket, error: Error) -> Result<()> {
+        console_error!("Live WebSocket error: {}", error);
+        Ok(())
+    }
+}
+
+impl LiveHub {
+    /// Accepts a WebSocket, gyms given with the connection are subscribed right away
+    async fn open_socket(&mut self, req: &Request) -> Result<Response> {
+        if !req.headers().get("Upgrade")?.is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+            return ApiError::new(ErrorCode::UpgradeRequired, "Expected a WebSocket upgrade").into_response();
+        }
+
+        let gyms = requested_gyms(req)?;
+
+        let pair = WebSocketPair::new()?;
+        // Accepting through the state lets the hub hibernate while sockets are idle, as long as no /live stream is open
+        self.state.accept_web_socket(&pair.server);
+        pair.server.serialize_attachment(SocketClient::default())?;
+
+        if let Some(gyms) = gyms {
+            self.subscribe(&pair.server, gyms).await?;
+        }
+
+        Response::from_websocket(pair.client)
+    }
+
+    /// Adds gyms to a socket's subscription and sends their last known values
+    async fn subscribe(&mut self, ws: &WebSocket, gyms: Vec<String>) -> Result<()> {
+        // Gyms are given by slug or URL and kept by URL
+        let mut requested = Vec::with_capacity(gyms.len());
+        for gym in &gyms {
+            match scraper::find_website(gym) {
+                Some(website) => requested.push(website.url),
+                None => return ws.send(&ServerMessage::Error { message: format!("Website not in configured list: {}", gym) }),
+            }
+        }
+        if requested.is_empty() {
+            requested = scraper::get_configured_websites().into_iter()
+                .map(|website| website.url)
+                .collect();
+        }
+
+        let mut client = ws.deserialize_attachment::<SocketClient>()?.unwrap_or_default();
+        for gym in &requested {
+            if !client.gyms.contains(gym) {
+                client.gyms.push(gym.clone());
+            }
+        }
+        ws.serialize_attachment(&client)?;
+        ws.send(&ServerMessage::Subscribed { gyms: &client.gyms })?;
+
+        // Gyms without a reading since the hub was created are left out of the snapshot
+        let storage = self.state.storage();
+        let mut snapshot = Vec::new();
+        for gym in &requested {
+            if let Ok(update) = storage.get::<LiveUpdate>(&latest_key(gym)).await {
+                snapshot.push(update);
+            }
+        }
+        ws.send(&ServerMessage::Snapshot { gyms: snapshot })
+    }
+
+    /// Sends an update to every socket subscribed to its gym
+    fn send_to_sockets(&self, update: &LiveUpdate) {
+        for ws in self.state.get_websockets() {
+            let subscribed = ws.deserialize_attachment::<SocketClient>().ok().flatten()
+                .is_some_and(|client| client.gyms.contains(&update.reading.website_url));
+
+            if subscribed {
+                if let Err(e) = ws.send(&ServerMessage::Update(update)) {
+                    console_error!("Error sending live update: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Starts an event stream, limited to one gym if one was given with the connection
+    async fn open_stream(&mut self, req: &Request) -> Result<Response> {
+        let website_url = requested_gyms(req)?.and_then(|gyms| gyms.into_iter().next());
+
+        let (sender, receiver) = mpsc::unbounded();
+        sender.unbounded_send(Ok(format!("retry: {}\n\n", RECONNECT_MILLIS).into_bytes()))
+            .map_err(|e| Error::from(e.to_string()))?;
+
+        if self.streams.is_empty() {
+            self.state.storage().set_alarm(KEEPALIVE_INTERVAL).await?;
+        }
+        self.streams.push(StreamClient { sender, website_url });
+
+        let mut response = Response::from_stream(receiver)?;
+        response.headers_mut().set("Content-Type", "text/event-stream")?;
+        response.headers_mut().set("Cache-Control", "no-cache")?;
+        Ok(response)
+    }
+
+    /// Sends an event to every stream interested in the gym, dropping disconnected clients
+    fn send_to_streams(&mut self, website_url: Option<&str>, event: &str) {
+        self.streams.retain(|client| {
+            let interested = match (&client.website_url, website_url) {
+                (Some(wanted), Some(url)) => wanted == url,
+                _ => true,
+            };
+            !interested || client.sender.unbounded_send(Ok(event.as_bytes().to_vec())).is_ok()
+        });
+    }
+}
+
+/// Storage key of the last update of a gym
+fn latest_key(website_url: &str) -> String {
+    format!("latest:{}", website_url)
+}
+
+/// Reads the gyms the worker resolved for a connection, None if the client didn't choose any
+fn requested_gyms(req: &Request) -> Result<Option<Vec<String>>> {
+    match req.headers().get(GYMS_HEADER)? {
+        Some(gyms) => Ok(Some(serde_json::from_str(&gyms)?)),
+        None => Ok(None),
+    }
+}
+
+/// Forwards a client's /live or /ws request to the hub, together with the website URLs of the gyms it asked for
+pub async fn connect(env: &Env, req: Request, gyms: Option<Vec<String>>) -> Result<Response> {
+    // Headers of an incoming request are immutable, so the hub gets a copy, without any gym
+    // list the client sent itself since the hub trusts that header to be resolved by the worker
+    let mut headers = Headers::new();
+    for (name, value) in req.headers().entries() {
+        if !name.eq_ignore_ascii_case(GYMS_HEADER) {
+            headers.set(&name, &value)?;
+        }
+    }
+    if let Some(gyms) = gyms {
+        headers.set(GYMS_HEADER, &serde_json::to_string(&gyms)?)?;
+    }
+
+    let mut init = RequestInit::new();
+    init.with_method(req.method())
+        .with_headers(headers);
+    let request = Request::new_with_init(req.url()?.as_str(), &init)?;
+
+    let stub = env.durable_object(BINDING)?.id_from_name(HUB_NAME)?.get_stub()?;
+    stub.fetch_with_request(request).await
+}
+
+/// Hands a new update to the hub, which pushes it to the connected clients
+pub async fn publish(env: &Env, update: &LiveUpdate) -> Result<()> {
+    let stub = env.durable_object(BINDING)?.id_from_name(HUB_NAME)?.get_stub()?;
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(serde_json::to_string(update)?.into()));
+
+    // The host is ignored, requests to a stub always reach the Durable Object
+    let request = Request::new_with_init("https://live/publish", &init)?;
+    let resp = stub.fetch_with_request(request).await?;
+
+    if resp.status_code() != 200 {
+        return Err(Error::from(format!("Publishing live update failed with status: {}", resp.status_code())));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,361 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde_json::{json, Map, Value};
+use worker::*;
+
+use crate::error::{ApiResult, ErrorEnvelope};
+
+/// Future returned by a route handler
+pub type HandlerFuture = Pin<Box<dyn Future<Output = ApiResult<Response>>>>;
+
+/// Handles the requests of a route
+pub type Handler = fn(Request, RouteContext<()>) -> HandlerFuture;
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema_of<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+/// Type of a query parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+}
+
+impl ParamType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Number => "number",
+            ParamType::Boolean => "boolean",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Param {
+    name: &'static str,
+    kind: ParamType,
+    description: &'static str,
+    required: bool,
+}
+
+/// What a route answers with
+#[derive(Debug, Clone, Copy)]
+enum Content {
+    Json(SchemaFn),
+    /// A body of the given media type without a schema, e.g. HTML
+    Media(&'static str),
+    WebSocket,
+    Redirect,
+}
+
+/// A route of the worker together with its documentation
+///
+/// The router and the OpenAPI document are both built from the same list of routes,
+/// see `routes()` in lib.rs.
+pub struct Route {
+    pub method: Method,
+    /// Router pattern, path parameters are written as `:name`
+    pub path: &'static str,
+    pub handler: Handler,
+    /// The response depends on the `Accept-Language` header, see `localized`
+    pub localized: bool,
+    summary: &'static str,
+    tag: &'static str,
+    params: Vec<Param>,
+    request_body: Option<SchemaFn>,
+    content: Content,
+    status: u16,
+    admin: bool,
+    deprecated: bool,
+    conditional: bool,
+}
+
+impl Route {
+    pub fn new(method: Method, path: &'static str, handler: Handler) -> Self {
+        Route {
+            method,
+            path,
+            handler,
+            localized: false,
+            summary: "",
+            tag: "",
+            params: Vec::new(),
+            request_body: None,
+            content: Content::Json(schema_of::<Value>),
+            status: 200,
+            admin: false,
+            deprecated: false,
+            conditional: false,
+        }
+    }
+
+    pub fn get(path: &'static str, handler: Handler) -> Self {
+        Route::new(Method::Get, path, handler)
+    }
+
+    pub fn post(path: &'static str, handler: Handler) -> Self {
+        Route::new(Method::Post, path, handler)
+    }
+
+    pub fn put(path: &'static str, handler: Handler) -> Self {
+        Route::new(Method::Put, path, handler)
+    }
+
+    pub fn delete(path: &'static str, handler: Handler) -> Self {
+        Route::new(Method::Delete, path, handler)
+    }
+
+    pub fn summary(mut self, summary: &'static str) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    pub fn tag(mut self, tag: &'static str) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    pub fn query(mut self, name: &'static str, kind: ParamType, description: &'static str) -> Self {
+        self.params.push(Param { name, kind, description, required: false });
+        self
+    }
+
+    pub fn required_query(mut self, name: &'static str, kind: ParamType, description: &'static str) -> Self {
+        self.params.push(Param { name, kind, description, required: true });
+        self
+    }
+
+    /// The response language is chosen by `lang` or the `Accept-Language` header
+    pub fn localized(mut self) -> Self {
+        self.localized = true;
+        self.query("lang", ParamType::String, "Response language, en or de (default: Accept-Language)")
+    }
+
+    /// Answers with JSON described by `T`, untyped JSON is the default
+    pub fn json<T: JsonSchema>(mut self) -> Self {
+        self.content = Content::Json(schema_of::<T>);
+        self
+    }
+
+    /// Answers with a body of the given media type
+    pub fn media(mut self, media_type: &'static str) -> Self {
+        self.content = Content::Media(media_type);
+        self
+    }
+
+    /// Upgrades the connection to a WebSocket
+    pub fn websocket(mut self) -> Self {
+        self.content = Content::WebSocket;
+        self
+    }
+
+    pub fn redirect(mut self) -> Self {
+        self.content = Content::Redirect;
+        self
+    }
+
+    /// Expects a JSON body described by `T`
+    pub fn body<T: JsonSchema>(mut self) -> Self {
+        self.request_body = Some(schema_of::<T>);
+        self
+    }
+
+    /// Answers with 201 Created
+    pub fn created(mut self) -> Self {
+        self.status = 201;
+        self
+    }
+
+    /// Requires the `Authorization: Bearer <ADMIN_TOKEN>` header
+    pub fn admin(mut self) -> Self {
+        self.admin = true;
+        self
+    }
+
+    /// Superseded by a route of the versioned API
+    pub fn deprecated(mut self) -> Self {
+        self.deprecated = true;
+        self
+    }
+
+    /// Sends an `ETag` and answers `If-None-Match` with 304 Not Modified, see `caching::Freshness`
+    pub fn conditional(mut self) -> Self {
+        self.conditional = true;
+        self
+    }
+
+    /// Names of the `:name` segments of the path
+    fn path_params(&self) -> Vec<&'static str> {
+        self.path.split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .collect()
+    }
+
+    fn operation(&self, generator: &mut SchemaGenerator) -> Value {
+        let mut parameters: Vec<Value> = self.path_params().into_iter()
+            .map(|name| json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" }
+            }))
+            .collect();
+
+        parameters.extend(self.params.iter().map(|param| json!({
+            "name": param.name,
+            "in": "query",
+            "required": param.required,
+            "description": param.description,
+            "schema": { "type": param.kind.as_str() }
+        })));
+
+        if self.conditional {
+            parameters.push(json!({
+                "name": "If-None-Match",
+                "in": "header",
+                "required": false,
+                "description": "ETag of a previous response, answered with 304 if the data is unchanged",
+                "schema": { "type": "string" }
+            }));
+        }
+
+        let mut responses = Map::new();
+        match self.content {
+            Content::Json(schema) => {
+                responses.insert(self.status.to_string(), json!({
+                    "description": "OK",
+                    "content": { "application/json": { "schema": schema(generator) } }
+                }));
+            },
+            Content::Media(media_type) => {
+                responses.insert(self.status.to_string(), json!({
+                    "description": "OK",
+                    "content": { media_type: { "schema": { "type": "string" } } }
+                }));
+            },
+            Content::WebSocket => {
+                responses.insert("101".to_string(), json!({ "description": "Switching Protocols" }));
+            },
+            Content::Redirect => {
+                responses.insert("302".to_string(), json!({ "description": "Found" }));
+            },
+        }
+        if self.conditional {
+            if let Some(ok) = responses.get_mut(&self.status.to_string()) {
+                ok["headers"] = json!({
+                    "ETag": { "description": "Version of the data", "schema": { "type": "string" } }
+                });
+            }
+            responses.insert("304".to_string(), json!({ "description": "Not Modified" }));
+        }
+        if self.admin {
+            responses.insert("401".to_string(), json!({
+                "description": "Missing or wrong admin token",
+                "content": { "application/json": { "schema": schema_of::<ErrorEnvelope>(generator) } }
+            }));
+        }
+        responses.insert("default".to_string(), json!({
+            "description": "Error, `error.code` tells the kind of error",
+            "content": { "application/json": { "schema": schema_of::<ErrorEnvelope>(generator) } }
+        }));
+
+        let mut operation = json!({
+            "operationId": self.operation_id(),
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses
+        });
+
+        if !self.tag.is_empty() {
+            operation["tags"] = json!([self.tag]);
+        }
+        if let Some(schema) = self.request_body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema(generator) } }
+            });
+        }
+        if self.admin {
+            operation["security"] = json!([{ "adminToken": [] }]);
+        }
+        if self.deprecated {
+            operation["deprecated"] = json!(true);
+        }
+
+        operation
+    }
+
+    /// Unique id derived from the method and path, e.g. `get_api_v1_readings_latest`
+    fn operation_id(&self) -> String {
+        let path: String = self.path.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}_{}", method_name(&self.method), path.trim_matches('_').replace("__", "_"))
+    }
+}
+
+/// Lowercase method as used for the keys of a path item
+fn method_name(method: &Method) -> String {
+    method.as_ref().to_ascii_lowercase()
+}
+
+/// Converts a router pattern to an OpenAPI path, `/admin/annotations/:id` becomes `/admin/annotations/{id}`
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{}}}", name),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Builds the OpenAPI 3.1 document describing the routes
+///
+/// `server_url` is the origin the worker is reached at.
+pub fn document(routes: &[Route], server_url: &str) -> Value {
+    // OpenAPI 3.1 uses JSON Schema 2020-12, shared types end up in the components
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| {
+            settings.definitions_path = "/components/schemas".into();
+            settings.meta_schema = None;
+        })
+        .into_generator();
+
+    let mut paths = Map::new();
+    for route in routes {
+        let path = paths.entry(openapi_path(route.path)).or_insert_with(|| json!({}));
+        path[method_name(&route.method).as_str()] = route.operation(&mut generator);
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Boulderwelt Crowd Level Scraper",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Crowd levels of the Boulderwelt gyms in Munich, recorded every 10 minutes. The routes under /api/v1 are the stable, typed API."
+        },
+        "servers": [{ "url": server_url }],
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(true),
+            "securitySchemes": {
+                "adminToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "The ADMIN_TOKEN secret"
+                }
+            }
+        }
+    })
+}
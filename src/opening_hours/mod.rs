@@ -0,0 +1,229 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::{self, Language};
+use crate::utils;
+
+/// Opening and closing time of a single day in local time ("HH:MM")
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TimeRange {
+    pub open: String,
+    pub close: String,
+}
+
+impl TimeRange {
+    /// Hours within one day, ranges past midnight aren't supported
+    ///
+    /// Panics if either time isn't "HH:MM" or the gym doesn't close after it opens,
+    /// so a broken configuration fails on the first request instead of hiding the gym.
+    pub fn new(open: &str, close: &str) -> Self {
+        let range = TimeRange {
+            open: open.to_string(),
+            close: close.to_string(),
+        };
+
+        if let Err(message) = range.validate() {
+            panic!("Invalid opening hours: {}", message);
+        }
+
+        range
+    }
+
+    /// Checks that both times are "HH:MM" and the closing time comes after the opening time
+    pub fn validate(&self) -> Result<(), String> {
+        let parse = |time: &str| NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| format!("'{}' is not a time as HH:MM", time));
+        let (open, close) = (parse(&self.open)?, parse(&self.close)?);
+
+        if close <= open {
+            return Err(format!(
+                "closing time {} is not after opening time {}, ranges past midnight aren't supported",
+                self.close, self.open
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the parsed opening and closing time, or None if the range is invalid
+    pub fn times(&self) -> Option<(NaiveTime, NaiveTime)> {
+        self.validate().ok()?;
+        let open = NaiveTime::parse_from_str(&self.open, "%H:%M").ok()?;
+        let close = NaiveTime::parse_from_str(&self.close, "%H:%M").ok()?;
+        Some((open, close))
+    }
+}
+
+/// Deviating hours for a specific local date, `hours: None` means closed all day
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OpeningException {
+    pub date: String,
+    pub hours: Option<TimeRange>,
+}
+
+/// Whether a gym is open at a given moment, as reported by the API
+#[derive(Serialize, Debug, Clone)]
+pub struct OpeningStatus {
+    pub status: &'static str,
+    pub open: bool,
+    pub closes_in_minutes: Option<i64>,
+    pub opens_at: Option<String>,
+    pub message: String,
+}
+
+/// Weekly opening hours of a gym plus date-specific exceptions
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OpeningHours {
+    /// Hours per weekday, indexed like `strftime('%w')` (0 = Sunday), None = closed
+    pub weekly: [Option<TimeRange>; 7],
+    pub exceptions: Vec<OpeningException>,
+}
+
+impl OpeningHours {
+    /// Creates a schedule with one set of hours for Monday to Friday and one for the weekend
+    pub fn weekdays_and_weekend(weekdays: TimeRange, weekend: TimeRange) -> Self {
+        OpeningHours {
+            weekly: [
+                Some(weekend.clone()),
+                Some(weekdays.clone()),
+                Some(weekdays.clone()),
+                Some(weekdays.clone()),
+                Some(weekdays.clone()),
+                Some(weekdays),
+                Some(weekend),
+            ],
+            exceptions: Vec::new(),
+        }
+    }
+
+    /// Returns the regular hours for a weekday (0 = Sunday)
+    pub fn hours_for_weekday(&self, weekday: i64) -> Option<(NaiveTime, NaiveTime)> {
+        self.weekly
+            .get(weekday as usize)
+            .and_then(|hours| hours.as_ref())
+            .and_then(|hours| hours.times())
+    }
+
+    /// Returns the hours for a local date, taking exceptions into account
+    pub fn hours_on(&self, date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+        let date_str = date.format("%Y-%m-%d").to_string();
+
+        match self.exceptions.iter().find(|exception| exception.date == date_str) {
+            Some(exception) => exception.hours.as_ref().and_then(|hours| hours.times()),
+            None => self.hours_for_weekday(utils::weekday_index(date.weekday())),
+        }
+    }
+
+    /// Checks whether the gym is open at the given local time
+    pub fn is_open_at(&self, local: NaiveDateTime) -> bool {
+        self.hours_on(local.date())
+            .is_some_and(|(open, close)| local.time() >= open && local.time() < close)
+    }
+
+    /// Returns the minutes until closing if the gym is open at the given local time
+    pub fn minutes_until_close(&self, local: NaiveDateTime) -> Option<i64> {
+        if !self.is_open_at(local) {
+            return None;
+        }
+
+        let (_, close) = self.hours_on(local.date())?;
+        Some((close - local.time()).num_minutes())
+    }
+
+    /// Returns the next local time at which the gym opens, looking up to two weeks ahead
+    pub fn next_opening(&self, local: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..14).find_map(|offset| {
+            let date = local.date() + Duration::days(offset);
+            let (open, _) = self.hours_on(date)?;
+            let opening = date.and_time(open);
+            (opening > local).then_some(opening)
+        })
+    }
+
+    /// Checks whether any part of the local hour `[hour, hour + 1)` lies within the
+    /// regular opening hours of the given weekday (0 = Sunday)
+    pub fn is_open_during_hour(&self, weekday: i64, hour: u32) -> bool {
+        let Some((open, close)) = self.hours_for_weekday(weekday) else {
+            return false;
+        };
+
+        let slot_start = hour * 60;
+        let slot_end = slot_start + 60;
+        let open_minutes = open.hour() * 60 + open.minute();
+        let close_minutes = close.hour() * 60 + close.minute();
+
+        slot_start < close_minutes && slot_end > open_minutes
+    }
+
+    /// Describes whether the gym is open at the given local time and for how long
+    pub fn status_at(&self, local: NaiveDateTime, language: Language) -> OpeningStatus {
+        match self.minutes_until_close(local) {
+            Some(minutes) => OpeningStatus {
+                status: "open",
+                open: true,
+                closes_in_minutes: Some(minutes),
+                opens_at: None,
+                message: i18n::text(language, "opening.open").replacen("{}", &minutes.to_string(), 1),
+            },
+            None => {
+                let next_opening = self.next_opening(local);
+                OpeningStatus {
+                    status: "closed",
+                    open: false,
+                    closes_in_minutes: None,
+                    opens_at: next_opening.map(|time| time.format("%Y-%m-%dT%H:%M").to_string()),
+                    message: match next_opening {
+                        Some(time) => {
+                            let day = i18n::weekday_name(language, utils::weekday_index(time.weekday()));
+                            i18n::text(language, "opening.closed_until")
+                                .replacen("{}", day, 1)
+                                .replacen("{}", &time.format("%H:%M").to_string(), 1)
+                        },
+                        None => i18n::text(language, "opening.closed").to_string(),
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_ranges_within_a_day() {
+        let range = TimeRange::new("07:00", "23:00");
+        assert_eq!(range.times(), Some((NaiveTime::from_hms_opt(7, 0, 0).unwrap(), NaiveTime::from_hms_opt(23, 0, 0).unwrap())));
+    }
+
+    #[test]
+    fn rejects_ranges_past_midnight() {
+        let range = TimeRange { open: "22:00".to_string(), close: "02:00".to_string() };
+        assert!(range.validate().unwrap_err().contains("past midnight"));
+        assert_eq!(range.times(), None);
+    }
+
+    #[test]
+    fn rejects_empty_and_malformed_ranges() {
+        assert!(TimeRange { open: "09:00".to_string(), close: "09:00".to_string() }.validate().is_err());
+        assert!(TimeRange { open: "9".to_string(), close: "18:00".to_string() }.validate().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid opening hours")]
+    fn constructor_panics_on_invalid_range() {
+        TimeRange::new("23:00", "07:00");
+    }
+
+    #[test]
+    fn configured_gyms_have_valid_hours() {
+        for website in crate::scraper::get_configured_websites() {
+            let ranges = website.opening_hours.weekly.iter().flatten()
+                .chain(website.opening_hours.exceptions.iter().filter_map(|exception| exception.hours.as_ref()));
+            for range in ranges {
+                assert_eq!(range.validate(), Ok(()), "{}", website.name);
+            }
+        }
+    }
+}
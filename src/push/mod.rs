@@ -0,0 +1,372 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use hkdf::Hkdf;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use worker::*;
+
+use crate::i18n::Language;
+use crate::subscriptions::Direction;
+use crate::utils;
+
+/// Record size announced in the aes128gcm header, the whole message is a single record
+const RECORD_SIZE: u32 = 4096;
+
+/// Seconds a push service keeps an undelivered message, alerts are stale after that
+const PUSH_TTL_SECONDS: u32 = 3600;
+
+/// Lifetime of the VAPID token, push services reject tokens valid for more than 24 hours
+const VAPID_TOKEN_HOURS: i64 = 12;
+
+/// Hosts of the browser push services (Chrome, Firefox, Edge and Safari), endpoints have to be
+/// on one of them or a subdomain, so the worker can't be made to send requests anywhere else
+const PUSH_SERVICE_HOSTS: &[&str] = &[
+    "fcm.googleapis.com",
+    "push.services.mozilla.com",
+    "notify.windows.com",
+    "push.apple.com",
+];
+
+/// Subscriptions a single browser push endpoint may hold across all gyms
+pub const MAX_SUBSCRIPTIONS_PER_ENDPOINT: usize = 10;
+
+/// Unpadded base64url as used by the Push API, accepting padded input as well
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Keys of a browser push subscription, base64url encoded
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct PushKeys {
+    /// The browser's P-256 public key
+    pub p256dh: String,
+    /// The 16 byte authentication secret
+    pub auth: String,
+}
+
+/// A browser push subscription as returned by `PushSubscription.toJSON()`
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct PushTarget {
+    pub endpoint: String,
+    pub keys: PushKeys,
+}
+
+impl PushTarget {
+    /// Checks the subscription and returns a message describing the first problem found
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        match Url::parse(&self.endpoint) {
+            Ok(url) if url.scheme() == "https" && url.port().is_none() && url.username().is_empty() => {
+                // IP literals and private hosts are never on the list
+                if !url.domain().is_some_and(is_push_service) {
+                    return Err("Push endpoint is not on a known push service".to_string());
+                }
+            },
+            _ => return Err("Invalid push endpoint".to_string()),
+        }
+
+        let p256dh = BASE64_URL.decode(&self.keys.p256dh).ok();
+        if p256dh.and_then(|key| PublicKey::from_sec1_bytes(&key).ok()).is_none() {
+            return Err("Invalid p256dh key".to_string());
+        }
+
+        if BASE64_URL.decode(&self.keys.auth).map(|auth| auth.len()).ok() != Some(16) {
+            return Err("Invalid auth secret".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn is_push_service(domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    PUSH_SERVICE_HOSTS.iter().any(|host| {
+        domain == *host || domain.strip_suffix(host).is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+/// Body of POST /push/subscribe, sent by the graph page
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct PushSubscribeRequest {
+    /// Gym by slug or website URL
+    pub website_url: String,
+    pub threshold: f64,
+    pub direction: Direction,
+    #[serde(default)]
+    pub language: Language,
+    pub subscription: PushTarget,
+}
+
+/// Outcome of a push delivery
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delivery {
+    Sent,
+    /// The push service no longer knows the subscription, it should be removed
+    Expired,
+}
+
+/// Reads the VAPID private key (base64url encoded 32 byte scalar) from the `VAPID_PRIVATE_KEY` secret
+fn vapid_signing_key(env: &Env) -> Result<SigningKey> {
+    let secret = env.secret("VAPID_PRIVATE_KEY")?.to_string();
+    let bytes = BASE64_URL.decode(secret.trim())
+        .map_err(|e| Error::from(format!("Invalid VAPID_PRIVATE_KEY: {}", e)))?;
+
+    SigningKey::from_slice(&bytes).map_err(|e| Error::from(format!("Invalid VAPID_PRIVATE_KEY: {}", e)))
+}
+
+/// Returns the VAPID public key the browser needs as `applicationServerKey`
+pub fn vapid_public_key(env: &Env) -> Result<String> {
+    let key = vapid_signing_key(env)?;
+    Ok(BASE64_URL.encode(key.verifying_key().to_encoded_point(false).as_bytes()))
+}
+
+/// Builds the `Authorization` header identifying this worker to the push service (RFC 8292)
+fn vapid_authorization(env: &Env, endpoint: &Url) -> Result<String> {
+    let key = vapid_signing_key(env)?;
+    let expires = utils::now_utc().and_utc().timestamp() + VAPID_TOKEN_HOURS * 3600;
+
+    // Some push services require a contact, usually a mailto: URL
+    let subject = env.var("VAPID_SUBJECT").ok().map(|value| value.to_string()).filter(|value| !value.is_empty());
+
+    vapid_header(&key, endpoint, expires, subject.as_deref())
+}
+
+/// Signs the VAPID token for the push service at `endpoint`, valid until the unix time `expires`
+fn vapid_header(key: &SigningKey, endpoint: &Url, expires: i64, subject: Option<&str>) -> Result<String> {
+    let public_key = BASE64_URL.encode(key.verifying_key().to_encoded_point(false).as_bytes());
+
+    let mut claims = json!({
+        "aud": endpoint.origin().ascii_serialization(),
+        "exp": expires
+    });
+    if let Some(subject) = subject {
+        claims["sub"] = json!(subject);
+    }
+
+    let header = BASE64_URL.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+    let signing_input = format!("{}.{}", header, BASE64_URL.encode(serde_json::to_string(&claims)?));
+    let signature: Signature = key.sign(signing_input.as_bytes());
+
+    Ok(format!("vapid t={}.{}, k={}", signing_input, BASE64_URL.encode(signature.to_bytes()), public_key))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| Error::from(format!("Error generating random bytes: {}", e)))?;
+    Ok(bytes)
+}
+
+fn hkdf_expand<const N: usize>(hkdf: &Hkdf<Sha256>, info: &[u8]) -> Result<[u8; N]> {
+    let mut okm = [0u8; N];
+    hkdf.expand(info, &mut okm).map_err(|e| Error::from(format!("Error deriving key: {}", e)))?;
+    Ok(okm)
+}
+
+/// Encrypts a payload for a subscription (RFC 8291 with the aes128gcm content coding of RFC 8188)
+pub fn encrypt(payload: &[u8], keys: &PushKeys) -> Result<Vec<u8>> {
+    // Fresh key pair for every message, a random 32 byte value is almost always a valid scalar
+    let as_secret = loop {
+        if let Ok(secret) = SecretKey::from_slice(&random_bytes::<32>()?) {
+            break secret;
+        }
+    };
+
+    encrypt_with(payload, keys, &as_secret, random_bytes::<16>()?)
+}
+
+/// Encrypts with the given application server key pair and salt, both have to be fresh for every message
+fn encrypt_with(payload: &[u8], keys: &PushKeys, as_secret: &SecretKey, salt: [u8; 16]) -> Result<Vec<u8>> {
+    // Header, authentication tag and the padding delimiter have to fit into the single record
+    if payload.len() > RECORD_SIZE as usize - 86 - 16 - 1 {
+        return Err(Error::from("Push payload too large"));
+    }
+
+    let ua_key = BASE64_URL.decode(&keys.p256dh).ok()
+        .and_then(|key| PublicKey::from_sec1_bytes(&key).ok())
+        .ok_or_else(|| Error::from("Invalid p256dh key"))?;
+    let ua_public = ua_key.to_encoded_point(false);
+    let auth_secret = BASE64_URL.decode(&keys.auth).map_err(|_| Error::from("Invalid auth secret"))?;
+
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared_secret = p256::ecdh::diffie_hellman(as_secret.to_nonzero_scalar(), ua_key.as_affine());
+
+    // Mix the shared secret with the subscription's auth secret
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public.as_bytes());
+    key_info.extend_from_slice(as_public.as_bytes());
+    let ikm: [u8; 32] = hkdf_expand(&Hkdf::<Sha256>::new(Some(&auth_secret), shared_secret.raw_secret_bytes()), &key_info)?;
+
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+    let content_key: [u8; 16] = hkdf_expand(&hkdf, b"Content-Encoding: aes128gcm\0")?;
+    let nonce: [u8; 12] = hkdf_expand(&hkdf, b"Content-Encoding: nonce\0")?;
+
+    // 0x02 marks the last (and only) record, no further padding
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+
+    let cipher = Aes128Gcm::new_from_slice(&content_key).map_err(|e| Error::from(format!("Error creating cipher: {}", e)))?;
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|e| Error::from(format!("Error encrypting push payload: {}", e)))?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+/// Encrypts a JSON payload and sends it to the subscription's push service
+pub async fn send_notification(env: &Env, target: &PushTarget, payload: &serde_json::Value) -> Result<Delivery> {
+    let endpoint = Url::parse(&target.endpoint)?;
+    let body = encrypt(serde_json::to_string(payload)?.as_bytes(), &target.keys)?;
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/octet-stream")?;
+    headers.set("Content-Encoding", "aes128gcm")?;
+    headers.set("TTL", &PUSH_TTL_SECONDS.to_string())?;
+    headers.set("Authorization", &vapid_authorization(env, &endpoint)?)?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(js_sys::Uint8Array::from(body.as_slice()).into()));
+
+    let request = Request::new_with_init(endpoint.as_str(), &init)?;
+    let resp = Fetch::Request(request).send().await?;
+
+    match resp.status_code() {
+        200..=299 => Ok(Delivery::Sent),
+        404 | 410 => Ok(Delivery::Expired),
+        status => Err(Error::from(format!("Push request failed with status: {}", status))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::signature::Verifier;
+    use p256::ecdsa::VerifyingKey;
+
+    use super::*;
+
+    fn decode(value: &str) -> Vec<u8> {
+        BASE64_URL.decode(value).unwrap()
+    }
+
+    /// Example of RFC 8291 Appendix A
+    #[test]
+    fn encrypts_the_rfc_8291_example() {
+        let keys = PushKeys {
+            p256dh: "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4".to_string(),
+            auth: "BTBZMqHH6r4Tts7J_aSIgg".to_string(),
+        };
+        let as_secret = SecretKey::from_slice(&decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let salt: [u8; 16] = decode("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+
+        let body = encrypt_with(b"When I grow up, I want to be a watermelon", &keys, &as_secret, salt).unwrap();
+
+        assert_eq!(
+            BASE64_URL.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_payloads() {
+        let keys = PushKeys {
+            p256dh: "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4".to_string(),
+            auth: "BTBZMqHH6r4Tts7J_aSIgg".to_string(),
+        };
+        assert!(encrypt(&[b'a'; RECORD_SIZE as usize], &keys).is_err());
+    }
+
+    #[test]
+    fn signs_a_vapid_token() {
+        let key = SigningKey::from_slice(&decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let endpoint = Url::parse("https://push.example.net/push/JzLQ3raZJfFBR0aqvOMsLrt54w4rJUsV").unwrap();
+
+        let header = vapid_header(&key, &endpoint, 1_453_523_768, Some("mailto:push@example.com")).unwrap();
+
+        let (token, public_key) = header.strip_prefix("vapid t=").unwrap().split_once(", k=").unwrap();
+        assert_eq!(public_key, "BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8");
+
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3);
+
+        let jwt_header: serde_json::Value = serde_json::from_slice(&decode(parts[0])).unwrap();
+        assert_eq!(jwt_header, json!({ "typ": "JWT", "alg": "ES256" }));
+
+        let claims: serde_json::Value = serde_json::from_slice(&decode(parts[1])).unwrap();
+        assert_eq!(claims, json!({
+            "aud": "https://push.example.net",
+            "exp": 1_453_523_768,
+            "sub": "mailto:push@example.com"
+        }));
+
+        // ES256 signatures are the raw 64 byte r || s over "header.claims"
+        let signature = Signature::from_slice(&decode(parts[2])).unwrap();
+        let verifying_key = VerifyingKey::from_sec1_bytes(&decode(public_key)).unwrap();
+        assert!(verifying_key.verify(format!("{}.{}", parts[0], parts[1]).as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn leaves_out_an_unset_subject() {
+        let key = SigningKey::from_slice(&decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let endpoint = Url::parse("https://push.example.net/push/abc").unwrap();
+
+        let header = vapid_header(&key, &endpoint, 1_453_523_768, None).unwrap();
+        let claims = header.strip_prefix("vapid t=").unwrap().split('.').nth(1).unwrap();
+
+        let claims: serde_json::Value = serde_json::from_slice(&decode(claims)).unwrap();
+        assert_eq!(claims, json!({ "aud": "https://push.example.net", "exp": 1_453_523_768 }));
+    }
+
+    fn target(endpoint: &str) -> PushTarget {
+        PushTarget {
+            endpoint: endpoint.to_string(),
+            keys: PushKeys {
+                p256dh: "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4".to_string(),
+                auth: "BTBZMqHH6r4Tts7J_aSIgg".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn accepts_endpoints_of_known_push_services() {
+        assert!(target("https://fcm.googleapis.com/fcm/send/abc:def").validate().is_ok());
+        assert!(target("https://updates.push.services.mozilla.com/wpush/v2/abc").validate().is_ok());
+        assert!(target("https://wns2-db5p.notify.windows.com/w/?token=abc").validate().is_ok());
+        assert!(target("https://web.push.apple.com/abc").validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_other_endpoints() {
+        for endpoint in [
+            "http://fcm.googleapis.com/fcm/send/abc",
+            "https://fcm.googleapis.com:8443/fcm/send/abc",
+            "https://user@fcm.googleapis.com/fcm/send/abc",
+            "https://fcm.googleapis.com.example.com/abc",
+            "https://evilfcm.googleapis.com/abc",
+            "https://push.example.net/abc",
+            "https://localhost/abc",
+            "https://127.0.0.1/abc",
+            "https://10.0.0.1/abc",
+            "https://[::1]/abc",
+            "not a url",
+        ] {
+            assert!(target(endpoint).validate().is_err(), "{}", endpoint);
+        }
+    }
+}
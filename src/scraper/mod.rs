@@ -1,19 +1,33 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use worker::*;
 
+use crate::i18n::{self, Language};
+use crate::opening_hours::{OpeningHours, TimeRange};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct WebsiteConfig {
+    /// Stable numeric id, referenced by the `gym_id` columns
+    pub id: i64,
+    /// Short stable identifier used in URLs, e.g. "muc-ost"
+    pub slug: String,
     pub url: String,
     pub name: String,
+    pub opening_hours: OpeningHours,
+    /// Upper bounds of the Very low / Low / Moderate / High description bands
+    pub crowd_level_bands: [f64; 4],
+    /// Location of the gym, used to look up the weather
+    pub latitude: f64,
+    pub longitude: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct ScrapedDetails {
     pub raw_percentage: f64,
     pub scrape_time_ms: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct ScrapedWebsiteData {
     pub timestamp: String,
     pub url: String,
@@ -31,25 +45,57 @@ pub struct ScrapedData {
     pub data: Vec<ScrapedWebsiteData>,
 }
 
+/// Regular opening hours shared by the Boulderwelt gyms in Munich
+fn boulderwelt_opening_hours() -> OpeningHours {
+    OpeningHours::weekdays_and_weekend(
+        TimeRange::new("07:00", "23:00"),
+        TimeRange::new("08:00", "23:00"),
+    )
+}
+
 /// Returns a list of configured websites to scrape
 pub fn get_configured_websites() -> Vec<WebsiteConfig> {
     vec![
         WebsiteConfig {
+            id: 1,
+            slug: "muc-ost".to_string(),
             url: "https://www.boulderwelt-muenchen-ost.de/".to_string(),
             name: "Boulderwelt München Ost".to_string(),
+            opening_hours: boulderwelt_opening_hours(),
+            crowd_level_bands: i18n::DEFAULT_CROWD_LEVEL_BANDS,
+            latitude: 48.1219,
+            longitude: 11.7006,
         },
         WebsiteConfig {
+            id: 2,
+            slug: "muc-west".to_string(),
             url: "https://www.boulderwelt-muenchen-west.de/".to_string(),
             name: "Boulderwelt München West".to_string(),
+            opening_hours: boulderwelt_opening_hours(),
+            crowd_level_bands: i18n::DEFAULT_CROWD_LEVEL_BANDS,
+            latitude: 48.1421,
+            longitude: 11.4983,
         },
         WebsiteConfig {
+            id: 3,
+            slug: "muc-sued".to_string(),
             url: "https://www.boulderwelt-muenchen-sued.de/".to_string(),
             name: "Boulderwelt München Süd".to_string(),
+            opening_hours: boulderwelt_opening_hours(),
+            crowd_level_bands: i18n::DEFAULT_CROWD_LEVEL_BANDS,
+            latitude: 48.0914,
+            longitude: 11.5227,
         },
-        // Add more websites here as needed
+        // Add more websites here as needed, ids and slugs must never change
     ]
 }
 
+/// Looks up a configured website by its slug or its URL
+pub fn find_website(gym: &str) -> Option<WebsiteConfig> {
+    get_configured_websites().into_iter()
+        .find(|site| site.slug == gym || site.url == gym)
+}
+
 /// Fetches crowd level data directly from the AJAX endpoint
 pub async fn fetch_crowd_data(website: &WebsiteConfig) -> Result<ScrapedWebsiteData> {
     let site_url = &website.url;
@@ -93,18 +139,8 @@ pub async fn fetch_crowd_data(website: &WebsiteConfig) -> Result<ScrapedWebsiteD
     // Convert the level to a string percentage
     let percentage = format!("{}", level);
     
-    // Calculate a crowd level description based on the percentage
-    let crowd_level_description = if level < 20.0 {
-        "Very low".to_string()
-    } else if level < 40.0 {
-        "Low".to_string()
-    } else if level < 60.0 {
-        "Moderate".to_string()
-    } else if level < 80.0 {
-        "High".to_string()
-    } else {
-        "Very high".to_string()
-    };
+    // Describe the crowd level using the gym's own bands, handlers may re-localise it
+    let crowd_level_description = i18n::describe_level(level, &website.crowd_level_bands, Language::En).to_string();
 
     // Get the scrape time, handling the Result<Option<String>>
     let scrape_time = match resp.headers().get("cf-request-time") {
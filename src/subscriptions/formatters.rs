@@ -0,0 +1,430 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use worker::Url;
+
+use crate::analysis;
+use crate::i18n::{self, Language};
+
+use super::Direction;
+
+/// Change in percentage points from which the level counts as rising or falling
+pub const TREND_THRESHOLD: f64 = 3.0;
+
+/// Payload format of a webhook subscription
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    /// Plain JSON with the raw values
+    #[default]
+    Json,
+    /// Discord incoming webhook with an embed
+    Discord,
+    /// Slack incoming webhook with Block Kit blocks
+    Slack,
+}
+
+impl MessageFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageFormat::Json => "json",
+            MessageFormat::Discord => "discord",
+            MessageFormat::Slack => "slack",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(MessageFormat::Json),
+            "discord" => Some(MessageFormat::Discord),
+            "slack" => Some(MessageFormat::Slack),
+            _ => None,
+        }
+    }
+}
+
+/// Direction the crowd level moved in since the previous readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trend {
+    Rising,
+    Falling,
+    Steady,
+}
+
+impl Trend {
+    /// Compares the current level with an earlier one, Steady if there is none
+    pub fn between(previous: Option<f64>, current: f64) -> Self {
+        match previous {
+            Some(previous) if current - previous >= TREND_THRESHOLD => Trend::Rising,
+            Some(previous) if previous - current >= TREND_THRESHOLD => Trend::Falling,
+            _ => Trend::Steady,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trend::Rising => "rising",
+            Trend::Falling => "falling",
+            Trend::Steady => "steady",
+        }
+    }
+
+    pub fn arrow(&self) -> &'static str {
+        match self {
+            Trend::Rising => "↑",
+            Trend::Falling => "↓",
+            Trend::Steady => "→",
+        }
+    }
+}
+
+/// Threshold that was crossed, for alerts
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub subscription_id: i64,
+    pub direction: Direction,
+    pub threshold: f64,
+}
+
+/// Everything a message about a gym's current crowd level shows
+#[derive(Debug, Clone)]
+pub struct CrowdUpdate {
+    pub website_name: String,
+    pub website_url: String,
+    pub percentage: f64,
+    pub description: String,
+    pub trend: Trend,
+    /// Absolute link to the gym's graph, None if the public URL of the worker is unknown
+    pub graph_url: Option<String>,
+    /// UTC time of the reading as ISO 8601
+    pub timestamp: String,
+    pub alert: Option<Alert>,
+}
+
+/// Absolute link to a gym's graph of the current day below the worker's public URL
+pub fn graph_url(base: &str, slug: &str) -> Option<String> {
+    let mut url = Url::parse(base).ok()?.join("/graph").ok()?;
+    url.query_pairs_mut()
+        .append_pair("url", slug)
+        .append_pair("days", "1");
+    Some(url.to_string())
+}
+
+/// Renders the update in the given payload format
+pub fn render(format: MessageFormat, update: &CrowdUpdate, language: Language) -> serde_json::Value {
+    match format {
+        MessageFormat::Json => json_payload(update),
+        MessageFormat::Discord => discord_payload(update, language),
+        MessageFormat::Slack => slack_payload(update, language),
+    }
+}
+
+/// One-line summary such as "Crowd level dropped below 30%", or the description for plain updates
+fn headline(update: &CrowdUpdate, language: Language) -> String {
+    match &update.alert {
+        Some(alert) => i18n::text(language, "message.alert")
+            .replacen("{}", i18n::text(language, alert.direction.text_key()), 1)
+            .replacen("{}", &alert.threshold.to_string(), 1),
+        None => update.description.clone(),
+    }
+}
+
+fn json_payload(update: &CrowdUpdate) -> serde_json::Value {
+    let mut payload = json!({
+        "website_url": update.website_url,
+        "website_name": update.website_name,
+        "percentage": update.percentage,
+        "description": update.description,
+        "trend": update.trend.as_str(),
+        "graph_url": update.graph_url,
+        "timestamp": update.timestamp
+    });
+
+    if let Some(alert) = &update.alert {
+        payload["subscription_id"] = json!(alert.subscription_id);
+        payload["threshold"] = json!(alert.threshold);
+        payload["direction"] = json!(alert.direction.as_str());
+    }
+
+    payload
+}
+
+/// Notification shown by the service worker of the graph page
+pub fn push_notification(update: &CrowdUpdate, language: Language) -> serde_json::Value {
+    json!({
+        "title": update.website_name,
+        "body": format!("{} - {}% {}", headline(update, language), update.percentage, update.trend.arrow()),
+        "website_url": update.website_url,
+        "url": update.graph_url,
+        "timestamp": update.timestamp
+    })
+}
+
+/// Embed colour (0xRRGGBB) from green for quiet to red for busy
+fn level_color(percentage: f64) -> u32 {
+    if percentage < analysis::QUIET_THRESHOLD {
+        0x4CAF50
+    } else if percentage < analysis::DEFAULT_BUSY_THRESHOLD {
+        0xFF9800
+    } else {
+        0xF44336
+    }
+}
+
+fn discord_payload(update: &CrowdUpdate, language: Language) -> serde_json::Value {
+    let mut embed = json!({
+        "title": update.website_name,
+        "description": headline(update, language),
+        "color": level_color(update.percentage),
+        "fields": [
+            {
+                "name": i18n::text(language, "message.current_level"),
+                "value": format!("{}% {}", update.percentage, update.trend.arrow()),
+                "inline": true
+            },
+            {
+                "name": i18n::text(language, "message.description"),
+                "value": update.description,
+                "inline": true
+            }
+        ],
+        "timestamp": update.timestamp
+    });
+
+    if let Some(graph_url) = &update.graph_url {
+        embed["url"] = json!(graph_url);
+    }
+
+    json!({
+        "embeds": [embed]
+    })
+}
+
+fn slack_payload(update: &CrowdUpdate, language: Language) -> serde_json::Value {
+    let mut blocks = vec![
+        json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": update.website_name }
+        }),
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": headline(update, language) },
+            "fields": [
+                {
+                    "type": "mrkdwn",
+                    "text": format!("*{}*\n{}% {}", i18n::text(language, "message.current_level"), update.percentage, update.trend.arrow())
+                },
+                {
+                    "type": "mrkdwn",
+                    "text": format!("*{}*\n{}", i18n::text(language, "message.description"), update.description)
+                }
+            ]
+        }),
+    ];
+
+    if let Some(graph_url) = &update.graph_url {
+        blocks.push(json!({
+            "type": "actions",
+            "elements": [{
+                "type": "button",
+                "text": { "type": "plain_text", "text": i18n::text(language, "compare.show_graph") },
+                "url": graph_url
+            }]
+        }));
+    }
+
+    json!({
+        // Shown in notifications and by clients without Block Kit support
+        "text": format!("{}: {}% ({})", update.website_name, update.percentage, update.description),
+        "blocks": blocks
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPH_URL: &str = "https://crowd.example.com/graph?url=muc-ost&days=1";
+
+    /// Alert for a falling level that dropped below 30%
+    fn alert_update(language: Language) -> CrowdUpdate {
+        CrowdUpdate {
+            website_name: "Boulderwelt München Ost".to_string(),
+            website_url: "https://www.boulderwelt-muenchen-ost.de/".to_string(),
+            percentage: 25.5,
+            description: i18n::describe_level(25.5, &i18n::DEFAULT_CROWD_LEVEL_BANDS, language).to_string(),
+            trend: Trend::Falling,
+            graph_url: graph_url("https://crowd.example.com/", "muc-ost"),
+            timestamp: "2025-03-28T17:40:00Z".to_string(),
+            alert: Some(Alert {
+                subscription_id: 7,
+                direction: Direction::Below,
+                threshold: 30.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn graph_url_links_the_slug() {
+        assert_eq!(graph_url("https://crowd.example.com/", "muc-ost").as_deref(), Some(GRAPH_URL));
+        assert_eq!(graph_url("https://crowd.example.com/prefix/", "muc-ost").as_deref(), Some(GRAPH_URL));
+        assert_eq!(graph_url("", "muc-ost"), None);
+    }
+
+    #[test]
+    fn json_payload_is_not_localized() {
+        let expected = json!({
+            "website_url": "https://www.boulderwelt-muenchen-ost.de/",
+            "website_name": "Boulderwelt München Ost",
+            "percentage": 25.5,
+            "description": "Low",
+            "trend": "falling",
+            "graph_url": GRAPH_URL,
+            "timestamp": "2025-03-28T17:40:00Z",
+            "subscription_id": 7,
+            "threshold": 30.0,
+            "direction": "below"
+        });
+
+        assert_eq!(render(MessageFormat::Json, &alert_update(Language::En), Language::En), expected);
+
+        let mut expected = expected;
+        expected["description"] = json!("Niedrig");
+        assert_eq!(render(MessageFormat::Json, &alert_update(Language::De), Language::De), expected);
+    }
+
+    #[test]
+    fn discord_payload_in_english() {
+        assert_eq!(render(MessageFormat::Discord, &alert_update(Language::En), Language::En), json!({
+            "embeds": [{
+                "title": "Boulderwelt München Ost",
+                "description": "Crowd level is below 30%",
+                "color": 0x4CAF50,
+                "fields": [
+                    { "name": "Current level", "value": "25.5% ↓", "inline": true },
+                    { "name": "Description", "value": "Low", "inline": true }
+                ],
+                "timestamp": "2025-03-28T17:40:00Z",
+                "url": GRAPH_URL
+            }]
+        }));
+    }
+
+    #[test]
+    fn discord_payload_in_german() {
+        assert_eq!(render(MessageFormat::Discord, &alert_update(Language::De), Language::De), json!({
+            "embeds": [{
+                "title": "Boulderwelt München Ost",
+                "description": "Auslastung liegt unter 30%",
+                "color": 0x4CAF50,
+                "fields": [
+                    { "name": "Aktuelle Auslastung", "value": "25.5% ↓", "inline": true },
+                    { "name": "Einschätzung", "value": "Niedrig", "inline": true }
+                ],
+                "timestamp": "2025-03-28T17:40:00Z",
+                "url": GRAPH_URL
+            }]
+        }));
+    }
+
+    #[test]
+    fn discord_payload_of_a_busy_update_without_graph_link() {
+        let update = CrowdUpdate {
+            percentage: 85.0,
+            description: "Very high".to_string(),
+            trend: Trend::Rising,
+            graph_url: None,
+            alert: None,
+            ..alert_update(Language::En)
+        };
+
+        assert_eq!(render(MessageFormat::Discord, &update, Language::En), json!({
+            "embeds": [{
+                "title": "Boulderwelt München Ost",
+                "description": "Very high",
+                "color": 0xF44336,
+                "fields": [
+                    { "name": "Current level", "value": "85% ↑", "inline": true },
+                    { "name": "Description", "value": "Very high", "inline": true }
+                ],
+                "timestamp": "2025-03-28T17:40:00Z"
+            }]
+        }));
+    }
+
+    #[test]
+    fn slack_payload_in_english() {
+        assert_eq!(render(MessageFormat::Slack, &alert_update(Language::En), Language::En), json!({
+            "text": "Boulderwelt München Ost: 25.5% (Low)",
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": "Boulderwelt München Ost" }
+                },
+                {
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": "Crowd level is below 30%" },
+                    "fields": [
+                        { "type": "mrkdwn", "text": "*Current level*\n25.5% ↓" },
+                        { "type": "mrkdwn", "text": "*Description*\nLow" }
+                    ]
+                },
+                {
+                    "type": "actions",
+                    "elements": [{
+                        "type": "button",
+                        "text": { "type": "plain_text", "text": "Show graph" },
+                        "url": GRAPH_URL
+                    }]
+                }
+            ]
+        }));
+    }
+
+    #[test]
+    fn slack_payload_in_german() {
+        assert_eq!(render(MessageFormat::Slack, &alert_update(Language::De), Language::De), json!({
+            "text": "Boulderwelt München Ost: 25.5% (Niedrig)",
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": "Boulderwelt München Ost" }
+                },
+                {
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": "Auslastung liegt unter 30%" },
+                    "fields": [
+                        { "type": "mrkdwn", "text": "*Aktuelle Auslastung*\n25.5% ↓" },
+                        { "type": "mrkdwn", "text": "*Einschätzung*\nNiedrig" }
+                    ]
+                },
+                {
+                    "type": "actions",
+                    "elements": [{
+                        "type": "button",
+                        "text": { "type": "plain_text", "text": "Verlauf anzeigen" },
+                        "url": GRAPH_URL
+                    }]
+                }
+            ]
+        }));
+    }
+
+    #[test]
+    fn push_notification_in_both_languages() {
+        assert_eq!(push_notification(&alert_update(Language::En), Language::En), json!({
+            "title": "Boulderwelt München Ost",
+            "body": "Crowd level is below 30% - 25.5% ↓",
+            "website_url": "https://www.boulderwelt-muenchen-ost.de/",
+            "url": GRAPH_URL,
+            "timestamp": "2025-03-28T17:40:00Z"
+        }));
+        assert_eq!(push_notification(&alert_update(Language::De), Language::De), json!({
+            "title": "Boulderwelt München Ost",
+            "body": "Auslastung liegt unter 30% - 25.5% ↓",
+            "website_url": "https://www.boulderwelt-muenchen-ost.de/",
+            "url": GRAPH_URL,
+            "timestamp": "2025-03-28T17:40:00Z"
+        }));
+    }
+}
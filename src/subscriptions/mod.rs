@@ -0,0 +1,238 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::i18n::Language;
+use crate::push::{PushKeys, PushTarget};
+use crate::utils;
+
+pub mod formatters;
+
+use formatters::MessageFormat;
+
+/// Cooldown used when a subscription doesn't specify one
+pub const DEFAULT_COOLDOWN_MINUTES: i64 = 60;
+
+/// Whether a subscription fires when the crowd level drops below or rises above its threshold
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Below,
+    Above,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Below => "below",
+            Direction::Above => "above",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "below" => Some(Direction::Below),
+            "above" => Some(Direction::Above),
+            _ => None,
+        }
+    }
+
+    /// Key of the localised direction in chat messages
+    pub fn text_key(&self) -> &'static str {
+        match self {
+            Direction::Below => "direction.below",
+            Direction::Above => "direction.above",
+        }
+    }
+}
+
+/// Local time window in which a subscription is active, e.g. weekday evenings
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct ActiveWindow {
+    /// Weekdays the window applies to, numbered like `strftime('%w')` (0 = Sunday)
+    pub days: Vec<i64>,
+    /// Start and end in local time ("HH:MM"), the end is exclusive
+    pub start: String,
+    pub end: String,
+}
+
+impl ActiveWindow {
+    fn times(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start = NaiveTime::parse_from_str(&self.start, "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(&self.end, "%H:%M").ok()?;
+        Some((start, end))
+    }
+
+    /// Checks whether the window contains the given local time
+    pub fn contains(&self, local: NaiveDateTime) -> bool {
+        let Some((start, end)) = self.times() else {
+            return false;
+        };
+
+        self.days.contains(&utils::weekday_index(local.weekday()))
+            && local.time() >= start
+            && local.time() < end
+    }
+}
+
+/// A threshold alert as sent to the admin API or created through the Telegram bot or the graph page
+///
+/// Alerts are delivered to exactly one of `webhook_url`, the Telegram chat `telegram_chat_id`
+/// or the browser push subscription `push`.
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+pub struct Subscription {
+    /// Gym by slug or website URL, stored as the URL
+    pub website_url: String,
+    pub threshold: f64,
+    pub direction: Direction,
+    /// Windows in which the subscription is active, empty means always
+    #[serde(default)]
+    pub windows: Vec<ActiveWindow>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub telegram_chat_id: Option<i64>,
+    #[serde(default)]
+    pub push: Option<PushTarget>,
+    /// Payload format for webhooks, ignored for Telegram
+    #[serde(default)]
+    pub format: MessageFormat,
+    /// Minimum time between two notifications
+    #[serde(default = "default_cooldown_minutes")]
+    pub cooldown_minutes: i64,
+    /// Language of chat messages
+    #[serde(default)]
+    pub language: Language,
+}
+
+fn default_cooldown_minutes() -> i64 {
+    DEFAULT_COOLDOWN_MINUTES
+}
+
+impl Subscription {
+    /// Checks the subscription and returns a message describing the first problem found
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(0.0..=100.0).contains(&self.threshold) {
+            return Err("Threshold must be between 0 and 100".to_string());
+        }
+
+        if self.cooldown_minutes < 0 {
+            return Err("Cooldown must not be negative".to_string());
+        }
+
+        match (&self.webhook_url, self.telegram_chat_id, &self.push) {
+            (Some(webhook_url), None, None) => match Url::parse(webhook_url) {
+                Ok(url) if url.scheme() == "https" || url.scheme() == "http" => {},
+                _ => return Err("Invalid webhook URL".to_string()),
+            },
+            (None, Some(_), None) => {},
+            (None, None, Some(push)) => push.validate()?,
+            _ => return Err("Exactly one of webhook_url, telegram_chat_id and push is required".to_string()),
+        }
+
+        for window in &self.windows {
+            if window.times().is_none() || window.days.iter().any(|day| !(0..7).contains(day)) {
+                return Err(format!("Invalid active window {}-{}", window.start, window.end));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a reading taken at the given local time satisfies the subscription
+    pub fn matches(&self, percentage: f64, local: NaiveDateTime) -> bool {
+        let in_window = self.windows.is_empty() || self.windows.iter().any(|window| window.contains(local));
+        let crossed = match self.direction {
+            Direction::Below => percentage < self.threshold,
+            Direction::Above => percentage > self.threshold,
+        };
+
+        in_window && crossed
+    }
+}
+
+/// A subscription as stored in D1, with the state used to avoid repeated notifications
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubscriptionRecord {
+    pub id: i64,
+    pub website_url: String,
+    pub threshold: f64,
+    pub direction: String,
+    /// JSON encoded list of `ActiveWindow`
+    pub windows: String,
+    pub webhook_url: Option<String>,
+    pub telegram_chat_id: Option<i64>,
+    pub push_endpoint: Option<String>,
+    pub push_p256dh: Option<String>,
+    pub push_auth: Option<String>,
+    pub format: String,
+    pub cooldown_minutes: i64,
+    pub language: String,
+    /// 1 if the previous reading already satisfied the subscription
+    pub matching: i64,
+    pub last_notified_at: Option<String>,
+}
+
+impl SubscriptionRecord {
+    /// Returns the subscription settings, or None if the stored row is invalid
+    pub fn subscription(&self) -> Option<Subscription> {
+        Some(Subscription {
+            website_url: self.website_url.clone(),
+            threshold: self.threshold,
+            direction: Direction::parse(&self.direction)?,
+            windows: serde_json::from_str(&self.windows).ok()?,
+            webhook_url: self.webhook_url.clone(),
+            telegram_chat_id: self.telegram_chat_id,
+            push: self.push_target(),
+            format: MessageFormat::parse(&self.format)?,
+            cooldown_minutes: self.cooldown_minutes,
+            language: Language::parse(&self.language).unwrap_or_default(),
+        })
+    }
+
+    fn push_target(&self) -> Option<PushTarget> {
+        Some(PushTarget {
+            endpoint: self.push_endpoint.clone()?,
+            keys: PushKeys {
+                p256dh: self.push_p256dh.clone()?,
+                auth: self.push_auth.clone()?,
+            },
+        })
+    }
+
+    /// Decides whether a matching reading should be notified
+    ///
+    /// Only the first matching reading after a non-matching one is sent, and never
+    /// within the cooldown of the previous notification.
+    pub fn should_notify(&self, now: NaiveDateTime) -> bool {
+        if self.matching == 1 {
+            return false;
+        }
+
+        match self.last_notified_at.as_deref().and_then(utils::parse_db_timestamp) {
+            Some(last) => (now - last).num_minutes() >= self.cooldown_minutes,
+            None => true,
+        }
+    }
+}
+
+/// Posts a JSON payload to a webhook
+pub async fn send_webhook(webhook_url: &str, payload: &serde_json::Value) -> Result<()> {
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(serde_json::to_string(payload)?.into()));
+
+    let request = Request::new_with_init(webhook_url, &init)?;
+    let resp = Fetch::Request(request).send().await?;
+
+    if !(200..300).contains(&resp.status_code()) {
+        return Err(Error::from(format!("Webhook request failed with status: {}", resp.status_code())));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use serde::Deserialize;
+use serde_json::json;
+use worker::*;
+
+use crate::utils;
+
+/// Bot API used when `TELEGRAM_API_URL` is not configured
+pub const DEFAULT_API_URL: &str = "https://api.telegram.org";
+
+/// The parts of a Telegram update the bot looks at
+#[derive(Deserialize, Debug)]
+pub struct Update {
+    pub message: Option<Message>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Message {
+    pub chat: Chat,
+    pub from: Option<User>,
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Chat {
+    pub id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    pub language_code: Option<String>,
+}
+
+/// Returns the Bot API base URL, configurable so the bot can run against a local stub
+pub fn api_url(env: &Env) -> String {
+    env.var("TELEGRAM_API_URL")
+        .map(|value| value.to_string())
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Checks the secret token Telegram sends with every webhook call
+///
+/// Without a configured `TELEGRAM_WEBHOOK_SECRET` every request is rejected.
+pub fn is_authorized(req: &Request, env: &Env) -> bool {
+    let expected = match env.secret("TELEGRAM_WEBHOOK_SECRET") {
+        Ok(secret) => secret.to_string(),
+        Err(_) => {
+            console_error!("TELEGRAM_WEBHOOK_SECRET secret is not configured, rejecting Telegram update");
+            return false;
+        }
+    };
+
+    match req.headers().get("X-Telegram-Bot-Api-Secret-Token") {
+        Ok(Some(token)) => utils::secrets_match(&token, &expected),
+        _ => false,
+    }
+}
+
+/// Splits a message like "/subscribe@MyBot Ost below 30" into the command and its arguments
+pub fn parse_command(text: &str) -> Option<(String, Vec<String>)> {
+    let mut parts = text.split_whitespace();
+    let command = parts.next()?.strip_prefix('/')?;
+    let command = command.split('@').next().unwrap_or(command).to_lowercase();
+
+    Some((command, parts.map(|part| part.to_string()).collect()))
+}
+
+/// Sends a text message to a chat
+pub async fn send_message(env: &Env, chat_id: i64, text: &str) -> Result<()> {
+    let token = env.secret("TELEGRAM_BOT_TOKEN")?.to_string();
+    let url = format!("{}/bot{}/sendMessage", api_url(env), token);
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+
+    let body = json!({
+        "chat_id": chat_id,
+        "text": text
+    });
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(serde_json::to_string(&body)?.into()));
+
+    let request = Request::new_with_init(&url, &init)?;
+    let resp = Fetch::Request(request).send().await?;
+
+    if resp.status_code() != 200 {
+        return Err(Error::from(format!("Telegram sendMessage failed with status: {}", resp.status_code())));
+    }
+
+    Ok(())
+}
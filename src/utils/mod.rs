@@ -1,5 +1,10 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use sha2::{Digest, Sha256};
 use worker::*;
 
+/// Format used by SQLite's CURRENT_TIMESTAMP, which is how `created_at` is stored
+pub const DB_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
 /// Logs information about an incoming request
 pub fn log_request(req: &Request) {
     console_log!(
@@ -10,3 +15,99 @@ pub fn log_request(req: &Request) {
         req.cf().and_then(|cf| cf.region()).unwrap_or_else(|| "unknown region".into())
     );
 }
+
+/// Returns the current time in UTC
+pub fn now_utc() -> NaiveDateTime {
+    DateTime::from_timestamp_millis(Date::now().as_millis() as i64)
+        .map(|dt| dt.naive_utc())
+        .unwrap_or_default()
+}
+
+/// Parses a timestamp as stored in D1 ("2025-03-29 22:50:09", always UTC)
+pub fn parse_db_timestamp(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, DB_TIMESTAMP_FORMAT).ok()
+}
+
+/// Formats a UTC timestamp the same way SQLite stores `created_at`
+pub fn format_db_timestamp(value: NaiveDateTime) -> String {
+    value.format(DB_TIMESTAMP_FORMAT).to_string()
+}
+
+/// Returns the last Sunday of the given month, used for the EU daylight saving switch
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    let first_of_next = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .unwrap_or_default();
+
+    let last_day = first_of_next - Duration::days(1);
+    let days_back = last_day.weekday().num_days_from_sunday() as i64;
+    last_day - Duration::days(days_back)
+}
+
+/// Returns the Europe/Berlin offset from UTC in hours at the given UTC instant
+pub fn berlin_offset_hours(utc: NaiveDateTime) -> i64 {
+    // Summer time runs from 01:00 UTC on the last Sunday of March
+    // until 01:00 UTC on the last Sunday of October
+    let year = utc.year();
+    let one_am = NaiveTime::from_hms_opt(1, 0, 0).unwrap_or_default();
+    let dst_start = last_sunday(year, 3).and_time(one_am);
+    let dst_end = last_sunday(year, 10).and_time(one_am);
+
+    if utc >= dst_start && utc < dst_end {
+        2
+    } else {
+        1
+    }
+}
+
+/// Converts a UTC timestamp to Munich local time
+pub fn utc_to_local(utc: NaiveDateTime) -> NaiveDateTime {
+    utc + Duration::hours(berlin_offset_hours(utc))
+}
+
+/// Converts a Munich local time to UTC
+pub fn local_to_utc(local: NaiveDateTime) -> NaiveDateTime {
+    // Guess with the standard offset first, then correct if that lands in summer time
+    let guess = local - Duration::hours(1);
+    local - Duration::hours(berlin_offset_hours(guess))
+}
+
+/// Returns the UTC instant at which the given local day starts
+pub fn local_day_start_utc(day: NaiveDate) -> NaiveDateTime {
+    local_to_utc(day.and_time(NaiveTime::MIN))
+}
+
+/// Returns the most recent UTC instant up to `now` that falls on the given weekday (0 = Sunday) and hour
+pub fn latest_utc_slot(now: NaiveDateTime, day_of_week: i64, hour: u32) -> NaiveDateTime {
+    let days_back = (weekday_index(now.weekday()) - day_of_week).rem_euclid(7);
+    let slot = (now.date() - Duration::days(days_back))
+        .and_hms_opt(hour, 0, 0)
+        .unwrap_or(now);
+
+    if slot > now {
+        slot - Duration::days(7)
+    } else {
+        slot
+    }
+}
+
+/// Maps a chrono weekday onto SQLite's `strftime('%w')` numbering (0 = Sunday)
+pub fn weekday_index(weekday: Weekday) -> i64 {
+    weekday.num_days_from_sunday() as i64
+}
+
+/// Compares a secret sent by a client with the expected one in constant time
+///
+/// Both are hashed first, so neither the position of the first difference nor the length of
+/// the expected secret shows in how long the comparison takes.
+pub fn secrets_match(given: &str, expected: &str) -> bool {
+    let given = Sha256::digest(given.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+
+    given.iter()
+        .zip(expected.iter())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Hourly precipitation (mm) from which a reading counts as taken in the rain
+pub const RAIN_THRESHOLD_MM: f64 = 0.1;
+
+/// Current weather at a gym's location
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherObservation {
+    /// Air temperature in °C
+    pub temperature: f64,
+    /// Precipitation in mm over the preceding hour
+    pub precipitation: f64,
+}
+
+/// Returns whether the given precipitation counts as rain
+pub fn is_rainy(precipitation: f64) -> bool {
+    precipitation >= RAIN_THRESHOLD_MM
+}
+
+/// The `current` block of an Open-Meteo style forecast response
+#[derive(Deserialize)]
+struct CurrentWeather {
+    temperature_2m: f64,
+    precipitation: f64,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    current: CurrentWeather,
+}
+
+/// Returns the configured weather API URL, or None when weather ingestion is disabled
+///
+/// Any endpoint answering like Open-Meteo's `/v1/forecast` works, including a local stub.
+pub fn weather_api_url(env: &Env) -> Option<String> {
+    env.var("WEATHER_API_URL")
+        .ok()
+        .map(|value| value.to_string())
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Fetches the current temperature and precipitation for a location
+pub async fn fetch_current_weather(api_url: &str, latitude: f64, longitude: f64) -> Result<WeatherObservation> {
+    let mut url = Url::parse(api_url)?;
+    url.query_pairs_mut()
+        .append_pair("latitude", &latitude.to_string())
+        .append_pair("longitude", &longitude.to_string())
+        .append_pair("current", "temperature_2m,precipitation");
+
+    console_log!("Fetching weather from {}", url);
+    let mut resp = Fetch::Url(url).send().await?;
+
+    if resp.status_code() != 200 {
+        return Err(Error::from(format!("Weather request failed with status: {}", resp.status_code())));
+    }
+
+    let forecast: ForecastResponse = match resp.json().await {
+        Ok(forecast) => forecast,
+        Err(e) => return Err(Error::from(format!("Failed to parse weather response: {}", e))),
+    };
+
+    Ok(WeatherObservation {
+        temperature: forecast.current.temperature_2m,
+        precipitation: forecast.current.precipitation,
+    })
+}
@@ -0,0 +1,25 @@
+"""Minimal stand-in for the Telegram Bot API that prints every sendMessage call.
+
+Run with `python3 stubs/telegram.py` and start the worker with
+`--var TELEGRAM_API_URL:http://localhost:8001`.
+"""
+import json
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+
+class BotApiStub(BaseHTTPRequestHandler):
+    def do_POST(self):
+        length = int(self.headers.get("Content-Length", 0))
+        body = json.loads(self.rfile.read(length) or b"{}")
+        print(f"{self.path}: {json.dumps(body, ensure_ascii=False)}", flush=True)
+
+        response = json.dumps({"ok": True, "result": {}}).encode()
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(response)))
+        self.end_headers()
+        self.wfile.write(response)
+
+
+if __name__ == "__main__":
+    HTTPServer(("localhost", 8001), BotApiStub).serve_forever()